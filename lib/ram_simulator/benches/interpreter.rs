@@ -0,0 +1,132 @@
+//! Compares the `match`-based interpreter on `Op`s with dispatching every step through
+//! boxed `Instruction` trait objects (the way programs used to be stored), and stepping
+//! with step reports with stepping that copies the state (the way `step` used to work).
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use ram_simulator::comparison::Comparison;
+use ram_simulator::error::ExecError;
+use ram_simulator::instruction::*;
+use ram_simulator::state::State;
+use ram_simulator::RegisterMachine;
+
+/// Number of loop iterations of the benchmarked program (8 steps each)
+const ITERATIONS: u128 = 100_000;
+
+/// Number of loop iterations when stepping
+const STEP_ITERATIONS: u128 = 10_000;
+
+/// Number of additional registers that are in use when stepping
+const USED_REGISTERS: usize = 256;
+
+/// Builds the same program as `Op`s and as boxed trait objects
+macro_rules! program {
+    ( $( $instruction:expr ), * ) => {
+        (
+            InstructionVec::<u128> { instructions: vec![ $( Op::from($instruction) ), * ] },
+            vec![ $( Box::new($instruction) as Box<dyn Instruction<u128>> ), * ],
+        )
+    };
+}
+
+/// Counts c(1) down to 0 and adds 3 to c(2) on every iteration
+fn countdown() -> (InstructionVec<u128>, Vec<Box<dyn Instruction<u128>>>) {
+    program![
+        Load::new(1),
+        CSub::new(1u128),
+        Store::new(1),
+        Load::new(2),
+        CAdd::new(3u128),
+        Store::new(2),
+        Load::new(1),
+        CondJmp::new(Comparison::Gt, 0u128, 1),
+        End::new()
+    ]
+}
+
+fn initial_state() -> State<u128> {
+    let mut state = State::initial();
+    state.set_reg(1, ITERATIONS);
+    state.start();
+    state
+}
+
+/// A machine running the countdown, with `USED_REGISTERS` more registers in use
+fn stepping_machine(program: &InstructionVec<u128>) -> RegisterMachine<u128> {
+    let mut state = State::initial();
+    state.set_reg(1, STEP_ITERATIONS);
+    for register in 3..3 + USED_REGISTERS {
+        state.set_reg(register, register as u128);
+    }
+
+    let mut machine = RegisterMachine::new(program.clone());
+    machine.set_state(state);
+    machine
+}
+
+/// The interpreter loop as it was before `Op`: a virtual call per method and step
+fn interpret_boxed(program: &[Box<dyn Instruction<u128>>], state: &mut State<u128>) -> Result<usize, ExecError> {
+    let mut steps = 0;
+    while state.is_running() {
+        let pc = state.get_pc();
+        let instruction = match program.get(pc) {
+            Some(instruction) => instruction,
+            None => return Err(ExecError::PcOutOfRange { pc }),
+        };
+
+        let cost = instruction.log_cost(state);
+        instruction.exec(state).map_err(|cause| ExecError::Fault {
+            pc,
+            instruction: instruction.to_string(),
+            cause,
+            line: None,
+        })?;
+        state.charge(cost);
+        state.inc_steps();
+        steps += 1;
+    }
+
+    Ok(steps)
+}
+
+fn interpreter(c: &mut Criterion) {
+    let (ops, boxed) = countdown();
+    let mut group = c.benchmark_group("countdown");
+
+    group.bench_function("op", |b| b.iter(|| {
+        let mut state = initial_state();
+        black_box(ops.interpret(&mut state, usize::MAX).unwrap())
+    }));
+
+    group.bench_function("boxed", |b| b.iter(|| {
+        let mut state = initial_state();
+        black_box(interpret_boxed(&boxed, &mut state).unwrap())
+    }));
+
+    group.finish();
+}
+
+fn stepping(c: &mut Criterion) {
+    let (ops, _) = countdown();
+    let mut group = c.benchmark_group("step");
+
+    group.bench_function("report", |b| b.iter(|| {
+        let mut machine = stepping_machine(&ops);
+        while machine.has_not_ended() {
+            black_box(machine.step().unwrap());
+        }
+    }));
+
+    group.bench_function("copy", |b| b.iter(|| {
+        let mut machine = stepping_machine(&ops);
+        while machine.has_not_ended() {
+            machine.step().unwrap();
+            black_box(machine.get_state().clone());
+        }
+    }));
+
+    group.finish();
+}
+
+criterion_group!(benches, interpreter, stepping);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+use std::fmt::{Display, Formatter};
+
+use crate::state::State;
+use crate::word::Word;
+
+/// Whether a register gets read or written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A register an instruction reads or writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Access {
+    pub register: usize,
+    pub kind: AccessKind,
+}
+
+impl Access {
+    /// Reading register `register`
+    pub fn read(register: usize) -> Access {
+        Access { register, kind: AccessKind::Read }
+    }
+
+    /// Writing register `register`
+    pub fn write(register: usize) -> Access {
+        Access { register, kind: AccessKind::Write }
+    }
+}
+
+impl Display for AccessKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+        })
+    }
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} c({})", self.kind, self.register)
+    }
+}
+
+/// The register that register `i` points to, if it holds a valid register index
+pub fn pointer<W: Word>(state: &State<W>, index: usize) -> Option<usize> {
+    state.peek_reg(index).to_usize()
+}
+
+/// Reads of an indirect operand `i`: `i` itself and the register it points to
+pub fn indirect<W: Word>(state: &State<W>, index: usize) -> Vec<Access> {
+    let mut accesses = vec![Access::read(index)];
+    // The instruction faults on invalid pointers before it gets to the target
+    if let Some(address) = pointer(state, index) {
+        accesses.push(Access::read(address));
+    }
+
+    accesses
+}
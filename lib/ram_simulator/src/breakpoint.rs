@@ -0,0 +1,342 @@
+use std::fmt::{Display, Formatter};
+
+use crate::access::{Access, AccessKind};
+use crate::comparison::Comparison;
+use crate::state::State;
+use crate::text::source_map::SourceMap;
+use crate::word::Word;
+
+/// Something that stops `RegisterMachine::run_until`
+#[derive(Debug, Clone)]
+pub enum Breakpoint<W: Word> {
+    /// Stops before the (first) instruction on the given line of the source gets executed
+    /// (see `SourceMap::pc`). Never fires on machines without a source map.
+    Line(usize),
+    /// Stops before the given instruction gets executed.
+    /// Instructions are counted like jump targets (the first one is 1).
+    Instruction(usize),
+    /// Stops after an instruction has accessed the register (`None` watches reads and writes)
+    Watch {
+        register: usize,
+        kind: Option<AccessKind>,
+    },
+    /// Stops before an instruction gets executed if the condition holds (also before the first one)
+    Condition(Condition<W>),
+}
+
+/// A breakpoint that has stopped a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakpointHit {
+    /// The id `RegisterMachine::add_breakpoint` has returned for the breakpoint
+    pub id: usize,
+    /// The number of steps the machine had executed when it stopped
+    pub step: usize,
+    /// The breakpoint (as printed by `Display`)
+    pub breakpoint: String,
+}
+
+/// A boolean expression over the registers `c(i)`, the PC `pc` and the step count `steps`,
+/// e.g. `c(3) > 10 && pc = 7`.
+///
+/// Comparisons (`=`, `==`, `!=`, `<`, `<=`, `>`, `>=`) can be combined with `&&`, `||`, `!`
+/// and parentheses. Registers can be addressed indirectly (`c(c(1))`). `pc` is the PC as shown in
+/// the trace, i.e. one less than the line of the instruction it points at.
+#[derive(Debug, Clone)]
+pub struct Condition<W: Word> {
+    source: String,
+    expression: Expression<W>,
+}
+
+#[derive(Debug, Clone)]
+enum Expression<W: Word> {
+    Compare(Operand<W>, Comparison, Operand<W>),
+    Not(Box<Expression<W>>),
+    And(Box<Expression<W>>, Box<Expression<W>>),
+    Or(Box<Expression<W>>, Box<Expression<W>>),
+}
+
+#[derive(Debug, Clone)]
+enum Operand<W: Word> {
+    Register(Box<Operand<W>>),
+    Pc,
+    Steps,
+    Constant(W),
+}
+
+impl<W: Word> Breakpoint<W> {
+    /// Parses a breakpoint:
+    /// - `line N` (or just `N`) for a line breakpoint, `instr N` for an instruction breakpoint
+    /// - `watch c(i)`, `watch read c(i)` or `watch write c(i)` for a watchpoint
+    /// - `if CONDITION` for a conditional breakpoint (see `Condition`)
+    pub fn parse(spec: &str) -> Result<Breakpoint<W>, String> {
+        let spec = spec.trim();
+        let (keyword, rest) = match spec.split_once(char::is_whitespace) {
+            Some((keyword, rest)) => (keyword, rest.trim()),
+            None => (spec, ""),
+        };
+
+        match keyword.to_lowercase().as_str() {
+            "line" => parse_line(rest).map(Breakpoint::Line),
+            "instr" => match rest.parse::<usize>() {
+                Ok(instruction) if instruction > 0 => Ok(Breakpoint::Instruction(instruction)),
+                _ => Err(format!("'{}' is not an instruction (instructions start at 1)", rest)),
+            },
+            "watch" => {
+                let (kind, register) = match rest.split_once(char::is_whitespace) {
+                    Some(("read", register)) => (Some(AccessKind::Read), register),
+                    Some(("write", register)) => (Some(AccessKind::Write), register),
+                    _ => (None, rest),
+                };
+
+                let register = register.trim();
+                let index = register.strip_prefix("c(")
+                    .and_then(|r| r.strip_suffix(')'))
+                    .unwrap_or(register);
+
+                match index.trim().parse::<usize>() {
+                    Ok(register) => Ok(Breakpoint::Watch { register, kind }),
+                    Err(_) => Err(format!("'{}' is not a register", register)),
+                }
+            },
+            "if" => Condition::parse(rest).map(Breakpoint::Condition),
+            _ => parse_line(spec).map(Breakpoint::Line),
+        }
+    }
+
+    /// Checks whether the breakpoint fires on `state` before the instruction its PC points at
+    /// gets executed (line and instruction breakpoints and conditions). Lines are looked up in
+    /// `source_map`.
+    pub fn is_hit_before(&self, state: &State<W>, source_map: Option<&SourceMap>) -> bool {
+        match self {
+            Breakpoint::Line(line) => source_map.and_then(|source_map| source_map.pc(*line)) == Some(state.get_pc()),
+            Breakpoint::Instruction(instruction) => state.get_pc() + 1 == *instruction,
+            Breakpoint::Watch { .. } => false,
+            Breakpoint::Condition(condition) => condition.eval(state),
+        }
+    }
+
+    /// Checks whether the breakpoint fires after an instruction made the given register
+    /// `accesses` (watchpoints)
+    pub fn is_hit_after(&self, accesses: &[Access]) -> bool {
+        match self {
+            Breakpoint::Watch { register, kind } => accesses.iter().any(|access| {
+                access.register == *register && kind.is_none_or(|k| k == access.kind)
+            }),
+            Breakpoint::Line(_) | Breakpoint::Instruction(_) | Breakpoint::Condition(_) => false,
+        }
+    }
+}
+
+fn parse_line(str: &str) -> Result<usize, String> {
+    match str.trim().parse::<usize>() {
+        Ok(line) if line > 0 => Ok(line),
+        _ => Err(format!("'{}' is not a line (lines start at 1)", str.trim())),
+    }
+}
+
+impl<W: Word> Condition<W> {
+    /// Parses a condition like `c(3) > 10 && pc = 7`
+    pub fn parse(source: &str) -> Result<Condition<W>, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0, _word: std::marker::PhantomData };
+
+        let expression = parser.or()?;
+        match parser.peek() {
+            None => Ok(Condition { source: source.trim().to_string(), expression }),
+            Some(token) => Err(format!("Unexpected '{}' in condition", token)),
+        }
+    }
+
+    /// Evaluates the condition on `state`.
+    /// Comparisons with registers that don't exist (e.g. `c(c(1))` with a negative `c(1)`) are false.
+    pub fn eval(&self, state: &State<W>) -> bool {
+        self.expression.eval(state)
+    }
+}
+
+impl<W: Word> Expression<W> {
+    fn eval(&self, state: &State<W>) -> bool {
+        match self {
+            Expression::Compare(a, comparison, b) => match (a.eval(state), b.eval(state)) {
+                (Some(a), Some(b)) => comparison.compare(a, b),
+                _ => false,
+            },
+            Expression::Not(e) => !e.eval(state),
+            Expression::And(a, b) => a.eval(state) && b.eval(state),
+            Expression::Or(a, b) => a.eval(state) || b.eval(state),
+        }
+    }
+}
+
+impl<W: Word> Operand<W> {
+    fn eval(&self, state: &State<W>) -> Option<W> {
+        match self {
+            Operand::Register(index) => Some(state.peek_reg(index.eval(state)?.to_usize()?)),
+            Operand::Pc => Some(W::from_usize(state.get_pc())),
+            Operand::Steps => Some(W::from_usize(state.get_steps())),
+            Operand::Constant(value) => Some(value.clone()),
+        }
+    }
+}
+
+/// Splits a condition into numbers, names and operators
+fn tokenize(source: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '-' {
+            let mut token = String::new();
+            token.push(c);
+            chars.next();
+            while let Some(&c) = chars.peek() {
+                if !c.is_ascii_alphanumeric() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        } else if "()".contains(c) {
+            tokens.push(c.to_string());
+            chars.next();
+        } else if "=!<>&|".contains(c) {
+            chars.next();
+            let token = match (c, chars.peek()) {
+                ('=', Some('=')) | ('!', Some('=')) | ('<', Some('=')) | ('>', Some('='))
+                    | ('&', Some('&')) | ('|', Some('|')) => {
+                    let second = chars.next().unwrap();
+                    format!("{}{}", c, second)
+                },
+                ('&', _) | ('|', _) => return Err(format!("Expected '{}{}' in condition", c, c)),
+                _ => c.to_string(),
+            };
+            tokens.push(token);
+        } else {
+            return Err(format!("Unexpected '{}' in condition", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser for conditions
+struct Parser<W: Word> {
+    tokens: Vec<String>,
+    position: usize,
+    _word: std::marker::PhantomData<W>,
+}
+
+impl<W: Word> Parser<W> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("Expected '{}' in condition, found '{}'", expected, token)),
+            None => Err(format!("Expected '{}' at the end of the condition", expected)),
+        }
+    }
+
+    /// or := and ('||' and)*
+    fn or(&mut self) -> Result<Expression<W>, String> {
+        let mut expression = self.and()?;
+        while self.peek() == Some("||") {
+            self.next();
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    /// and := unary ('&&' unary)*
+    fn and(&mut self) -> Result<Expression<W>, String> {
+        let mut expression = self.unary()?;
+        while self.peek() == Some("&&") {
+            self.next();
+            expression = Expression::And(Box::new(expression), Box::new(self.unary()?));
+        }
+        Ok(expression)
+    }
+
+    /// unary := '!' unary | '(' or ')' | operand comparison operand
+    fn unary(&mut self) -> Result<Expression<W>, String> {
+        match self.peek() {
+            Some("!") => {
+                self.next();
+                Ok(Expression::Not(Box::new(self.unary()?)))
+            },
+            Some("(") => {
+                self.next();
+                let expression = self.or()?;
+                self.expect(")")?;
+                Ok(expression)
+            },
+            _ => {
+                let a = self.operand()?;
+                let comparison = match self.next() {
+                    Some(op) if ["=", "==", "!=", "<", "<=", ">", ">="].contains(&op.as_str()) =>
+                        Comparison::str_to_comp(&op),
+                    Some(token) => return Err(format!("Expected a comparison in condition, found '{}'", token)),
+                    None => return Err("Expected a comparison at the end of the condition".to_string()),
+                };
+                let b = self.operand()?;
+                Ok(Expression::Compare(a, comparison, b))
+            },
+        }
+    }
+
+    /// operand := 'c' '(' operand ')' | 'pc' | 'steps' | number
+    fn operand(&mut self) -> Result<Operand<W>, String> {
+        match self.next() {
+            Some(token) => match token.to_lowercase().as_str() {
+                "c" => {
+                    self.expect("(")?;
+                    let index = self.operand()?;
+                    self.expect(")")?;
+                    Ok(Operand::Register(Box::new(index)))
+                },
+                "pc" => Ok(Operand::Pc),
+                "steps" => Ok(Operand::Steps),
+                _ => match W::parse_word(&token) {
+                    Ok(value) => Ok(Operand::Constant(value)),
+                    Err(err) => Err(format!("'{}' is not a valid operand [{}]", token, err)),
+                },
+            },
+            None => Err("Expected an operand at the end of the condition".to_string()),
+        }
+    }
+}
+
+impl<W: Word> Display for Breakpoint<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Line(line) => write!(f, "line {}", line),
+            Breakpoint::Instruction(instruction) => write!(f, "instr {}", instruction),
+            Breakpoint::Watch { register, kind: None } => write!(f, "watch c({})", register),
+            Breakpoint::Watch { register, kind: Some(kind) } => write!(f, "watch {} c({})", kind, register),
+            Breakpoint::Condition(condition) => write!(f, "if {}", condition),
+        }
+    }
+}
+
+impl<W: Word> Display for Condition<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Display for BreakpointHit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Breakpoint {} ({}) hit at step {}", self.id, self.breakpoint, self.step)
+    }
+}
@@ -1,9 +1,9 @@
-#[derive(Debug)]
-#[allow(dead_code)]
-
 /// Partial order comparisons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(dead_code)]
 pub enum Comparison {
     Eq,
+    Ne,
     Lt,
     Le,
     Gt,
@@ -15,6 +15,7 @@ impl Comparison {
     pub fn compare<T: PartialOrd>(&self, a: T, b: T) -> bool {
         match self {
             Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
             Comparison::Lt => a < b,
             Comparison::Le => a <= b,
             Comparison::Gt => a > b,
@@ -22,9 +23,23 @@ impl Comparison {
         }
     }
 
+    /// How the comparison is written in programs
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
     pub fn str_to_comp(str: &str) -> Comparison {
         match str.trim() {
             "="  => Comparison::Eq,
+            "==" => Comparison::Eq,
+            "!=" => Comparison::Ne,
             "<"  => Comparison::Lt,
             "<=" => Comparison::Le,
             ">"  => Comparison::Gt,
@@ -0,0 +1,118 @@
+use std::fmt::{Display, Formatter};
+
+use crate::state::State;
+use crate::word::Word;
+
+/// Time complexity of a run under the uniform and the logarithmic cost measure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Cost {
+    /// Every instruction costs 1
+    pub uniform: u64,
+    /// Every instruction costs the bit lengths of the numbers it touches
+    pub logarithmic: u64,
+}
+
+/// Selects one of the two cost measures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CostMeasure {
+    #[default]
+    Uniform,
+    Logarithmic,
+}
+
+impl Cost {
+    /// Charges a single instruction that costs `logarithmic` under the logarithmic measure
+    pub fn charge(&mut self, logarithmic: u64) {
+        self.uniform += 1;
+        self.logarithmic += logarithmic;
+    }
+
+    /// Returns the cost under the given measure
+    pub fn get(&self, measure: CostMeasure) -> u64 {
+        match measure {
+            CostMeasure::Uniform => self.uniform,
+            CostMeasure::Logarithmic => self.logarithmic,
+        }
+    }
+
+    /// Returns the cost that has been added since `earlier`
+    pub fn since(&self, earlier: &Cost) -> Cost {
+        Cost {
+            uniform: self.uniform - earlier.uniform,
+            logarithmic: self.logarithmic - earlier.logarithmic,
+        }
+    }
+}
+
+impl CostMeasure {
+    /// Parses the name of a cost measure (as printed by `Display`)
+    pub fn from_name(name: &str) -> Option<CostMeasure> {
+        match name.trim().to_lowercase().as_str() {
+            "uniform" => Some(CostMeasure::Uniform),
+            "logarithmic" | "log" => Some(CostMeasure::Logarithmic),
+            _ => None,
+        }
+    }
+}
+
+impl Display for CostMeasure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CostMeasure::Uniform => "uniform",
+            CostMeasure::Logarithmic => "logarithmic",
+        })
+    }
+}
+
+impl Display for Cost {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "uniform: {}, logarithmic: {}", self.uniform, self.logarithmic)
+    }
+}
+
+/// Space complexity of a run under the uniform and the logarithmic cost measure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Space {
+    /// Number of distinct registers that have been used
+    pub uniform: u64,
+    /// Maximum (over time) of the summed bit lengths of the used registers
+    pub logarithmic: u64,
+}
+
+impl Display for Space {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "uniform: {}, logarithmic: {}", self.uniform, self.logarithmic)
+    }
+}
+
+/// Length of a number under the logarithmic cost measure: its bit length, but at least 1
+pub fn length<W: Word>(value: &W) -> u64 {
+    value.bits().max(1)
+}
+
+/// Length of a register address or constant under the logarithmic cost measure
+pub fn address_length(address: usize) -> u64 {
+    ((usize::BITS - address.leading_zeros()) as u64).max(1)
+}
+
+/// Logarithmic cost of reading the accumulator: `l(c(0))`
+pub fn accumulator<W: Word>(state: &State<W>) -> u64 {
+    length(&state.get_acc())
+}
+
+/// Logarithmic cost of reading register `i`: `l(i) + l(c(i))`
+pub fn direct<W: Word>(state: &State<W>, index: usize) -> u64 {
+    address_length(index) + length(&state.peek_reg(index))
+}
+
+/// Logarithmic cost of reading the register that register `i` points to: `l(i) + l(c(i)) + l(c(c(i)))`
+pub fn indirect<W: Word>(state: &State<W>, index: usize) -> u64 {
+    let pointer = state.peek_reg(index);
+    let target = match pointer.to_usize() {
+        Some(address) => length(&state.peek_reg(address)),
+        // The instruction faults anyway
+        None => 0,
+    };
+
+    address_length(index) + length(&pointer) + target
+}
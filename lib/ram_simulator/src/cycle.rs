@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::state::State;
+use crate::word::Word;
+
+/// Everything that determines how a machine continues: the PC and the register contents.
+/// Registers that hold 0 are left out, so touching a register doesn't change the configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Configuration<W: Word> {
+    pub pc: usize,
+    pub registers: Vec<(usize, W)>,
+}
+
+/// How a machine looks for repeated configurations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopDetection {
+    /// Remembers every configuration. Finds the first repetition, but memory grows with the run.
+    Hash,
+    /// Brent's cycle-finding algorithm. Only keeps a single configuration around, but may
+    /// notice a repetition a few iterations of the loop late.
+    #[default]
+    Brent,
+}
+
+/// A configuration that has been seen before. A deterministic machine that gets there can't halt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repetition {
+    /// The step at which the configuration was reached again
+    pub step: usize,
+    /// The step at which the configuration was reached earlier
+    pub repeats: usize,
+}
+
+/// Watches the configurations of a run for repetitions
+#[derive(Debug, Clone)]
+pub struct LoopDetector<W: Word> {
+    mode: LoopDetection,
+    /// Step of the last configuration that has been observed
+    last_step: Option<usize>,
+    /// All configurations seen so far (`LoopDetection::Hash`)
+    seen: HashMap<Configuration<W>, usize>,
+    /// The configuration the current ones get compared to (`LoopDetection::Brent`)
+    saved: Option<(Configuration<W>, usize)>,
+    /// Number of steps after which `saved` gets replaced (`LoopDetection::Brent`)
+    power: usize,
+}
+
+impl<W: Word> LoopDetector<W> {
+    /// Creates a detector that hasn't observed anything yet
+    pub fn new(mode: LoopDetection) -> LoopDetector<W> {
+        LoopDetector {
+            mode,
+            last_step: None,
+            seen: HashMap::new(),
+            saved: None,
+            power: 1,
+        }
+    }
+
+    /// Returns how the detector looks for repetitions
+    pub fn mode(&self) -> LoopDetection {
+        self.mode
+    }
+
+    /// Forgets all observed configurations
+    pub fn clear(&mut self) {
+        *self = LoopDetector::new(self.mode);
+    }
+
+    /// Observes the configuration of `state`. Returns the repetition if it has been seen before.
+    /// States that go back in time (e.g. after a reset) start a new observation.
+    pub fn observe(&mut self, state: &State<W>) -> Option<Repetition> {
+        let step = state.get_steps();
+        match self.last_step {
+            Some(last) if last == step => return None,
+            Some(last) if last > step => self.clear(),
+            _ => {},
+        }
+        self.last_step = Some(step);
+
+        let configuration = state.configuration();
+
+        match self.mode {
+            LoopDetection::Hash => match self.seen.get(&configuration) {
+                Some(&repeats) => Some(Repetition { step, repeats }),
+                None => {
+                    self.seen.insert(configuration, step);
+                    None
+                },
+            },
+            LoopDetection::Brent => {
+                if let Some((saved, repeats)) = &self.saved {
+                    if *saved == configuration {
+                        return Some(Repetition { step, repeats: *repeats });
+                    }
+
+                    if step - repeats < self.power {
+                        return None;
+                    }
+                    self.power *= 2;
+                }
+
+                self.saved = Some((configuration, step));
+                None
+            },
+        }
+    }
+}
+
+impl LoopDetection {
+    /// Parses the name of a detection mode (as printed by `Display`)
+    pub fn from_name(name: &str) -> Option<LoopDetection> {
+        match name.trim().to_lowercase().as_str() {
+            "hash" => Some(LoopDetection::Hash),
+            "brent" => Some(LoopDetection::Brent),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LoopDetection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LoopDetection::Hash => "hash",
+            LoopDetection::Brent => "brent",
+        })
+    }
+}
+
+impl Display for Repetition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The configuration at step {} repeats step {}, the machine won't halt", self.step, self.repeats)
+    }
+}
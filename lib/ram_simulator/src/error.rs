@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::text::source_map::Span;
+
+/// Cause of a failed instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// A register used as a pointer holds a value that isn't a valid register index
+    PointerOutOfRange(String),
+    /// The result of an arithmetic operation doesn't fit into a register
+    Overflow,
+    /// A jump targets an instruction that can't exist (jump targets are 1-based)
+    InvalidJump(usize),
+    /// Division by zero (if the machine's semantics trap on it)
+    DivisionByZero,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::PointerOutOfRange(ptr) => write!(f, "pointer {} is not a valid register index", ptr),
+            Fault::Overflow => write!(f, "arithmetic overflow"),
+            Fault::InvalidJump(target) => write!(f, "invalid jump target {}", target),
+            Fault::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+/// Errors that can occur while running a machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecError {
+    /// The instruction at `pc` failed
+    Fault {
+        pc: usize,
+        instruction: String,
+        cause: Fault,
+        /// The source line of the instruction (if the machine has a source map)
+        line: Option<usize>,
+    },
+    /// The program counter doesn't point at an instruction
+    PcOutOfRange {
+        pc: usize,
+    },
+    /// The machine has already reached an END instruction
+    Halted,
+    /// The trace could not be written
+    Output(String),
+    /// The machine is in its initial configuration and can't go back any further
+    NoPreviousStep,
+    /// The machine can't go back to the step, since it hasn't been recorded
+    NotRecorded {
+        step: usize,
+    },
+}
+
+impl Display for ExecError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // PCs are shown 1-based, the same way jump targets count instructions
+        match self {
+            ExecError::Fault { instruction, cause, line: Some(line), .. } =>
+                write!(f, "Line {} ({}): {}", line, instruction, cause),
+            ExecError::Fault { pc, instruction, cause, line: None } =>
+                write!(f, "Instruction {} ({}): {}", pc + 1, instruction, cause),
+            ExecError::PcOutOfRange { pc } =>
+                write!(f, "Instruction {} doesn't exist (missing END?)", pc + 1),
+            ExecError::Halted =>
+                write!(f, "The machine has reached an END instruction"),
+            ExecError::Output(err) =>
+                write!(f, "Could not write output: {}", err),
+            ExecError::NoPreviousStep =>
+                write!(f, "The machine is in its initial configuration, there's no step to go back to"),
+            ExecError::NotRecorded { step } =>
+                write!(f, "Step {} hasn't been recorded, the machine can't go back to it", step),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+/// What's wrong with a line of a program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParseErrorKind {
+    /// The program couldn't be read
+    Io,
+    /// A word that isn't an instruction
+    UnknownInstruction,
+    /// An instruction without its operand
+    MissingArgument,
+    /// An operand that isn't a number (of the right kind)
+    InvalidArgument,
+    /// An `IF` that doesn't match `IF c(0) <comparison> <value> THEN GOTO <line>`
+    InvalidCondition,
+    /// The program doesn't start with `INIT`
+    MissingInit,
+    /// `INIT` after the first line
+    MisplacedInit,
+    /// The program has no `END`
+    MissingEnd,
+    /// A label definition whose name isn't an identifier
+    InvalidLabel,
+    /// A jump to a label that isn't defined
+    UndefinedLabel,
+    /// A label that is defined more than once
+    DuplicateLabel,
+    /// A directive that isn't `.reg NAME = VALUE` or `.const NAME = VALUE`
+    InvalidDirective,
+    /// A register or constant name that isn't defined
+    UndefinedName,
+    /// A register or constant name that is defined more than once
+    DuplicateName,
+    /// A macro that is defined or invoked wrongly
+    InvalidMacro,
+    /// An `.include` of a file that can't be read
+    InvalidInclude,
+    /// A file that (indirectly) includes itself
+    IncludeCycle,
+    /// A `/*` without `*/` before the end of its file
+    UnterminatedComment,
+    /// A token after an instruction's operands that isn't a comment
+    UnexpectedToken,
+}
+
+/// An error in a program's source, with where it is and (maybe) how to fix it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// Where the error is (`None` if it's not about a specific place, e.g. an unreadable file)
+    pub span: Option<Span>,
+    pub message: String,
+    /// A possible fix, e.g. "did you mean CLOAD?"
+    pub suggestion: Option<String>,
+    /// The included file the error is in (`None` if it's in the program itself)
+    pub file: Option<String>,
+    /// More context, e.g. the macro invocation an error in a macro's body comes from
+    pub note: Option<String>,
+}
+
+/// All errors the parser has found in a program
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ParseErrors {
+    pub errors: Vec<ParseError>,
+    /// The text of the included files, by name, for the excerpts
+    pub sources: HashMap<String, String>,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, span: Option<Span>, message: String) -> ParseError {
+        ParseError { kind, span, message, suggestion: None, file: None, note: None }
+    }
+
+    /// Adds a possible fix to the error
+    pub fn suggest(mut self, suggestion: String) -> ParseError {
+        self.suggestion = Some(suggestion);
+        self
+    }
+
+    /// Renders the error with an excerpt of `source` (which the program has been parsed from),
+    /// marking its place with carets, e.g.
+    ///
+    /// ```text
+    /// error: unknown instruction LOADX
+    ///  --> program.s:3:1
+    ///   |
+    /// 3 | LOADX 1
+    ///   | ^^^^^ did you mean LOAD?
+    /// ```
+    pub fn render(&self, source: &str, name: &str) -> String {
+        let mut res = format!("error: {}\n", self.message);
+
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                res.push_str(&format!(" --> {}\n", name));
+                if let Some(suggestion) = &self.suggestion {
+                    res.push_str(&format!("  = help: {}\n", suggestion));
+                }
+                if let Some(note) = &self.note {
+                    res.push_str(&format!("  = note: {}\n", note));
+                }
+                return res;
+            },
+        };
+
+        let text = source.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+        let gutter = " ".repeat(span.line.to_string().len());
+
+        // Tabs are shown as 4 spaces, the carets have to line up with that
+        let width = |c: char| if c == '\t' { 4 } else { 1 };
+        let offset: usize = text.chars().take(span.start - 1).map(width).sum();
+        let length: usize = text.chars().skip(span.start - 1).take(span.end.saturating_sub(span.start)).map(width).sum();
+
+        res.push_str(&format!("{}--> {}:{}:{}\n", gutter, name, span.line, span.start));
+        res.push_str(&format!("{} |\n", gutter));
+        res.push_str(&format!("{} | {}\n", span.line, text.replace('\t', "    ")));
+        res.push_str(&format!("{} | {}{}", gutter, " ".repeat(offset), "^".repeat(length.max(1))));
+        if let Some(suggestion) = &self.suggestion {
+            res.push(' ');
+            res.push_str(suggestion);
+        }
+        res.push('\n');
+        if let Some(note) = &self.note {
+            res.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+
+        res
+    }
+}
+
+impl ParseErrors {
+    /// Renders all errors (see `ParseError::render`), separated by empty lines. Errors in
+    /// included files get excerpts of those.
+    pub fn render(&self, source: &str, name: &str) -> String {
+        self.errors.iter()
+            .map(|error| match &error.file {
+                Some(file) => error.render(self.sources.get(file).map_or("", String::as_str), file),
+                None => error.render(source, name),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file)?;
+        }
+        match self.span {
+            Some(span) => write!(f, "Line {}, column {}: {}", span.line, span.start, self.message)?,
+            None => write!(f, "{}", self.message)?,
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " ({})", suggestion)?;
+        }
+        match &self.note {
+            Some(note) => write!(f, ", {}", note),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for ParseErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (n, error) in self.errors.iter().enumerate() {
+            if n != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl std::error::Error for ParseErrors {}
@@ -0,0 +1,92 @@
+use crate::cost::Cost;
+use crate::state::State;
+use crate::word::Word;
+
+/// Everything needed to undo a single step: the old PC and counters, plus the old contents of
+/// the registers the step touched (`None` if a register hadn't been used before)
+#[derive(Debug, Clone)]
+pub struct StepRecord<W: Word> {
+    pub(crate) pc: usize,
+    pub(crate) steps: usize,
+    pub(crate) running: bool,
+    pub(crate) highest_register: usize,
+    pub(crate) cost: Cost,
+    pub(crate) live_length: u64,
+    pub(crate) max_live_length: u64,
+    pub(crate) registers: Vec<(usize, Option<W>)>,
+}
+
+/// Recorded steps of a run, used to execute it backwards.
+///
+/// Every `checkpoint_interval` steps, the whole state gets saved and the undo records before it
+/// get dropped, so long runs only keep a bounded number of records around. Going back past the
+/// latest checkpoint restores an earlier one and replays the run from there.
+#[derive(Debug, Clone)]
+pub struct History<W: Word> {
+    checkpoint_interval: usize,
+    /// Saved states in ascending order of their steps
+    checkpoints: Vec<State<W>>,
+    /// Undo records since the latest checkpoint, the latest step last
+    records: Vec<StepRecord<W>>,
+}
+
+impl<W: Word> History<W> {
+    /// Creates an empty history that saves a checkpoint every `checkpoint_interval` steps
+    pub fn new(checkpoint_interval: usize) -> History<W> {
+        History {
+            checkpoint_interval: checkpoint_interval.max(1),
+            checkpoints: Vec::new(),
+            records: Vec::new(),
+        }
+    }
+
+    /// Returns the number of steps between two checkpoints
+    pub fn checkpoint_interval(&self) -> usize {
+        self.checkpoint_interval
+    }
+
+    /// Returns the earliest step the machine can go back to, if any
+    pub fn earliest_step(&self) -> Option<usize> {
+        self.checkpoints.first().map(|c| c.get_steps())
+    }
+
+    /// Forgets everything that has been recorded
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.records.clear();
+    }
+
+    /// Records `state` before it executes a step that touches the `touched` registers
+    pub fn record(&mut self, state: &State<W>, touched: &[usize]) {
+        let due = match self.checkpoints.last() {
+            Some(checkpoint) => state.get_steps() >= checkpoint.get_steps() + self.checkpoint_interval,
+            None => true,
+        };
+
+        if due {
+            self.checkpoints.push(state.clone());
+            self.records.clear();
+        }
+
+        self.records.push(state.step_record(touched));
+    }
+
+    /// Takes the record of the latest step, if it has been recorded since the latest checkpoint
+    pub fn pop(&mut self) -> Option<StepRecord<W>> {
+        self.records.pop()
+    }
+
+    /// Returns the latest checkpoint at or before `step` and drops the ones after it.
+    /// The undo records get dropped as well, since they belong to the latest checkpoint.
+    pub fn rewind(&mut self, step: usize) -> Option<State<W>> {
+        self.records.clear();
+        while let Some(checkpoint) = self.checkpoints.last() {
+            if checkpoint.get_steps() <= step {
+                return Some(checkpoint.clone());
+            }
+            self.checkpoints.pop();
+        }
+
+        None
+    }
+}
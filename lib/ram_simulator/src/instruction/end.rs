@@ -2,11 +2,28 @@ use super::Instruction;
 use super::StringRepr;
 use super::End;
 
+use crate::access::Access;
+use crate::error::Fault;
 use crate::state::State;
+use crate::text::source_map::SourceMap;
+use crate::word::Word;
 
-impl Instruction for End {
-    fn exec(&self, state: &mut State){
+impl<W: Word> Instruction<W> for End {
+    fn log_cost(&self, _state: &State<W>) -> u64 {
+        1
+    }
+
+    fn accesses(&self, _state: &State<W>) -> Vec<Access> {
+        Vec::new()
+    }
+
+    fn explain(&self, _state: &State<W>, _source_map: Option<&SourceMap>) -> String {
+        "END: the machine halts".to_string()
+    }
+
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
         state.stop();
+        Ok(())
     }
 }
 
@@ -0,0 +1,95 @@
+//! Building blocks for `Instruction::explain`. PCs are 0-based, like the `PC:` of the trace.
+
+use crate::access;
+use crate::error::Fault;
+use crate::semantics::Semantics;
+use crate::state::State;
+use crate::text::source_map::SourceMap;
+use crate::word::Word;
+
+/// The value an instruction works with
+pub(crate) enum Operand<'a, W: Word> {
+    /// `c(i)`
+    Direct(usize),
+    /// `c(c(i))`
+    Indirect(usize),
+    /// A constant
+    Constant(&'a W),
+}
+
+impl<'a, W: Word> Operand<'a, W> {
+    /// How the operand is written, e.g. `c(c(2))`
+    fn expression(&self) -> String {
+        match self {
+            Operand::Direct(i) => format!("c({})", i),
+            Operand::Indirect(i) => format!("c(c({}))", i),
+            Operand::Constant(value) => value.to_string(),
+        }
+    }
+
+    /// The value of the operand on `state`, and the register an indirect operand points to
+    fn resolve(&self, state: &State<W>) -> Result<(Option<usize>, W), Fault> {
+        match self {
+            Operand::Direct(i) => Ok((None, state.peek_reg(*i))),
+            Operand::Indirect(i) => match access::pointer(state, *i) {
+                Some(address) => Ok((Some(address), state.peek_reg(address))),
+                None => Err(Fault::PointerOutOfRange(state.peek_reg(*i).to_string())),
+            },
+            Operand::Constant(value) => Ok((None, (*value).clone())),
+        }
+    }
+}
+
+/// How the PC moves on to the next instruction: `PC 5 → 6`
+pub(crate) fn next_line<W: Word>(state: &State<W>) -> String {
+    format!("PC {} → {}", state.get_pc(), state.get_pc() + 1)
+}
+
+/// Explains loading `operand` into the accumulator: `c(0) := c(c(2)) = c(7) = 5, PC 5 → 6`
+pub(crate) fn load<W: Word>(state: &State<W>, operand: Operand<W>) -> String {
+    match operand.resolve(state) {
+        Ok((_, value)) if matches!(operand, Operand::Constant(_)) =>
+            format!("c(0) := {}, {}", value, next_line(state)),
+        Ok((address, value)) => {
+            let resolved = address.map(|a| format!(" = c({})", a)).unwrap_or_default();
+            format!("c(0) := {}{} = {}, {}", operand.expression(), resolved, value, next_line(state))
+        },
+        Err(fault) => format!("c(0) := {} fails: {}", operand.expression(), fault),
+    }
+}
+
+/// Explains an arithmetic instruction: `c(0) := c(0) + c(2) = 5 + 3 = 8, PC 5 → 6`
+pub(crate) fn arithmetic<W: Word>(
+    state: &State<W>,
+    symbol: &str,
+    operand: Operand<W>,
+    operation: fn(&Semantics, &W, &W) -> Result<W, Fault>,
+) -> String {
+    let assignment = format!("c(0) := c(0) {} {}", symbol, operand.expression());
+    let (address, value) = match operand.resolve(state) {
+        Ok(resolved) => resolved,
+        Err(fault) => return format!("{} fails: {}", assignment, fault),
+    };
+
+    let acc = state.peek_reg(0);
+    let resolved = address.map(|a| format!(" = c(0) {} c({})", symbol, a)).unwrap_or_default();
+
+    match operation(&state.semantics(), &acc, &value) {
+        Ok(result) => format!(
+            "{}{} = {} {} {} = {}, {}", assignment, resolved, acc, symbol, value, result, next_line(state)
+        ),
+        Err(fault) => format!("{}{} = {} {} {} fails: {}", assignment, resolved, acc, symbol, value, fault),
+    }
+}
+
+/// Explains continuing at the (1-based) jump target `target`, which faults for 0: `continue at PC 7`,
+/// or `continue at line 12` if `source_map` knows the line of the instruction
+pub(crate) fn continue_at(target: usize, source_map: Option<&SourceMap>) -> String {
+    match target.checked_sub(1) {
+        Some(pc) => match source_map.and_then(|source_map| source_map.line(pc)) {
+            Some(line) => format!("continue at line {}", line),
+            None => format!("continue at PC {}", pc),
+        },
+        None => format!("fails: {}", Fault::InvalidJump(target)),
+    }
+}
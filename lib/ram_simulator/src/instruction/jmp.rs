@@ -1,49 +1,99 @@
 use super::Instruction;
 use super::StringRepr;
+use super::explain;
 use super::{Jmp, CondJmp};
 
+use crate::access::Access;
 use crate::comparison::Comparison;
+use crate::cost;
+use crate::error::Fault;
 use crate::state::State;
+use crate::text::source_map::SourceMap;
+use crate::word::Word;
 
-impl Instruction for Jmp {
-    fn exec(&self, state: &mut State) {
-        state.set_pc(self.operand - 1);
+impl<W: Word> Instruction<W> for Jmp {
+    fn log_cost(&self, _state: &State<W>) -> u64 {
+        1
+    }
+
+    fn accesses(&self, _state: &State<W>) -> Vec<Access> {
+        Vec::new()
+    }
+
+    fn explain(&self, _state: &State<W>, source_map: Option<&SourceMap>) -> String {
+        format!("{}: {}", StringRepr::to_string(self), explain::continue_at(self.operand, source_map))
+    }
+
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
+        match self.operand.checked_sub(1) {
+            Some(pc) => state.set_pc(pc),
+            None => return Err(Fault::InvalidJump(self.operand)),
+        }
+        Ok(())
     }
 }
 
-impl Instruction for CondJmp {
-    fn exec(&self, state: &mut State) {
+impl<W: Word> Instruction<W> for CondJmp<W> {
+    fn log_cost(&self, state: &State<W>) -> u64 {
+        cost::accumulator(state) + cost::length(&self.value)
+    }
+
+    fn accesses(&self, _state: &State<W>) -> Vec<Access> {
+        vec![Access::read(0)]
+    }
+
+    fn explain(&self, state: &State<W>, source_map: Option<&SourceMap>) -> String {
         let acc = state.get_acc();
-        if self.comparison.compare(acc, self.value) {
-            state.set_pc(self.jmp_address - 1);
+        let holds = self.comparison.compare(&acc, &self.value);
+        let next = match holds {
+            true => explain::continue_at(self.jmp_address, source_map),
+            false => explain::continue_at(state.get_pc() + 2, source_map),
+        };
+
+        let symbol = self.comparison.symbol();
+        format!("IF c(0) {} {}: {} {} {} is {}, {}", symbol, self.value, acc, symbol, self.value, holds, next)
+    }
+
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
+        let acc = state.get_acc();
+        if self.comparison.compare(&acc, &self.value) {
+            match self.jmp_address.checked_sub(1) {
+                Some(pc) => state.set_pc(pc),
+                None => return Err(Fault::InvalidJump(self.jmp_address)),
+            }
         } else {
             state.inc_pc();
         }
+        Ok(())
     }
 
     
 }
 
-impl CondJmp {
-    pub fn new(comp: Comparison, value: u128, addr: usize) -> CondJmp {
-        CondJmp { comparison: comp, value: value, jmp_address: addr }
+impl<W: Word> CondJmp<W> {
+    pub fn new(comp: Comparison, value: W, addr: usize) -> CondJmp<W> {
+        CondJmp { comparison: comp, value, jmp_address: addr }
+    }
+
+    pub fn comparison(&self) -> Comparison {
+        self.comparison
+    }
+
+    pub fn value(&self) -> &W {
+        &self.value
+    }
+
+    pub fn jmp_address(&self) -> usize {
+        self.jmp_address
     }
 }
 
-impl StringRepr for CondJmp {
+impl<W: Word> StringRepr for CondJmp<W> {
     fn command_name(&self) -> String { 
         "COND_JMP".to_string()
     }
 
     fn to_string(&self) -> String {
-        let comp: String = match self.comparison {
-            Comparison::Eq => "=",
-            Comparison::Ge => ">=",
-            Comparison::Gt => ">",
-            Comparison::Le => "<=",
-            Comparison::Lt => "<",
-        }.to_string();
-
-        format!("IF c(0){}{} THEN GOTO {}", comp, self.value, self.jmp_address)
+        format!("IF c(0) {} {} THEN GOTO {}", self.comparison.symbol(), self.value, self.jmp_address)
     }
 }
\ No newline at end of file
@@ -1,28 +1,76 @@
 use super::Instruction;
+use super::StringRepr;
+use super::explain::{self, Operand};
 use super::{Load, IndLoad, CLoad};
 
+use crate::access::{self, Access};
+use crate::cost;
+use crate::error::Fault;
 use crate::state::State;
+use crate::text::source_map::SourceMap;
+use crate::word::Word;
 
-impl Instruction for Load {
-    fn exec(&self, state: &mut State) {
+impl<W: Word> Instruction<W> for Load {
+    fn log_cost(&self, state: &State<W>) -> u64 {
+        cost::direct(state, self.operand)
+    }
+
+    fn accesses(&self, _state: &State<W>) -> Vec<Access> {
+        vec![Access::read(self.operand), Access::write(0)]
+    }
+
+    fn explain(&self, state: &State<W>, _source_map: Option<&SourceMap>) -> String {
+        format!("{}: {}", StringRepr::to_string(self), explain::load(state, Operand::Direct(self.operand)))
+    }
+
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
         let value = state.get_reg(self.operand);
         state.set_acc(value);
         state.inc_pc();
+        Ok(())
     }
 }
 
-impl Instruction for CLoad {
-   fn exec(&self, state: &mut crate::state::State) {
-       state.set_acc(self.operand);
+impl<W: Word> Instruction<W> for CLoad<W> {
+   fn log_cost(&self, _state: &State<W>) -> u64 {
+       cost::length(&self.operand)
+   }
+
+   fn accesses(&self, _state: &State<W>) -> Vec<Access> {
+       vec![Access::write(0)]
+   }
+
+   fn explain(&self, state: &State<W>, _source_map: Option<&SourceMap>) -> String {
+       format!("{}: {}", StringRepr::to_string(self), explain::load(state, Operand::Constant(&self.operand)))
+   }
+
+   fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
+       state.set_acc(self.operand.clone());
        state.inc_pc();
+       Ok(())
    }
 }
 
-impl Instruction for IndLoad {
-    fn exec(&self, state: &mut State) {
-        let address = state.get_reg(self.operand) as usize;
+impl<W: Word> Instruction<W> for IndLoad {
+    fn log_cost(&self, state: &State<W>) -> u64 {
+        cost::indirect(state, self.operand)
+    }
+
+    fn accesses(&self, state: &State<W>) -> Vec<Access> {
+        let mut accesses = access::indirect(state, self.operand);
+        accesses.push(Access::write(0));
+        accesses
+    }
+
+    fn explain(&self, state: &State<W>, _source_map: Option<&SourceMap>) -> String {
+        format!("{}: {}", StringRepr::to_string(self), explain::load(state, Operand::Indirect(self.operand)))
+    }
+
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
+        let address = state.get_pointer(self.operand)?;
         let value = state.get_reg(address);
         state.set_acc(value);
         state.inc_pc();
+        Ok(())
     }
-}
\ No newline at end of file
+}
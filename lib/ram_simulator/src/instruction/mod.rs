@@ -1,15 +1,28 @@
+use crate::access::Access;
 use crate::comparison::Comparison;
+use crate::error::{ExecError, Fault};
 use crate::state::{State};
+use crate::text::source_map::SourceMap;
 use crate::text::Serializable;
+use crate::word::{Value, Word};
 
 /// Wrapper for a program (as a list of instructions)
-pub struct InstructionVec {
-    pub instructions: Vec<Box<dyn Instruction>>
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct InstructionVec<W: Word = Value> {
+    pub instructions: Vec<Op<W>>
 }
 
 /// Trait that defines an executable instruction
-pub trait Instruction: StringRepr {
-    fn exec(&self, state: &mut State);
+pub trait Instruction<W: Word>: StringRepr {
+    /// Cost of executing the instruction on `state` under the logarithmic cost measure
+    fn log_cost(&self, state: &State<W>) -> u64;
+    /// Registers the instruction reads and writes when it gets executed on `state`
+    fn accesses(&self, state: &State<W>) -> Vec<Access>;
+    /// Explains in words what executing the instruction on `state` does,
+    /// e.g. `ADD 2: c(0) := c(0) + c(2) = 5 + 3 = 8, PC 5 → 6`.
+    /// Jumps name the source line they continue at if there's a `source_map`.
+    fn explain(&self, state: &State<W>, source_map: Option<&SourceMap>) -> String;
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault>;
 }
 
 /// Forces (most) instructions to have a defined string representation
@@ -20,7 +33,7 @@ pub trait StringRepr {
 }
 
 /// Serialization functions for Instructions
-impl <T> Serializable for T where T: Instruction {
+impl <T> Serializable for T where T: StringRepr {
     fn to_string(&self) -> String {
         self.to_string()
     }
@@ -31,11 +44,11 @@ impl <T> Serializable for T where T: Instruction {
 }
 
 /// Serialization functions for Instruction Vector Wrappers
-impl Serializable for InstructionVec {
+impl<W: Word> Serializable for InstructionVec<W> {
     fn to_string(&self) -> String {
         let mut res = String::new();
         for inst in self.instructions.iter() {
-            res.push_str(inst.to_string().as_str());
+            res.push_str(StringRepr::to_string(inst).as_str());
             res.push('\n');
         }
 
@@ -44,26 +57,27 @@ impl Serializable for InstructionVec {
 
     fn dump(&self) {
         for inst in self.instructions.iter() {
-           println!("{}", inst.to_string().as_str());
+           println!("{}", StringRepr::to_string(inst).as_str());
         }
     }
 }
 
 /// Automatically generates single operand instructions
+/// (the operand is a register address)
 macro_rules! make_single_operand_instruction {
-    ( $( $name:ident, $argtype:ident ), * ) => {
+    ( $( $name:ident ), * ) => {
         $(
-            #[derive(Debug, Clone, Copy)]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
             pub struct $name {
-                operand: $argtype,
+                operand: usize,
             }
 
             impl $name {
                 pub fn operand(&self) -> usize {
-                    self.operand as usize
+                    self.operand
                 }
 
-                pub fn new(op: $argtype) -> $name {
+                pub fn new(op: usize) -> $name {
                     $name {
                         operand: op
                     }
@@ -83,6 +97,41 @@ macro_rules! make_single_operand_instruction {
     };
 }
 
+/// Automatically generates single operand instructions
+/// (the operand is a constant of the machine's word type)
+macro_rules! make_constant_instruction {
+    ( $( $name:ident ), * ) => {
+        $(
+            #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+            pub struct $name<W: Word> {
+                operand: W,
+            }
+
+            impl<W: Word> $name<W> {
+                pub fn operand(&self) -> &W {
+                    &self.operand
+                }
+
+                pub fn new(op: W) -> $name<W> {
+                    $name {
+                        operand: op
+                    }
+                }
+            }
+
+            impl<W: Word> StringRepr for $name<W> {
+                fn command_name(&self) -> String {
+                    stringify!($name).to_string().to_uppercase()
+                }
+
+                fn to_string(&self) -> String {
+                    format!("{} {}", self.command_name(), self.operand())
+                }
+            }
+        )*
+    };
+}
+
 // Modules hold the concrete implementation of the instructions
 // i.e. fn exec(...)
 pub mod load;
@@ -93,6 +142,10 @@ pub mod mult;
 pub mod div;
 pub mod jmp;
 pub mod end;
+pub mod op;
+mod explain;
+
+pub use op::Op;
 
 make_single_operand_instruction![
     /*
@@ -102,69 +155,105 @@ make_single_operand_instruction![
      */
 
     /* Load values on to the accumulator (r0) */
-    Load,       usize,
-    CLoad,      u128,
-    IndLoad,    usize,
+    Load,
+    IndLoad,
 
     /* Store the value that the accumulator currently holds */
-    Store,      usize,
-    IndStore,   usize,
+    Store,
+    IndStore,
 
     /* ADD Functions */
-    Add,        usize,
-    CAdd,       u128,
-    IndAdd,     usize,
+    Add,
+    IndAdd,
 
     /* SUB Functions */
-    Sub,        usize,
-    CSub,       u128,
-    IndSub,     usize,
+    Sub,
+    IndSub,
 
     /* MULT Functions */
-    Mult,       usize,
-    CMult,      u128,
-    IndMult,    usize,
+    Mult,
+    IndMult,
 
     /* DIV Functions */
-    Div,        usize,
-    CDiv,       u128,
-    IndDiv,     usize,
+    Div,
+    IndDiv,
 
     /* JMP Instruction */
-    Jmp,        usize
+    Jmp
+];
+
+make_constant_instruction![
+    CLoad,
+    CAdd,
+    CSub,
+    CMult,
+    CDiv
 ];
 
 
 /* END instruction */
 // This is the only instruction with no arguments: no point in defining a macro for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct End {}
 
 /* Conditional Jump Instrunction */
 // IF r0?value THEN GOTO jmp_address
-pub struct CondJmp {
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CondJmp<W: Word> {
     comparison:     Comparison,
-    value:          u128,
+    value:          W,
     jmp_address:    usize,
 }
 
-impl InstructionVec {
-    /// Executes the instruction at `index` on the machine defined by `state`
-    pub fn exec_instruction(&self, index: usize, state: &mut State) {
-        self.instructions.get(index).unwrap().exec(state)
+impl<W: Word> InstructionVec<W> {
+    /// Executes the instruction at `index` on the machine defined by `state` and charges its cost
+    pub fn exec_instruction(&self, index: usize, state: &mut State<W>) -> Result<(), ExecError> {
+        match self.instructions.get(index) {
+            Some(instruction) => {
+                let cost = instruction.exec_with_cost(state).map_err(|cause| ExecError::Fault {
+                    pc: index,
+                    instruction: StringRepr::to_string(instruction),
+                    cause,
+                    line: None,
+                })?;
+                state.charge(cost);
+                Ok(())
+            },
+            None => Err(ExecError::PcOutOfRange { pc: index }),
+        }
+    }
+
+    /// Executes instructions on `state`, starting at its PC, until the machine reaches `END`
+    /// or has executed `max_steps` instructions. Returns the number of executed instructions.
+    pub fn interpret(&self, state: &mut State<W>, max_steps: usize) -> Result<usize, ExecError> {
+        let mut steps = 0;
+        while state.is_running() && steps < max_steps {
+            self.exec_instruction(state.get_pc(), state)?;
+            state.inc_steps();
+            steps += 1;
+        }
+
+        Ok(steps)
     }
 
     /// Pushes `instruction` on to the `InstructionVec`
-    pub fn push_instruction<T>(&mut self, instruction: T) where T: Instruction + 'static {
-        self.instructions.push(Box::new(instruction));
+    pub fn push_instruction<T>(&mut self, instruction: T) where T: Into<Op<W>> {
+        self.instructions.push(instruction.into());
+    }
+
+    /// Returns the instruction at `index`, if there is one
+    pub fn get_instruction(&self, index: usize) -> Option<&Op<W>> {
+        self.instructions.get(index)
     }
 
-    /// Returned the boxed instruction at `index`
-    pub fn get_boxed_instruction(&self, index: usize) -> &Box<dyn Instruction> {
-        self.instructions.get(index).unwrap()
+    /// Returns the highest register the instructions address directly (or hold a pointer in),
+    /// 0 if there's none. Registers behind the pointers aren't known before a run.
+    pub fn highest_register(&self) -> usize {
+        self.instructions.iter().filter_map(Op::register).max().unwrap_or(0)
     }
 
     /// Empty InstructionVec constructor
-    pub fn new() -> InstructionVec {
+    pub fn new() -> InstructionVec<W> {
         InstructionVec { instructions: Vec::new() }
     }
 }
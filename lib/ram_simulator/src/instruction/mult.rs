@@ -1,31 +1,84 @@
 use super::Instruction;
+use super::StringRepr;
+use super::explain::{self, Operand};
 use super::{Mult, CMult, IndMult};
 
+use crate::access::{self, Access};
+use crate::cost;
+use crate::error::Fault;
+use crate::semantics::Semantics;
 use crate::state::State;
+use crate::text::source_map::SourceMap;
+use crate::word::Word;
 
-impl Instruction for Mult {
-    fn exec(&self, state: &mut State) {
+impl<W: Word> Instruction<W> for Mult {
+    fn log_cost(&self, state: &State<W>) -> u64 {
+        cost::accumulator(state) + cost::direct(state, self.operand)
+    }
+
+    fn accesses(&self, _state: &State<W>) -> Vec<Access> {
+        vec![Access::read(0), Access::read(self.operand), Access::write(0)]
+    }
+
+    fn explain(&self, state: &State<W>, _source_map: Option<&SourceMap>) -> String {
+        format!("{}: {}", StringRepr::to_string(self), explain::arithmetic(state, "*", Operand::Direct(self.operand), Semantics::mul))
+    }
+
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
         let acc = state.get_acc();
         let value = state.get_reg(self.operand);
-        state.set_acc(acc * value);
+        let result = state.semantics().mul(&acc, &value)?;
+        state.set_acc(result);
         state.inc_pc();
+        Ok(())
     }
 }
 
-impl Instruction for CMult {
-    fn exec(&self, state: &mut State) {
+impl<W: Word> Instruction<W> for CMult<W> {
+    fn log_cost(&self, state: &State<W>) -> u64 {
+        cost::accumulator(state) + cost::length(&self.operand)
+    }
+
+    fn accesses(&self, _state: &State<W>) -> Vec<Access> {
+        vec![Access::read(0), Access::write(0)]
+    }
+
+    fn explain(&self, state: &State<W>, _source_map: Option<&SourceMap>) -> String {
+        format!("{}: {}", StringRepr::to_string(self), explain::arithmetic(state, "*", Operand::Constant(&self.operand), Semantics::mul))
+    }
+
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
         let acc = state.get_acc();
-        state.set_acc(acc * self.operand);
+        let result = state.semantics().mul(&acc, &self.operand)?;
+        state.set_acc(result);
         state.inc_pc();
+        Ok(())
     }
 }
 
-impl Instruction for IndMult {
-    fn exec(&self, state: &mut State) {
+impl<W: Word> Instruction<W> for IndMult {
+    fn log_cost(&self, state: &State<W>) -> u64 {
+        cost::accumulator(state) + cost::indirect(state, self.operand)
+    }
+
+    fn accesses(&self, state: &State<W>) -> Vec<Access> {
+        let mut accesses = vec![Access::read(0)];
+        accesses.extend(access::indirect(state, self.operand));
+        accesses.push(Access::write(0));
+        accesses
+    }
+
+    fn explain(&self, state: &State<W>, _source_map: Option<&SourceMap>) -> String {
+        format!("{}: {}", StringRepr::to_string(self), explain::arithmetic(state, "*", Operand::Indirect(self.operand), Semantics::mul))
+    }
+
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
         let acc = state.get_acc();
-        let address = state.get_reg(self.operand) as usize;
+        let address = state.get_pointer(self.operand)?;
         let value = state.get_reg(address);
-        state.set_acc(acc * value);
+        let result = state.semantics().mul(&acc, &value)?;
+        state.set_acc(result);
         state.inc_pc();
+        Ok(())
     }   
-}
\ No newline at end of file
+}
@@ -0,0 +1,134 @@
+use super::{Instruction, StringRepr};
+use super::*;
+
+use crate::access::Access;
+use crate::error::Fault;
+use crate::state::State;
+use crate::word::{Value, Word};
+
+/// Generates `Op` with a variant per instruction type, the conversions into it
+/// and the `match`-based dispatch to the instructions' implementations
+macro_rules! make_op {
+    ( $( $name:ident $( <$w:ident> )? ), * ) => {
+        /// A single instruction of a program.
+        ///
+        /// Unlike boxed `Instruction`s, programs made of these can be cloned, compared, hashed and
+        /// pattern-matched, and executing them doesn't need dynamic dispatch.
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum Op<W: Word = Value> {
+            $( $name($name $( <$w> )?), )*
+        }
+
+        $(
+            impl<W: Word> From<$name $( <$w> )?> for Op<W> {
+                fn from(instruction: $name $( <$w> )?) -> Op<W> {
+                    Op::$name(instruction)
+                }
+            }
+        )*
+
+        impl<W: Word> Instruction<W> for Op<W> {
+            fn log_cost(&self, state: &State<W>) -> u64 {
+                match self {
+                    $( Op::$name(i) => Instruction::<W>::log_cost(i, state), )*
+                }
+            }
+
+            fn accesses(&self, state: &State<W>) -> Vec<Access> {
+                match self {
+                    $( Op::$name(i) => Instruction::<W>::accesses(i, state), )*
+                }
+            }
+
+            fn explain(&self, state: &State<W>, source_map: Option<&SourceMap>) -> String {
+                match self {
+                    $( Op::$name(i) => Instruction::<W>::explain(i, state, source_map), )*
+                }
+            }
+
+            fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
+                match self {
+                    $( Op::$name(i) => Instruction::<W>::exec(i, state), )*
+                }
+            }
+        }
+
+        impl<W: Word> Op<W> {
+            /// Executes the instruction on `state` and returns its cost under the logarithmic
+            /// cost measure. Dispatches once for both (the interpreter's hot path).
+            #[inline]
+            pub fn exec_with_cost(&self, state: &mut State<W>) -> Result<u64, Fault> {
+                match self {
+                    $( Op::$name(i) => {
+                        let cost = Instruction::<W>::log_cost(i, state);
+                        Instruction::<W>::exec(i, state)?;
+                        Ok(cost)
+                    }, )*
+                }
+            }
+        }
+
+        impl<W: Word> StringRepr for Op<W> {
+            fn command_name(&self) -> String {
+                match self {
+                    $( Op::$name(i) => i.command_name(), )*
+                }
+            }
+
+            fn to_string(&self) -> String {
+                match self {
+                    $( Op::$name(i) => StringRepr::to_string(i), )*
+                }
+            }
+        }
+    };
+}
+
+make_op![
+    Load,
+    IndLoad,
+    CLoad<W>,
+    Store,
+    IndStore,
+    Add,
+    IndAdd,
+    CAdd<W>,
+    Sub,
+    IndSub,
+    CSub<W>,
+    Mult,
+    IndMult,
+    CMult<W>,
+    Div,
+    IndDiv,
+    CDiv<W>,
+    Jmp,
+    CondJmp<W>,
+    End
+];
+
+impl<W: Word> Op<W> {
+    /// Checks whether the instruction is `END`
+    pub fn is_end(&self) -> bool {
+        matches!(self, Op::End(_))
+    }
+
+    /// Returns the register the instruction addresses directly (or through a pointer in it)
+    pub fn register(&self) -> Option<usize> {
+        match self {
+            Op::Load(i) => Some(i.operand()),
+            Op::IndLoad(i) => Some(i.operand()),
+            Op::Store(i) => Some(i.operand()),
+            Op::IndStore(i) => Some(i.operand()),
+            Op::Add(i) => Some(i.operand()),
+            Op::IndAdd(i) => Some(i.operand()),
+            Op::Sub(i) => Some(i.operand()),
+            Op::IndSub(i) => Some(i.operand()),
+            Op::Mult(i) => Some(i.operand()),
+            Op::IndMult(i) => Some(i.operand()),
+            Op::Div(i) => Some(i.operand()),
+            Op::IndDiv(i) => Some(i.operand()),
+            _ => None,
+        }
+    }
+}
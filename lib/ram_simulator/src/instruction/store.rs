@@ -1,21 +1,68 @@
 use super::Instruction;
+use super::StringRepr;
+use super::explain;
 use super::{Store, IndStore};
 
+use crate::access::{self, Access};
+use crate::cost;
+use crate::error::Fault;
 use crate::state::State;
+use crate::text::source_map::SourceMap;
+use crate::word::Word;
 
-impl Instruction for Store {
-    fn exec(&self, state: &mut State) {
+impl<W: Word> Instruction<W> for Store {
+    fn log_cost(&self, state: &State<W>) -> u64 {
+        cost::accumulator(state) + cost::address_length(self.operand)
+    }
+
+    fn accesses(&self, _state: &State<W>) -> Vec<Access> {
+        vec![Access::read(0), Access::write(self.operand)]
+    }
+
+    fn explain(&self, state: &State<W>, _source_map: Option<&SourceMap>) -> String {
+        format!("{}: c({}) := c(0) = {}, {}", StringRepr::to_string(self), self.operand, state.get_acc(), explain::next_line(state))
+    }
+
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
         let value = state.get_acc();
         state.set_reg(self.operand, value);
         state.inc_pc();
+        Ok(())
     }
 }
 
-impl Instruction for IndStore {
-    fn exec(&self, state: &mut State) {
-        let address = state.get_reg(self.operand) as usize;
+impl<W: Word> Instruction<W> for IndStore {
+    fn log_cost(&self, state: &State<W>) -> u64 {
+        // l(c(0)) + l(i) + l(c(i)): the target register is only written to
+        cost::accumulator(state) + cost::direct(state, self.operand)
+    }
+
+    fn accesses(&self, state: &State<W>) -> Vec<Access> {
+        let mut accesses = vec![Access::read(0), Access::read(self.operand)];
+        if let Some(address) = access::pointer(state, self.operand) {
+            accesses.push(Access::write(address));
+        }
+        accesses
+    }
+
+    fn explain(&self, state: &State<W>, _source_map: Option<&SourceMap>) -> String {
+        match access::pointer(state, self.operand) {
+            Some(address) => format!(
+                "{}: c(c({})) = c({}) := c(0) = {}, {}",
+                StringRepr::to_string(self), self.operand, address, state.get_acc(), explain::next_line(state)
+            ),
+            None => format!(
+                "{}: c(c({})) := c(0) fails: {}",
+                StringRepr::to_string(self), self.operand, Fault::PointerOutOfRange(state.peek_reg(self.operand).to_string())
+            ),
+        }
+    }
+
+    fn exec(&self, state: &mut State<W>) -> Result<(), Fault> {
+        let address = state.get_pointer(self.operand)?;
         let value = state.get_acc();
         state.set_reg(address, value);
         state.inc_pc();
+        Ok(())
     }
-}
\ No newline at end of file
+}
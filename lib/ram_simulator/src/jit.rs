@@ -0,0 +1,949 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::mem::offset_of;
+
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{
+    types, AbiParam, Block, BlockCall, FuncRef, InstBuilder, JumpTableData, MemFlags, StackSlot,
+    StackSlotData, StackSlotKind,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{Linkage, Module};
+
+use crate::comparison::Comparison;
+use crate::cost::{self, Cost, CostMeasure};
+use crate::error::{ExecError, Fault};
+use crate::instruction::{InstructionVec, Op, StringRepr};
+use crate::run::{RunConfig, Termination};
+use crate::semantics::Semantics;
+use crate::state::State;
+use crate::RegisterMachine;
+
+/// Registers the compiled code keeps in its array at least
+const MIN_REGISTERS: usize = 1024;
+/// Registers the compiled code keeps in its array at most.
+/// Runs that use registers beyond this continue in the interpreter.
+pub const MAX_REGISTERS: usize = 1 << 20;
+/// Maximum number of steps between two checks of the cancellation flag
+const CHUNK_STEPS: u64 = 1 << 24;
+
+/// Why the compiled code returned (the status codes of `Context`)
+const HALTED: u32 = 0;
+const PAUSED: u32 = 1;
+const FAULT: u32 = 2;
+const DEOPT: u32 = 3;
+const PC_OUT_OF_RANGE: u32 = 4;
+
+/// Operations of `ADD`, `SUB`, `MULT` and `DIV` (as handed to `arithmetic`)
+const ADD: u32 = 0;
+const SUB: u32 = 1;
+const MUL: u32 = 2;
+const DIV: u32 = 3;
+
+/// Causes of faults in `Context::fault`
+const OVERFLOW: u64 = 1;
+const DIVISION_BY_ZERO: u64 = 2;
+const INVALID_JUMP: u64 = 3;
+
+/// Why compiled code returned to its caller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exit {
+    /// The machine reached an `END` instruction
+    Halted,
+    /// The machine reached the step or cost limit it has been run with
+    Paused,
+    /// An instruction failed (the machine has been stopped)
+    Fault(ExecError),
+    /// The next instruction can't be executed by the compiled code (it accesses a register outside
+    /// of its array or the PC doesn't point at an instruction), the interpreter has to execute it
+    Deopt,
+}
+
+/// A program compiled to native code with Cranelift (for machines with `u128` registers).
+///
+/// The compiled code works on the registers `0..registers()` as an array, which gets filled from
+/// the `State` before and written back to it after each call of `run`. The PC, step count and
+/// cost are tracked the same way the interpreter tracks them, so a machine can switch between
+/// both at any step.
+pub struct JitProgram {
+    /// Owns the compiled code (freed on drop)
+    module: Option<JITModule>,
+    function: unsafe extern "C" fn(*mut Context) -> u32,
+    program: InstructionVec<u128>,
+    /// The semantics the code has been compiled for
+    semantics: Semantics,
+    registers: usize,
+    /// The register array and which of its registers have been used, kept between calls of `run`
+    values: Vec<u128>,
+    touched: Vec<u8>,
+}
+
+/// What the compiled code reads and updates, shared with `run`
+#[repr(C)]
+struct Context {
+    registers: *mut u128,
+    /// 1 for every register that has been used, 0 otherwise
+    touched: *mut u8,
+    pc: u64,
+    steps: u64,
+    max_steps: u64,
+    logarithmic: u64,
+    max_logarithmic: u64,
+    live_length: u64,
+    max_live_length: u64,
+    /// Cause of a fault (`OVERFLOW`, `DIVISION_BY_ZERO` or `INVALID_JUMP`)
+    fault: u64,
+    /// Target of the jump that faulted with `INVALID_JUMP`
+    jump_target: u64,
+    semantics: Semantics,
+}
+
+/// Called by the compiled code for `ADD`/`SUB`/`MULT`/`DIV` under semantics other than the
+/// default one (which it executes itself), so they follow the machine's semantics exactly. Takes the operands from `operands` and stores the result in `operands[0]`.
+/// Returns the cause of a fault or 0.
+extern "C" fn arithmetic(context: *const Context, operation: u32, operands: *mut u128) -> u64 {
+    // The compiled code passes its context and a (16 byte aligned) stack slot of two words
+    let (semantics, operands) = unsafe { ((*context).semantics, &mut *(operands as *mut [u128; 2])) };
+    let [a, b] = *operands;
+
+    let result = match operation {
+        ADD => semantics.add(&a, &b),
+        SUB => semantics.sub(&a, &b),
+        MUL => semantics.mul(&a, &b),
+        _ => semantics.div(&a, &b),
+    };
+
+    match result {
+        Ok(value) => {
+            operands[0] = value;
+            0
+        },
+        Err(Fault::DivisionByZero) => DIVISION_BY_ZERO,
+        Err(_) => OVERFLOW,
+    }
+}
+
+impl JitProgram {
+    /// Compiles `program` to work on (at least) `registers` registers under `semantics`.
+    /// The default semantics get executed inline, all others call back into `Semantics`.
+    pub fn compile(program: &InstructionVec<u128>, registers: usize, semantics: Semantics) -> Result<JitProgram, String> {
+        let registers = registers.max(program.highest_register() + 1).clamp(MIN_REGISTERS, MAX_REGISTERS);
+
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").map_err(|e| e.to_string())?;
+        let isa = cranelift_native::builder()?
+            .finish(settings::Flags::new(flags))
+            .map_err(|e| e.to_string())?;
+
+        let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+        builder.symbol("ram_arithmetic", arithmetic as *const u8);
+        let mut module = JITModule::new(builder);
+        let pointer = module.target_config().pointer_type();
+
+        let mut signature = module.make_signature();
+        signature.params.push(AbiParam::new(pointer));
+        signature.params.push(AbiParam::new(types::I32));
+        signature.params.push(AbiParam::new(pointer));
+        signature.returns.push(AbiParam::new(types::I64));
+        let arithmetic_id = module.declare_function("ram_arithmetic", Linkage::Import, &signature)
+            .map_err(|e| e.to_string())?;
+
+        let mut context = module.make_context();
+        context.func.signature.params.push(AbiParam::new(pointer));
+        context.func.signature.returns.push(AbiParam::new(types::I32));
+
+        let mut builder_context = FunctionBuilderContext::new();
+        let builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let arithmetic = module.declare_func_in_func(arithmetic_id, builder.func);
+        Compiler::new(builder, arithmetic, semantics, registers, program.instructions.len()).compile(program);
+
+        let id = module.declare_function("ram_program", Linkage::Local, &context.func.signature)
+            .map_err(|e| e.to_string())?;
+        module.define_function(id, &mut context).map_err(|e| e.to_string())?;
+        module.clear_context(&mut context);
+        module.finalize_definitions().map_err(|e| e.to_string())?;
+
+        let code = module.get_finalized_function(id);
+        Ok(JitProgram {
+            module: Some(module),
+            // The function has been compiled with the signature of `function`
+            function: unsafe { std::mem::transmute::<*const u8, unsafe extern "C" fn(*mut Context) -> u32>(code) },
+            program: program.clone(),
+            semantics,
+            registers,
+            values: vec![0; registers],
+            touched: vec![0; registers],
+        })
+    }
+
+    /// Returns the number of registers the compiled code keeps in its array
+    pub fn registers(&self) -> usize {
+        self.registers
+    }
+
+    /// Runs the compiled code on `state` until the machine halts, faults, has executed `max_steps`
+    /// steps in total or has spent `max_logarithmic` (in total) under the logarithmic cost measure.
+    /// A state with other semantics than the compiled ones is left to the interpreter.
+    pub fn run(&mut self, state: &mut State<u128>, max_steps: u64, max_logarithmic: u64) -> Exit {
+        let pc = state.get_pc();
+        if pc >= self.program.instructions.len()
+            || state.get_highest_register() >= self.registers
+            || state.semantics() != self.semantics {
+            return Exit::Deopt;
+        }
+
+        // The arrays are left zeroed by the last call
+        let (registers, touched) = (&mut self.values, &mut self.touched);
+        for (&index, &value) in state.registers() {
+            registers[index] = value;
+            touched[index] = 1;
+        }
+
+        let (live_length, max_live_length) = state.live_lengths();
+        let mut context = Context {
+            registers: registers.as_mut_ptr(),
+            touched: touched.as_mut_ptr(),
+            pc: pc as u64,
+            steps: state.get_steps() as u64,
+            max_steps,
+            logarithmic: state.get_cost().logarithmic,
+            max_logarithmic,
+            live_length,
+            max_live_length,
+            fault: 0,
+            jump_target: 0,
+            semantics: self.semantics,
+        };
+
+        // The arrays hold `self.registers` entries, which the compiled code doesn't index beyond
+        let status = unsafe { (self.function)(&mut context) };
+
+        let pc = context.pc as usize;
+        state.sync(
+            pc,
+            context.steps as usize,
+            context.logarithmic,
+            (context.live_length, context.max_live_length),
+            touched.iter().enumerate().filter(|(_, &t)| t != 0).map(|(i, _)| (i, registers[i])),
+        );
+        registers.fill(0);
+        touched.fill(0);
+
+        match status {
+            PAUSED => Exit::Paused,
+            DEOPT => Exit::Deopt,
+            HALTED => {
+                state.stop();
+                Exit::Halted
+            },
+            PC_OUT_OF_RANGE => {
+                state.stop();
+                Exit::Fault(ExecError::PcOutOfRange { pc })
+            },
+            _ => {
+                state.stop();
+                let cause = match context.fault {
+                    DIVISION_BY_ZERO => Fault::DivisionByZero,
+                    INVALID_JUMP => Fault::InvalidJump(context.jump_target as usize),
+                    _ => Fault::Overflow,
+                };
+                Exit::Fault(ExecError::Fault {
+                    pc,
+                    instruction: self.program.get_instruction(pc).map(StringRepr::to_string).unwrap_or_default(),
+                    cause,
+                    line: None,
+                })
+            },
+        }
+    }
+}
+
+impl Drop for JitProgram {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // `function` can't be called anymore
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+/// The operand of a load or an arithmetic instruction
+enum Operand {
+    Direct(usize),
+    Indirect(usize),
+    Constant(u128),
+}
+
+/// A register index that is either known while compiling or computed at runtime
+#[derive(Clone, Copy)]
+enum Index {
+    Static(usize),
+    Dynamic(cranelift_codegen::ir::Value),
+}
+
+type Value = cranelift_codegen::ir::Value;
+
+/// Generates the code of a program: a block per instruction, which checks the limits, executes
+/// the instruction (mirroring its `exec` and `log_cost`) and jumps to the next one
+struct Compiler<'a> {
+    builder: FunctionBuilder<'a>,
+    arithmetic: FuncRef,
+    semantics: Semantics,
+    /// Scratch space for the operands of `arithmetic`
+    operands: StackSlot,
+    registers: usize,
+    context: Value,
+    register_array: Value,
+    touched_array: Value,
+    max_steps: Value,
+    max_logarithmic: Value,
+    steps: Variable,
+    logarithmic: Variable,
+    live_length: Variable,
+    max_live_length: Variable,
+    /// Stores the counters and returns (parameters: status, PC)
+    exit: Block,
+    /// The block of every instruction
+    blocks: Vec<Block>,
+    /// Blocks for jump targets (and the end of the program) without an instruction
+    missing: HashMap<usize, Block>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(
+        mut builder: FunctionBuilder<'a>,
+        arithmetic: FuncRef,
+        semantics: Semantics,
+        registers: usize,
+        length: usize,
+    ) -> Compiler<'a> {
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+
+        let flags = MemFlags::trusted();
+        let context = builder.block_params(entry)[0];
+        let load = |builder: &mut FunctionBuilder, offset: usize| {
+            builder.ins().load(types::I64, flags, context, offset as i32)
+        };
+
+        let register_array = load(&mut builder, offset_of!(Context, registers));
+        let touched_array = load(&mut builder, offset_of!(Context, touched));
+        let max_steps = load(&mut builder, offset_of!(Context, max_steps));
+        let max_logarithmic = load(&mut builder, offset_of!(Context, max_logarithmic));
+
+        let mut variable = |offset: usize, index: u32| {
+            let variable = Variable::from_u32(index);
+            builder.declare_var(variable, types::I64);
+            let value = load(&mut builder, offset);
+            builder.def_var(variable, value);
+            variable
+        };
+        let steps = variable(offset_of!(Context, steps), 0);
+        let logarithmic = variable(offset_of!(Context, logarithmic), 1);
+        let live_length = variable(offset_of!(Context, live_length), 2);
+        let max_live_length = variable(offset_of!(Context, max_live_length), 3);
+
+        let operands = builder.create_sized_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 32, 4));
+        let exit = builder.create_block();
+        builder.append_block_param(exit, types::I32);
+        builder.append_block_param(exit, types::I64);
+        let blocks = (0..length).map(|_| builder.create_block()).collect();
+
+        Compiler {
+            builder,
+            arithmetic,
+            semantics,
+            operands,
+            registers,
+            context,
+            register_array,
+            touched_array,
+            max_steps,
+            max_logarithmic,
+            steps,
+            logarithmic,
+            live_length,
+            max_live_length,
+            exit,
+            blocks,
+            missing: HashMap::new(),
+        }
+    }
+
+    fn compile(mut self, program: &InstructionVec<u128>) {
+        self.dispatch();
+
+        for (pc, op) in program.instructions.iter().enumerate() {
+            self.builder.switch_to_block(self.blocks[pc]);
+            self.check_limits(pc);
+            self.instruction(pc, op);
+        }
+
+        // Running past the last instruction
+        self.block_at(program.instructions.len());
+
+        let mut pending: Vec<(usize, Block)> = self.missing.iter().map(|(&pc, &block)| (pc, block)).collect();
+        pending.sort_unstable();
+        for (pc, block) in pending {
+            self.builder.switch_to_block(block);
+            self.check_limits(pc);
+            self.exit(PC_OUT_OF_RANGE, pc);
+        }
+
+        self.builder.switch_to_block(self.exit);
+        let status = self.builder.block_params(self.exit)[0];
+        let pc = self.builder.block_params(self.exit)[1];
+        self.store(offset_of!(Context, pc), pc);
+        for (variable, offset) in [
+            (self.steps, offset_of!(Context, steps)),
+            (self.logarithmic, offset_of!(Context, logarithmic)),
+            (self.live_length, offset_of!(Context, live_length)),
+            (self.max_live_length, offset_of!(Context, max_live_length)),
+        ] {
+            let value = self.builder.use_var(variable);
+            self.store(offset, value);
+        }
+        self.builder.ins().return_(&[status]);
+
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+    }
+
+    /// Jumps from the entry block to the instruction the PC points at
+    fn dispatch(&mut self) {
+        let pc = self.builder.ins().load(types::I64, MemFlags::trusted(), self.context, offset_of!(Context, pc) as i32);
+        let index = self.builder.ins().ireduce(types::I32, pc);
+        let deopt = self.builder.ins().iconst(types::I32, DEOPT as i64);
+
+        let pool = &mut self.builder.func.dfg.value_lists;
+        let default = BlockCall::new(self.exit, &[deopt, pc], pool);
+        let table: Vec<BlockCall> = self.blocks.iter().map(|&block| BlockCall::new(block, &[], pool)).collect();
+        let table = self.builder.create_jump_table(JumpTableData::new(default, &table));
+        self.builder.ins().br_table(index, table);
+    }
+
+    /// Returns the block of the instruction at `pc` (or the one that faults if there is none)
+    fn block_at(&mut self, pc: usize) -> Block {
+        if let Some(&block) = self.blocks.get(pc) {
+            return block;
+        }
+
+        match self.missing.get(&pc) {
+            Some(&block) => block,
+            None => {
+                let block = self.builder.create_block();
+                self.missing.insert(pc, block);
+                block
+            },
+        }
+    }
+
+    fn instruction(&mut self, pc: usize, op: &Op<u128>) {
+        match op {
+            Op::Load(i) => self.load(pc, Operand::Direct(i.operand())),
+            Op::IndLoad(i) => self.load(pc, Operand::Indirect(i.operand())),
+            Op::CLoad(c) => self.load(pc, Operand::Constant(*c.operand())),
+            Op::Store(i) => self.store_accumulator(pc, i.operand(), false),
+            Op::IndStore(i) => self.store_accumulator(pc, i.operand(), true),
+            Op::Add(i) => self.arithmetic(pc, ADD, Operand::Direct(i.operand())),
+            Op::IndAdd(i) => self.arithmetic(pc, ADD, Operand::Indirect(i.operand())),
+            Op::CAdd(c) => self.arithmetic(pc, ADD, Operand::Constant(*c.operand())),
+            Op::Sub(i) => self.arithmetic(pc, SUB, Operand::Direct(i.operand())),
+            Op::IndSub(i) => self.arithmetic(pc, SUB, Operand::Indirect(i.operand())),
+            Op::CSub(c) => self.arithmetic(pc, SUB, Operand::Constant(*c.operand())),
+            Op::Mult(i) => self.arithmetic(pc, MUL, Operand::Direct(i.operand())),
+            Op::IndMult(i) => self.arithmetic(pc, MUL, Operand::Indirect(i.operand())),
+            Op::CMult(c) => self.arithmetic(pc, MUL, Operand::Constant(*c.operand())),
+            Op::Div(i) => self.arithmetic(pc, DIV, Operand::Direct(i.operand())),
+            Op::IndDiv(i) => self.arithmetic(pc, DIV, Operand::Indirect(i.operand())),
+            Op::CDiv(c) => self.arithmetic(pc, DIV, Operand::Constant(*c.operand())),
+            Op::Jmp(j) => {
+                let cost = self.builder.ins().iconst(types::I64, 1);
+                self.jump(pc, j.operand(), cost);
+            },
+            Op::CondJmp(j) => {
+                let acc = self.read(Index::Static(0));
+                let acc_length = self.length(acc);
+                let cost = self.builder.ins().iadd_imm(acc_length, cost::length(j.value()) as i64);
+                let value = self.constant(*j.value());
+                let condition = self.builder.ins().icmp(condition_code(j.comparison()), acc, value);
+
+                let taken = self.builder.create_block();
+                let not_taken = self.builder.create_block();
+                self.builder.ins().brif(condition, taken, &[], not_taken, &[]);
+
+                self.builder.switch_to_block(taken);
+                self.jump(pc, j.jmp_address(), cost);
+                self.builder.switch_to_block(not_taken);
+                self.next(pc, cost);
+            },
+            Op::End(_) => {
+                let cost = self.builder.ins().iconst(types::I64, 1);
+                self.finish_step(cost);
+                self.exit(HALTED, pc);
+            },
+        }
+    }
+
+    /// `LOAD`, `INDLOAD` and `CLOAD`
+    fn load(&mut self, pc: usize, operand: Operand) {
+        if let Some((value, cost)) = self.fetch(pc, operand) {
+            self.write(Index::Static(0), value);
+            self.next(pc, cost);
+        }
+    }
+
+    /// `STORE` and `INDSTORE`
+    fn store_accumulator(&mut self, pc: usize, register: usize, indirect: bool) {
+        if register >= self.registers {
+            return self.exit(DEOPT, pc);
+        }
+
+        let acc = self.read(Index::Static(0));
+        let acc_length = self.length(acc);
+        let cost = self.builder.ins().iadd_imm(acc_length, cost::address_length(register) as i64);
+
+        if indirect {
+            let (pointer, target) = self.pointer(pc, register);
+            let pointer_length = self.length(pointer);
+            let cost = self.builder.ins().iadd(cost, pointer_length);
+            self.write(target, acc);
+            self.next(pc, cost);
+        } else {
+            self.write(Index::Static(register), acc);
+            self.next(pc, cost);
+        }
+    }
+
+    /// `ADD`, `SUB`, `MULT` and `DIV` (with all three kinds of operands)
+    fn arithmetic(&mut self, pc: usize, operation: u32, operand: Operand) {
+        let acc = self.read(Index::Static(0));
+        let Some((value, cost)) = self.fetch(pc, operand) else {
+            return;
+        };
+        let acc_length = self.length(acc);
+        let cost = self.builder.ins().iadd(cost, acc_length);
+
+        let result = match self.semantics == Semantics::default() {
+            true => self.natural_arithmetic(pc, operation, acc, value),
+            false => self.call_arithmetic(pc, operation, acc, value),
+        };
+        self.write(Index::Static(0), result);
+        self.next(pc, cost);
+    }
+
+    /// Computes `a <operation> b` under the default semantics: `SUB` stops at 0, `DIV` by 0 is 0
+    /// and results beyond `u128::MAX` fault
+    fn natural_arithmetic(&mut self, pc: usize, operation: u32, a: Value, b: Value) -> Value {
+        match operation {
+            ADD => {
+                let sum = self.builder.ins().iadd(a, b);
+                let overflow = self.builder.ins().icmp(IntCC::UnsignedLessThan, sum, a);
+                self.fault_if(overflow, OVERFLOW, pc);
+                sum
+            },
+            SUB => {
+                let below_zero = self.builder.ins().icmp(IntCC::UnsignedLessThan, a, b);
+                let difference = self.builder.ins().isub(a, b);
+                let zero = self.constant(0);
+                self.builder.ins().select(below_zero, zero, difference)
+            },
+            MUL => {
+                let overflow = self.multiplication_overflows(a, b);
+                self.fault_if(overflow, OVERFLOW, pc);
+                self.builder.ins().imul(a, b)
+            },
+            _ => self.natural_division(pc, a, b),
+        }
+    }
+
+    /// Computes `a / b` (0 if `b` is 0). Cranelift can't divide 128-bit numbers, so only
+    /// operands that fit into 64 bits get divided inline, larger ones are left to `arithmetic`.
+    fn natural_division(&mut self, pc: usize, a: Value, b: Value) -> Value {
+        let (al, ah) = self.builder.ins().isplit(a);
+        let (bl, bh) = self.builder.ins().isplit(b);
+        let high = self.builder.ins().bor(ah, bh);
+        let narrow = self.builder.ins().icmp_imm(IntCC::Equal, high, 0);
+
+        let inline = self.builder.create_block();
+        let call = self.builder.create_block();
+        let done = self.builder.create_block();
+        self.builder.append_block_param(done, types::I128);
+        self.builder.ins().brif(narrow, inline, &[], call, &[]);
+
+        // `udiv` traps on 0, so it divides by 1 instead and the result gets replaced
+        self.builder.switch_to_block(inline);
+        let zero = self.builder.ins().iconst(types::I64, 0);
+        let one = self.builder.ins().iconst(types::I64, 1);
+        let by_zero = self.builder.ins().icmp_imm(IntCC::Equal, bl, 0);
+        let divisor = self.builder.ins().select(by_zero, one, bl);
+        let quotient = self.builder.ins().udiv(al, divisor);
+        let quotient = self.builder.ins().select(by_zero, zero, quotient);
+        let quotient = self.builder.ins().uextend(types::I128, quotient);
+        self.builder.ins().jump(done, &[quotient]);
+
+        self.builder.switch_to_block(call);
+        let quotient = self.call_arithmetic(pc, DIV, a, b);
+        self.builder.ins().jump(done, &[quotient]);
+
+        self.builder.switch_to_block(done);
+        self.builder.block_params(done)[0]
+    }
+
+    /// Whether `a * b` is beyond `u128::MAX`, computed on the 64-bit halves of both:
+    /// `a * b = ah * bh << 128 + (al * bh + ah * bl) << 64 + al * bl`
+    fn multiplication_overflows(&mut self, a: Value, b: Value) -> Value {
+        let (al, ah) = self.builder.ins().isplit(a);
+        let (bl, bh) = self.builder.ins().isplit(b);
+
+        let ah_set = self.builder.ins().icmp_imm(IntCC::NotEqual, ah, 0);
+        let bh_set = self.builder.ins().icmp_imm(IntCC::NotEqual, bh, 0);
+        let both_high = self.builder.ins().band(ah_set, bh_set);
+
+        // At most one of the cross products isn't 0 from here on, so their sum doesn't wrap
+        let mut overflow = both_high;
+        for (x, y) in [(al, bh), (ah, bl)] {
+            let high = self.builder.ins().umulhi(x, y);
+            let wide = self.builder.ins().icmp_imm(IntCC::NotEqual, high, 0);
+            overflow = self.builder.ins().bor(overflow, wide);
+        }
+        let first = self.builder.ins().imul(al, bh);
+        let second = self.builder.ins().imul(ah, bl);
+        let cross = self.builder.ins().iadd(first, second);
+
+        let low_high = self.builder.ins().umulhi(al, bl);
+        let high = self.builder.ins().iadd(low_high, cross);
+        let carry = self.builder.ins().icmp(IntCC::UnsignedLessThan, high, low_high);
+        self.builder.ins().bor(overflow, carry)
+    }
+
+    /// Computes `a <operation> b` by calling `arithmetic`, faults the way it says
+    fn call_arithmetic(&mut self, pc: usize, operation: u32, a: Value, b: Value) -> Value {
+        let slot = self.operands;
+        self.builder.ins().stack_store(a, slot, 0);
+        self.builder.ins().stack_store(b, slot, 16);
+        let operands = self.builder.ins().stack_addr(types::I64, slot, 0);
+        let operation = self.builder.ins().iconst(types::I32, operation as i64);
+        let call = self.builder.ins().call(self.arithmetic, &[self.context, operation, operands]);
+        let cause = self.builder.inst_results(call)[0];
+
+        let fault = self.builder.create_block();
+        let ok = self.builder.create_block();
+        self.builder.ins().brif(cause, fault, &[], ok, &[]);
+
+        self.builder.switch_to_block(fault);
+        self.fault(cause, pc);
+
+        self.builder.switch_to_block(ok);
+        self.builder.ins().stack_load(types::I128, slot, 0)
+    }
+
+    /// Reads an operand like `get_reg` (or `get_pointer`) does and returns it with its cost.
+    /// Returns `None` if it has left the block for the interpreter.
+    fn fetch(&mut self, pc: usize, operand: Operand) -> Option<(Value, Value)> {
+        match operand {
+            Operand::Direct(register) | Operand::Indirect(register) if register >= self.registers => {
+                self.exit(DEOPT, pc);
+                None
+            },
+            Operand::Direct(register) => {
+                let value = self.read(Index::Static(register));
+                let length = self.length(value);
+                let cost = self.builder.ins().iadd_imm(length, cost::address_length(register) as i64);
+                Some((value, cost))
+            },
+            Operand::Indirect(register) => {
+                let (pointer, target) = self.pointer(pc, register);
+                let value = self.read(target);
+                let pointer_length = self.length(pointer);
+                let length = self.length(value);
+                let cost = self.builder.ins().iadd(pointer_length, length);
+                let cost = self.builder.ins().iadd_imm(cost, cost::address_length(register) as i64);
+                Some((value, cost))
+            },
+            Operand::Constant(value) => {
+                let cost = self.builder.ins().iconst(types::I64, cost::length(&value) as i64);
+                Some((self.constant(value), cost))
+            },
+        }
+    }
+
+    /// Reads the pointer in `register`.
+    /// Leaves for the interpreter if it points outside of the array.
+    fn pointer(&mut self, pc: usize, register: usize) -> (Value, Index) {
+        let pointer = self.read(Index::Static(register));
+        let limit = self.constant(self.registers as u128);
+        let outside = self.builder.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, pointer, limit);
+        self.exit_if(outside, DEOPT, pc);
+
+        let index = self.builder.ins().ireduce(types::I64, pointer);
+        (pointer, Index::Dynamic(index))
+    }
+
+    /// Jumps to the 1-based `target` (or faults if it is 0)
+    fn jump(&mut self, pc: usize, target: usize, cost: Value) {
+        match target.checked_sub(1) {
+            Some(target) => {
+                self.finish_step(cost);
+                let block = self.block_at(target);
+                self.builder.ins().jump(block, &[]);
+            },
+            None => {
+                let target = self.builder.ins().iconst(types::I64, target as i64);
+                self.store(offset_of!(Context, jump_target), target);
+                let cause = self.builder.ins().iconst(types::I64, INVALID_JUMP as i64);
+                self.fault(cause, pc);
+            },
+        }
+    }
+
+    /// Counts a successful step and continues with the next instruction
+    fn next(&mut self, pc: usize, cost: Value) {
+        self.finish_step(cost);
+        let block = self.block_at(pc + 1);
+        self.builder.ins().jump(block, &[]);
+    }
+
+    fn finish_step(&mut self, cost: Value) {
+        let steps = self.builder.use_var(self.steps);
+        let steps = self.builder.ins().iadd_imm(steps, 1);
+        self.builder.def_var(self.steps, steps);
+
+        let logarithmic = self.builder.use_var(self.logarithmic);
+        let logarithmic = self.builder.ins().iadd(logarithmic, cost);
+        self.builder.def_var(self.logarithmic, logarithmic);
+    }
+
+    /// Leaves before the instruction at `pc` if the step or cost limit has been reached
+    fn check_limits(&mut self, pc: usize) {
+        let steps = self.builder.use_var(self.steps);
+        let logarithmic = self.builder.use_var(self.logarithmic);
+        let steps_reached = self.builder.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, steps, self.max_steps);
+        let cost_reached = self.builder.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, logarithmic, self.max_logarithmic);
+        let reached = self.builder.ins().bor(steps_reached, cost_reached);
+        self.exit_if(reached, PAUSED, pc);
+    }
+
+    fn exit(&mut self, status: u32, pc: usize) {
+        let status = self.builder.ins().iconst(types::I32, status as i64);
+        let pc = self.builder.ins().iconst(types::I64, pc as i64);
+        self.builder.ins().jump(self.exit, &[status, pc]);
+    }
+
+    /// Leaves if `condition` holds and continues in a new block otherwise
+    fn exit_if(&mut self, condition: Value, status: u32, pc: usize) {
+        let status = self.builder.ins().iconst(types::I32, status as i64);
+        let pc = self.builder.ins().iconst(types::I64, pc as i64);
+        let next = self.builder.create_block();
+        self.builder.ins().brif(condition, self.exit, &[status, pc], next, &[]);
+        self.builder.switch_to_block(next);
+    }
+
+    /// Leaves with a fault of the instruction at `pc`
+    fn fault(&mut self, cause: Value, pc: usize) {
+        self.store(offset_of!(Context, fault), cause);
+        self.exit(FAULT, pc);
+    }
+
+    /// Leaves with a fault of the instruction at `pc` if `condition` holds
+    fn fault_if(&mut self, condition: Value, cause: u64, pc: usize) {
+        let fault = self.builder.create_block();
+        let next = self.builder.create_block();
+        self.builder.ins().brif(condition, fault, &[], next, &[]);
+
+        self.builder.switch_to_block(fault);
+        let cause = self.builder.ins().iconst(types::I64, cause as i64);
+        self.fault(cause, pc);
+        self.builder.switch_to_block(next);
+    }
+
+    fn store(&mut self, offset: usize, value: Value) {
+        self.builder.ins().store(MemFlags::trusted(), value, self.context, offset as i32);
+    }
+
+    /// Returns the address of a register and of its entry in the touched array
+    fn addresses(&mut self, index: Index) -> ((Value, i32), (Value, i32)) {
+        match index {
+            Index::Static(i) => ((self.register_array, (i * 16) as i32), (self.touched_array, i as i32)),
+            Index::Dynamic(i) => {
+                let offset = self.builder.ins().ishl_imm(i, 4);
+                let register = self.builder.ins().iadd(self.register_array, offset);
+                let touched = self.builder.ins().iadd(self.touched_array, i);
+                ((register, 0), (touched, 0))
+            },
+        }
+    }
+
+    /// Reads a register (like `get_reg`, reads don't store unused registers)
+    fn read(&mut self, index: Index) -> Value {
+        let ((address, offset), _) = self.addresses(index);
+        self.builder.ins().load(types::I128, MemFlags::trusted(), address, offset)
+    }
+
+    /// Writes a register (like `set_reg`), replacing the length of its old value in the live length
+    fn write(&mut self, index: Index, value: Value) {
+        let ((address, offset), (touched_address, touched_offset)) = self.addresses(index);
+        let touched = self.builder.ins().uload8(types::I64, MemFlags::trusted(), touched_address, touched_offset);
+        let old = self.builder.ins().load(types::I128, MemFlags::trusted(), address, offset);
+        let old_length = self.length(old);
+        let old_length = self.builder.ins().imul(old_length, touched);
+        let length = self.length(value);
+
+        let one = self.builder.ins().iconst(types::I64, 1);
+        self.builder.ins().istore8(MemFlags::trusted(), one, touched_address, touched_offset);
+        self.builder.ins().store(MemFlags::trusted(), value, address, offset);
+
+        let live_length = self.builder.use_var(self.live_length);
+        let live_length = self.builder.ins().isub(live_length, old_length);
+        let live_length = self.builder.ins().iadd(live_length, length);
+        self.set_live_length(live_length);
+    }
+
+    fn set_live_length(&mut self, live_length: Value) {
+        let max_live_length = self.builder.use_var(self.max_live_length);
+        let max_live_length = self.builder.ins().umax(max_live_length, live_length);
+        self.builder.def_var(self.live_length, live_length);
+        self.builder.def_var(self.max_live_length, max_live_length);
+    }
+
+    /// Length of a number under the logarithmic cost measure (like `cost::length`)
+    fn length(&mut self, value: Value) -> Value {
+        let zeros = self.builder.ins().clz(value);
+        let zeros = self.builder.ins().ireduce(types::I64, zeros);
+        let bits = self.builder.ins().irsub_imm(zeros, 128);
+        let one = self.builder.ins().iconst(types::I64, 1);
+        self.builder.ins().umax(bits, one)
+    }
+
+    fn constant(&mut self, value: u128) -> Value {
+        let low = self.builder.ins().iconst(types::I64, value as u64 as i64);
+        let high = self.builder.ins().iconst(types::I64, (value >> 64) as u64 as i64);
+        self.builder.ins().iconcat(low, high)
+    }
+}
+
+/// Comparisons of `COND_JMP` on natural numbers
+fn condition_code(comparison: Comparison) -> IntCC {
+    match comparison {
+        Comparison::Eq => IntCC::Equal,
+        Comparison::Ne => IntCC::NotEqual,
+        Comparison::Lt => IntCC::UnsignedLessThan,
+        Comparison::Le => IntCC::UnsignedLessThanOrEqual,
+        Comparison::Gt => IntCC::UnsignedGreaterThan,
+        Comparison::Ge => IntCC::UnsignedGreaterThanOrEqual,
+    }
+}
+
+impl RegisterMachine<u128> {
+    /// Runs the machine like `run_until`, but executes the program as native code (see
+    /// `JitProgram`), without breakpoints, loop detection and a trace. Instructions the compiled
+    /// code can't execute are handed to the interpreter. Steps executed this way aren't recorded,
+    /// so the history gets cleared.
+    pub fn run_jit(&mut self, config: &RunConfig) -> Result<Termination, String> {
+        let termination = self.run_compiled(config);
+        self.clear_loop_detector();
+        self.clear_history();
+        termination
+    }
+
+    fn run_compiled(&mut self, config: &RunConfig) -> Result<Termination, String> {
+        if !self.machine_state.is_running() && self.machine_state.get_steps() == 0 {
+            self.machine_state.start();
+        }
+
+        if !self.machine_state.is_running() {
+            return Ok(Termination::Fault(self.fault.clone().unwrap_or(ExecError::Halted)));
+        }
+
+        let start_cost = self.machine_state.get_cost();
+        let mut jit = JitProgram::compile(&self.program, self.machine_state.get_highest_register() + 1, self.get_semantics())?;
+
+        while self.machine_state.is_running() {
+            let cost = self.machine_state.get_cost().since(&start_cost);
+            if let Some(termination) = config.check(cost.uniform, &cost) {
+                return Ok(termination);
+            }
+
+            // The interpreter has used a register beyond the array
+            let highest = self.machine_state.get_highest_register();
+            if highest >= jit.registers() && highest < MAX_REGISTERS {
+                jit = JitProgram::compile(&self.program, (highest + 1).next_power_of_two(), self.get_semantics())?;
+            }
+
+            let (max_steps, max_logarithmic) = limits(config, &self.machine_state, &cost);
+            match jit.run(&mut self.machine_state, max_steps, max_logarithmic) {
+                Exit::Halted | Exit::Paused => {},
+                Exit::Fault(err) => {
+                    let err = self.locate(err);
+                    self.fault = Some(err.clone());
+                    return Ok(Termination::Fault(err));
+                },
+                Exit::Deopt => if let Err(err) = self.exec_next() {
+                    return Ok(Termination::Fault(err));
+                },
+            }
+        }
+
+        Ok(Termination::Halted)
+    }
+
+    /// Runs the machine with `run_jit` and checks the outcome against the interpreter, which runs
+    /// the same program from the same state. Returns the first difference as an error.
+    pub fn run_jit_checked(&mut self, config: &RunConfig) -> Result<Termination, String> {
+        let mut reference = RegisterMachine::new(self.program.clone());
+        reference.source_map = self.source_map.clone();
+        reference.machine_state = self.machine_state.clone();
+        reference.fault = self.fault.clone();
+
+        let expected = reference.run_until(std::io::sink(), config);
+        let actual = self.run_jit(config)?;
+
+        let (a, b) = (&reference.machine_state, &self.machine_state);
+        compare("Termination", &expected, &actual)?;
+        compare("PC", a.get_pc(), b.get_pc())?;
+        compare("Steps", a.get_steps(), b.get_steps())?;
+        compare("Running", a.is_running(), b.is_running())?;
+        compare("Cost", a.get_cost(), b.get_cost())?;
+        compare("Space", a.get_space(), b.get_space())?;
+        compare("Live length", a.live_lengths(), b.live_lengths())?;
+        compare("Highest register", a.get_highest_register(), b.get_highest_register())?;
+        compare("Registers", a.registers(), b.registers())?;
+
+        Ok(actual)
+    }
+}
+
+/// Returns the total steps and logarithmic cost the compiled code may run up to before it has to
+/// return to `run_jit` (to check the budgets of `config` and its cancellation flag).
+/// `used` is what the run has spent so far.
+fn limits(config: &RunConfig, state: &State<u128>, used: &Cost) -> (u64, u64) {
+    let steps = state.get_steps() as u64;
+    let logarithmic = state.get_cost().logarithmic;
+
+    let mut max_steps = steps + CHUNK_STEPS;
+    let mut max_logarithmic = u64::MAX;
+
+    if let Some(max) = config.max_steps {
+        max_steps = max_steps.min(steps + max.saturating_sub(used.uniform));
+    }
+
+    if let Some(max) = config.max_cost {
+        match config.cost_measure {
+            CostMeasure::Uniform => max_steps = max_steps.min(steps + max.saturating_sub(used.uniform)),
+            CostMeasure::Logarithmic =>
+                max_logarithmic = logarithmic.saturating_add(max.saturating_sub(used.logarithmic)),
+        }
+    }
+
+    (max_steps, max_logarithmic)
+}
+
+fn compare<T: PartialEq + Debug>(what: &str, interpreter: T, jit: T) -> Result<(), String> {
+    match interpreter == jit {
+        true => Ok(()),
+        false => Err(format!("{} differs: {:?} (interpreter) vs. {:?} (JIT)", what, interpreter, jit)),
+    }
+}
@@ -1,126 +1,558 @@
 use std::io::Write;
 
-use instruction::InstructionVec;
+use access::{Access, AccessKind};
+use breakpoint::{Breakpoint, BreakpointHit};
+use cycle::{LoopDetection, LoopDetector, Repetition};
+use error::ExecError;
+use history::History;
+use instruction::{Instruction, InstructionVec};
+use run::{Completion, RegisterChange, RunConfig, StepReport, Termination};
+use semantics::Semantics;
 use state::State;
+use text::format::{self, TraceFormat};
+use text::source_map::SourceMap;
+use word::{Value, Word};
 
-use crate::text::Serializable;
 
 pub mod state;
+pub mod access;
+pub mod breakpoint;
 pub mod comparison;
+pub mod cost;
+pub mod cycle;
+pub mod error;
+pub mod history;
 pub mod instruction;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod run;
+pub mod semantics;
 pub mod text;
+pub mod word;
+
+/// What `run_loop` calls after every step: with the step's report and the state after it
+type AfterStep<'a, W> = &'a mut dyn FnMut(&StepReport<W>, &State<W>) -> Result<(), ExecError>;
 
 /// The machine itself
-pub struct RegisterMachine {
-    machine_state: state::State,
-    program: InstructionVec,
+pub struct RegisterMachine<W: Word = Value> {
+    machine_state: state::State<W>,
+    program: InstructionVec<W>,
+    /// The error the machine stopped on, if any
+    fault: Option<ExecError>,
+    /// Watches runs for repeated configurations, if enabled
+    loop_detector: Option<LoopDetector<W>>,
+    /// Breakpoints for `run_until` and their ids
+    breakpoints: Vec<(usize, Breakpoint<W>)>,
+    /// The id the next breakpoint gets
+    next_breakpoint_id: usize,
+    /// The steps and PC a run has stopped at before an instruction because of a breakpoint, so
+    /// resuming there doesn't stop at once again
+    paused: Option<(usize, usize)>,
+    /// Recorded steps for executing backwards, if enabled
+    history: Option<History<W>>,
+    /// Where the instructions are in the program's source, if it has been parsed from one
+    source_map: Option<SourceMap>,
 }
 
-impl RegisterMachine {
+impl<W: Word> RegisterMachine<W> {
     /// Creates a new RAM that has `isv` loaded as a program
-    pub fn new(isv: InstructionVec) -> RegisterMachine {
+    pub fn new(isv: InstructionVec<W>) -> RegisterMachine<W> {
         RegisterMachine {
             machine_state: state::State::initial(),
             program: isv,
+            fault: None,
+            loop_detector: None,
+            breakpoints: Vec::new(),
+            next_breakpoint_id: 1,
+            paused: None,
+            history: None,
+            source_map: None,
         }
     }
 
     /// Creates a new empty RAM
-    pub const fn new_empty() -> RegisterMachine {
+    pub const fn new_empty() -> RegisterMachine<W> {
         RegisterMachine {
             machine_state: state::State::initial(),
             program: InstructionVec { instructions: Vec::new() },
+            fault: None,
+            loop_detector: None,
+            breakpoints: Vec::new(),
+            next_breakpoint_id: 1,
+            paused: None,
+            history: None,
+            source_map: None,
         }
     }
 
     /// Resets the state of the RAM
     pub fn reset(&mut self) {
         self.machine_state.reset();
+        self.fault = None;
+        self.paused = None;
+        self.clear_loop_detector();
+        self.clear_history();
     }
 
     /// Loads a new InstructionVec `isv` onto the machine and resets it.
-    pub fn load_program(&mut self, isv: InstructionVec) {
+    /// The source map of the previous program gets dropped.
+    pub fn load_program(&mut self, isv: InstructionVec<W>) {
         self.reset();
         self.program = isv;
+        self.source_map = None;
+    }
+
+    /// Returns the program loaded onto the machine
+    pub fn get_program(&self) -> &InstructionVec<W> {
+        &self.program
+    }
+
+    /// Sets where the program's instructions are in its source (the parser does this)
+    pub fn set_source_map(&mut self, source_map: Option<SourceMap>) {
+        self.source_map = source_map;
+    }
+
+    /// Returns where the program's instructions are in its source, if it has been parsed from one
+    pub fn get_source_map(&self) -> Option<&SourceMap> {
+        self.source_map.as_ref()
+    }
+
+    /// Adds the source line of the failed instruction to an error (if the machine has a source map)
+    fn locate(&self, err: ExecError) -> ExecError {
+        match (err, &self.source_map) {
+            (ExecError::Fault { pc, instruction, cause, line: None }, Some(source_map)) =>
+                ExecError::Fault { pc, instruction, cause, line: source_map.line(pc) },
+            (err, _) => err,
+        }
     }
 
     /// Pushes a vector onto the registers of the machine (useful to set initial state)
-    pub fn push_vec(&mut self, vec: Vec<u128>) {
-        for (i, &v) in vec.iter().enumerate() {
+    pub fn push_vec(&mut self, vec: Vec<W>) {
+        for (i, v) in vec.into_iter().enumerate() {
             self.machine_state.set_reg(i+1, v);
         }
     }
 
-    /// Runs the machine (until it internally reaches `END`) and outputs register values on each step
-    /// Returns errors if any
-    pub fn run<T: Write>(&mut self, mut output: T) -> Option<String> {
-        self.machine_state.start();
+    /// Sets the arithmetic semantics of the machine.
+    /// Fails if they can't be used with the machine's word type.
+    pub fn set_semantics(&mut self, semantics: Semantics) -> Result<(), String> {
+        semantics.check::<W>()?;
+        self.machine_state.set_semantics(semantics);
+        // Replaying recorded steps under other semantics would yield other states
+        self.clear_history();
+        Ok(())
+    }
+
+    /// Returns the arithmetic semantics of the machine
+    pub fn get_semantics(&self) -> Semantics {
+        self.machine_state.semantics()
+    }
+
+    /// Enables (or, with `None`, disables) looking for repeated configurations while running
+    pub fn set_loop_detection(&mut self, mode: Option<LoopDetection>) {
+        self.loop_detector = mode.map(LoopDetector::new);
+    }
 
-        // Output initial config in yellow
-        match output.write(b"\x1b[33mInitial Configuration -- ") {
-            Ok(_) => {},
-            Err(u) => return Some(u.to_string()),
+    /// Returns how the machine looks for repeated configurations, if it does
+    pub fn get_loop_detection(&self) -> Option<LoopDetection> {
+        self.loop_detector.as_ref().map(|d| d.mode())
+    }
+
+    /// Feeds the current configuration to the loop detector (if enabled).
+    /// Returns the repetition if the machine has been in this configuration before.
+    pub fn detect_loop(&mut self) -> Option<Repetition> {
+        if !self.has_not_ended() {
+            return None;
+        }
+
+        match &mut self.loop_detector {
+            Some(detector) => detector.observe(&self.machine_state),
+            None => None,
+        }
+    }
+
+    fn clear_loop_detector(&mut self) {
+        if let Some(detector) = &mut self.loop_detector {
+            detector.clear();
+        }
+    }
+
+    /// Records the steps of the machine from now on (or, with `None`, stops recording them), so it
+    /// can execute backwards. The whole state is saved every `checkpoint_interval` steps.
+    pub fn set_history(&mut self, checkpoint_interval: Option<usize>) {
+        self.history = checkpoint_interval.map(History::new);
+    }
+
+    /// Returns the recorded steps, if recording is enabled
+    pub fn get_history(&self) -> Option<&History<W>> {
+        self.history.as_ref()
+    }
+
+    fn clear_history(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.clear();
         }
+    }
 
-        match self.machine_state.print_registers(&mut output) {
-            Ok(_) => {},
-            Err(u) => return Some(u),
+    /// Goes back a single step (or, if the machine has stopped on an error, to the state before it)
+    pub fn step_back(&mut self) -> Result<&State<W>, ExecError> {
+        let steps = self.machine_state.get_steps();
+        let step = match self.fault {
+            Some(_) => steps,
+            None => steps.checked_sub(1).ok_or(ExecError::NoPreviousStep)?,
         };
 
-        match output.write(b"\x1b[0m\n") {
-            Ok(_) => {},
-            Err(u) => return Some(u.to_string())
+        self.run_back_to(step)
+    }
+
+    /// Goes back to the state after `step` steps (at most to the earliest recorded step)
+    pub fn run_back_to(&mut self, step: usize) -> Result<&State<W>, ExecError> {
+        let history = match &mut self.history {
+            Some(history) if history.earliest_step().is_some_and(|earliest| earliest <= step) => history,
+            _ => return Err(ExecError::NotRecorded { step }),
         };
 
-        // Let the machine run
+        if step > self.machine_state.get_steps() {
+            return Err(ExecError::NotRecorded { step });
+        }
+
+        // Undo the steps since the latest checkpoint
+        while self.fault.is_some() || self.machine_state.get_steps() > step {
+            match history.pop() {
+                Some(record) => {
+                    self.machine_state.undo(record);
+                    self.fault = None;
+                },
+                None => break,
+            }
+        }
+
+        // Replay the run from an earlier checkpoint
+        if self.fault.is_some() || self.machine_state.get_steps() != step {
+            match history.rewind(step) {
+                Some(checkpoint) => {
+                    self.machine_state.overwrite(&checkpoint);
+                    self.fault = None;
+                },
+                None => return Err(ExecError::NotRecorded { step }),
+            }
+
+            while self.machine_state.get_steps() < step {
+                self.exec_next()?;
+            }
+        }
+
+        Ok(&self.machine_state)
+    }
+
+    /// Moves the machine to the state after `step` steps, backwards or forwards.
+    /// Going forwards stops early if the machine halts.
+    pub fn seek(&mut self, step: usize) -> Result<&State<W>, ExecError> {
+        if self.fault.is_some() || step <= self.machine_state.get_steps() {
+            return self.run_back_to(step);
+        }
+
+        if !self.machine_state.is_running() && self.machine_state.get_steps() == 0 {
+            self.machine_state.start();
+        }
+
+        while self.machine_state.is_running() && self.machine_state.get_steps() < step {
+            self.exec_next()?;
+        }
+
+        Ok(&self.machine_state)
+    }
+
+    /// Adds a breakpoint and returns its id
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint<W>) -> usize {
+        let id = self.next_breakpoint_id;
+        self.next_breakpoint_id += 1;
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    /// Removes the breakpoint with the given id. Returns false if there is none.
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|(i, _)| *i != id);
+        count != self.breakpoints.len()
+    }
+
+    /// Removes all breakpoints
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Returns the breakpoints and their ids
+    pub fn get_breakpoints(&self) -> &[(usize, Breakpoint<W>)] {
+        &self.breakpoints
+    }
+
+    /// Returns the first breakpoint that fires before the next instruction (`accesses` is `None`),
+    /// or after an instruction made the given `accesses`
+    fn check_breakpoints(&self, accesses: Option<&[Access]>) -> Option<BreakpointHit> {
+        self.breakpoints.iter()
+            .find(|(_, breakpoint)| match accesses {
+                None => breakpoint.is_hit_before(&self.machine_state, self.source_map.as_ref()),
+                Some(accesses) => breakpoint.is_hit_after(accesses),
+            })
+            .map(|(id, breakpoint)| BreakpointHit {
+                id: *id,
+                step: self.machine_state.get_steps(),
+                breakpoint: breakpoint.to_string(),
+            })
+    }
+
+    /// Runs the machine (until it internally reaches `END`) and outputs register values on each step
+    /// Returns why the machine stopped
+    pub fn run<T: Write>(&mut self, output: T) -> Termination {
+        self.run_with(output, &RunConfig::default())
+    }
+
+    /// Outputs the initial configuration and runs the machine like `run_until`.
+    /// Outputs the cost and space of the run if the machine halts.
+    pub fn run_with<T: Write>(&mut self, output: T, config: &RunConfig) -> Termination {
+        self.run_formatted(output, config, &mut format::Ansi::default())
+    }
+
+    /// Runs the machine like `run_with`, but writes the trace in the given format
+    pub fn run_formatted<T: Write>(&mut self, mut output: T, config: &RunConfig, format: &mut dyn TraceFormat<W>) -> Termination {
+        self.machine_state.start();
+        if let Some(source_map) = &self.source_map {
+            format.name_registers(source_map.register_names());
+        }
+
+        if let Err(u) = format.initial(&mut output, &self.machine_state) {
+            return Termination::Fault(ExecError::Output(u.to_string()));
+        }
+
+        let termination = self.run_until_formatted(&mut output, config, format);
+        if !termination.is_halted() {
+            return termination;
+        }
+
+        // Output the time and space it took
+        if let Err(u) = format.summary(&mut output, &self.machine_state) {
+            return Termination::Fault(ExecError::Output(u.to_string()));
+        }
+
+        Termination::Halted
+    } 
+
+    /// Runs the machine until it reaches `END`, a breakpoint fires, it exhausts a budget of `config`,
+    /// gets cancelled or repeats a configuration (if loop detection is enabled).
+    /// Outputs register values on each step. A stopped run can be resumed by calling this again.
+    pub fn run_until<T: Write>(&mut self, output: T, config: &RunConfig) -> Termination {
+        self.run_until_formatted(output, config, &mut format::Ansi::default())
+    }
+
+    /// Runs the machine like `run_until`, but writes the trace in the given format
+    pub fn run_until_formatted<T: Write>(&mut self, mut output: T, config: &RunConfig, format: &mut dyn TraceFormat<W>) -> Termination {
+        if let Some(source_map) = &self.source_map {
+            format.name_registers(source_map.register_names());
+        }
+        self.run_loop(config, true, Some(&mut |report: &StepReport<W>, state: &State<W>| {
+            format.step(&mut output, report, state).map_err(|u| ExecError::Output(u.to_string()))
+        }))
+    }
+
+    /// Runs the machine like `run_until`, but without any output and ignoring breakpoints.
+    /// Returns why it stopped together with the final state, the steps and the cost.
+    pub fn run_to_completion(&mut self, config: &RunConfig) -> Completion<W> {
+        let termination = self.run_loop(config, false, None);
+
+        Completion {
+            termination,
+            state: self.machine_state.clone(),
+            steps: self.machine_state.get_steps(),
+            cost: self.machine_state.get_cost(),
+            space: self.machine_state.get_space(),
+        }
+    }
+
+    /// The loop behind `run_until` and `run_to_completion`: `after_step` (if any) gets called with
+    /// a report of every step and the state after it
+    fn run_loop(
+        &mut self,
+        config: &RunConfig,
+        breakpoints: bool,
+        mut after_step: Option<AfterStep<W>>,
+    ) -> Termination {
+        if !self.machine_state.is_running() && self.machine_state.get_steps() == 0 {
+            self.machine_state.start();
+        }
+
+        if !self.machine_state.is_running() {
+            return Termination::Fault(self.fault.clone().unwrap_or(ExecError::Halted));
+        }
+
+        let start_cost = self.machine_state.get_cost();
+        if let Some(repetition) = self.detect_loop() {
+            return Termination::Loop(repetition);
+        }
+
+        let resumed = self.paused.take();
         while self.machine_state.is_running() {
-            let pc = self.machine_state.get_pc();
-            
-            self.program.exec_instruction(pc, &mut self.machine_state);
-            self.machine_state.inc_steps();
-            
-            match output.write(self.machine_state.to_string().as_bytes()) {
-                Ok(_) => {},
-                Err(u) => return Some(u.to_string()),
-            };
-            match output.write(b"\n") {
-                Ok(_) => {},
-                Err(u) => return Some(u.to_string()),
+            let cost = self.machine_state.get_cost().since(&start_cost);
+            if let Some(termination) = config.check(cost.uniform, &cost) {
+                return termination;
+            }
+
+            let position = (self.machine_state.get_steps(), self.machine_state.get_pc());
+            if breakpoints && resumed != Some(position) {
+                if let Some(hit) = self.check_breakpoints(None) {
+                    self.paused = Some(position);
+                    return Termination::Breakpoint(hit);
+                }
+            }
+
+            let accesses = match &mut after_step {
+                Some(after_step) => match self.exec_reported() {
+                    Ok((report, accesses)) => match after_step(&report, &self.machine_state) {
+                        Ok(_) => accesses,
+                        Err(err) => return Termination::Fault(err),
+                    },
+                    Err(err) => return Termination::Fault(err),
+                },
+                None => match self.exec_next() {
+                    Ok(accesses) => accesses,
+                    Err(err) => return Termination::Fault(err),
+                },
             };
+
+            if !self.machine_state.is_running() {
+                break;
+            }
+
+            if let Some(repetition) = self.detect_loop() {
+                return Termination::Loop(repetition);
+            }
+
+            if breakpoints {
+                if let Some(hit) = self.check_breakpoints(Some(&accesses)) {
+                    return Termination::Breakpoint(hit);
+                }
+            }
         }
 
-        None
-    } 
+        Termination::Halted
+    }
 
-    /// Runs the machine for a single step
-    pub fn step(&mut self) -> Result<State, &str> {
+    /// Runs the machine for a single step and reports what it did
+    /// (the new state can be borrowed with `get_state`)
+    pub fn step(&mut self) -> Result<StepReport<W>, ExecError> {
         if !self.machine_state.is_running() && self.machine_state.get_steps() == 0 {
             self.machine_state.start();
         }
 
-        if !self.machine_state.is_running() && self.machine_state.get_acc() != 0 {
-            return Err("The machine has reached an END instruction")
+        if !self.machine_state.is_running() {
+            return Err(self.fault.clone().unwrap_or(ExecError::Halted))
+        }
+
+        self.exec_reported().map(|(report, _)| report)
+    }
+
+    /// Explains in words what executing the next instruction does (see `Instruction::explain`).
+    /// Returns None if the machine has stopped or the PC doesn't point at an instruction.
+    pub fn explain_next(&self) -> Option<String> {
+        if !self.has_not_ended() {
+            return None;
+        }
+
+        self.program.get_instruction(self.machine_state.get_pc())
+            .map(|instruction| instruction.explain(&self.machine_state, self.source_map.as_ref()))
+    }
+
+    /// Executes the instruction the PC points at like `exec_next`, and also reports what it did
+    fn exec_reported(&mut self) -> Result<(StepReport<W>, Vec<Access>), ExecError> {
+        let pc = self.machine_state.get_pc();
+        let instruction = match self.program.get_instruction(pc) {
+            Some(instruction) => instruction.clone(),
+            // Let `exec_next` fault
+            None => return self.exec_next().map(|_| unreachable!()),
+        };
+
+        // The registers written, with their old contents
+        let accesses = instruction.accesses(&self.machine_state);
+        let mut changes: Vec<RegisterChange<W>> = accesses.iter()
+            .filter(|access| access.kind == AccessKind::Write)
+            .map(|access| RegisterChange {
+                register: access.register,
+                old: self.machine_state.peek_reg(access.register),
+                new: W::zero(),
+            })
+            .collect();
+
+        let accesses = self.exec_accessing(accesses)?;
+
+        for change in &mut changes {
+            change.new = self.machine_state.peek_reg(change.register);
         }
 
+        let line = self.source_map.as_ref().and_then(|source_map| source_map.line(pc));
+        Ok((StepReport { pc, line, instruction, changes }, accesses))
+    }
+
+    /// Executes the instruction the PC points at and returns the registers it has accessed.
+    /// Faults stop the machine and are kept until it gets reset.
+    fn exec_next(&mut self) -> Result<Vec<Access>, ExecError> {
+        let pc = self.machine_state.get_pc();
+
+        // Only breakpoints and the history need to know which registers get accessed
+        let accesses = match self.program.get_instruction(pc) {
+            Some(instruction) if self.history.is_some() || !self.breakpoints.is_empty() =>
+                instruction.accesses(&self.machine_state),
+            _ => Vec::new(),
+        };
+
+        self.exec_accessing(accesses)
+    }
+
+    /// Executes the instruction the PC points at, which accesses the registers in `accesses`
+    fn exec_accessing(&mut self, accesses: Vec<Access>) -> Result<Vec<Access>, ExecError> {
         let pc = self.machine_state.get_pc();
-        self.program.exec_instruction(pc, &mut self.machine_state);
-        self.machine_state.inc_steps();
-            
-        Ok(self.machine_state)
+
+        if let Some(history) = &mut self.history {
+            let touched: Vec<usize> = accesses.iter().map(|access| access.register).collect();
+            history.record(&self.machine_state, &touched);
+        }
+
+        match self.program.exec_instruction(pc, &mut self.machine_state) {
+            Ok(_) => {
+                self.machine_state.inc_steps();
+                Ok(accesses)
+            },
+            Err(err) => {
+                let err = self.locate(err);
+                self.machine_state.stop();
+                self.fault = Some(err.clone());
+                Err(err)
+            }
+        }
     }
 
     /// Returns whether the internal state of the machine has reached an END instruction
     pub fn has_not_ended(&self) -> bool {
-        self.machine_state.is_running() || self.machine_state.get_steps() == 0 
+        self.fault.is_none()
+            && (self.machine_state.is_running() || self.machine_state.get_steps() == 0)
+    }
+
+    /// Returns the error the machine stopped on, if any
+    pub fn get_fault(&self) -> Option<&ExecError> {
+        self.fault.as_ref()
     }
 
     /// Sets the internal state of the machine
-    pub fn set_state(&mut self, new: State) {
+    pub fn set_state(&mut self, new: State<W>) {
         self.machine_state.overwrite(&new);
+        self.fault = None;
+        self.paused = None;
+        self.clear_loop_detector();
+        self.clear_history();
     }
 
     /// Gets the internal state of the machine
-    pub fn get_state(&self) -> &State {
+    pub fn get_state(&self) -> &State<W> {
         &self.machine_state
     }
 }
\ No newline at end of file
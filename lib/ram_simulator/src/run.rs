@@ -0,0 +1,183 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::breakpoint::BreakpointHit;
+use crate::cost::{Cost, CostMeasure, Space};
+use crate::cycle::Repetition;
+use crate::error::ExecError;
+use crate::instruction::{Op, StringRepr};
+use crate::state::State;
+use crate::word::Word;
+
+/// Limits for `RegisterMachine::run_with`. Budgets count from the start of the run.
+#[derive(Debug, Clone, Default)]
+pub struct RunConfig {
+    /// Maximum number of instructions to execute
+    pub max_steps: Option<u64>,
+    /// Maximum cost (under `cost_measure`) the run may spend
+    pub max_cost: Option<u64>,
+    /// The cost measure `max_cost` refers to
+    pub cost_measure: CostMeasure,
+    /// The run stops as soon as this gets set (e.g. from another thread)
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+/// The budget a run has exhausted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Steps(u64),
+    Cost(CostMeasure, u64),
+}
+
+/// Why a run stopped
+#[must_use]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Termination {
+    /// The machine reached an END instruction
+    Halted,
+    /// The machine was still running when its budget ran out
+    BudgetExhausted(Budget),
+    /// The cancellation flag was set
+    Cancelled,
+    /// The machine returned to an earlier configuration, so it won't ever halt
+    Loop(Repetition),
+    /// A breakpoint fired (the machine can be resumed)
+    Breakpoint(BreakpointHit),
+    /// The machine stopped on an error
+    Fault(ExecError),
+}
+
+/// The result of `RegisterMachine::run_to_completion`
+#[derive(Debug, Clone)]
+pub struct Completion<W: Word> {
+    /// Why the run stopped
+    pub termination: Termination,
+    /// The final configuration
+    pub state: State<W>,
+    /// Number of instructions executed (in total, not only by this run)
+    pub steps: usize,
+    /// Cost of the machine's run so far
+    pub cost: Cost,
+    /// Space of the machine's run so far
+    pub space: Space,
+}
+
+/// What a single step did (see `RegisterMachine::step`). The full state can be borrowed
+/// from the machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepReport<W: Word> {
+    /// The PC of the executed instruction
+    pub pc: usize,
+    /// The source line of the executed instruction (if the machine has a source map)
+    pub line: Option<usize>,
+    /// The executed instruction
+    pub instruction: Op<W>,
+    /// The registers the instruction has written
+    pub changes: Vec<RegisterChange<W>>,
+}
+
+/// A register written by a step, with its contents before and after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterChange<W: Word> {
+    pub register: usize,
+    pub old: W,
+    pub new: W,
+}
+
+impl RunConfig {
+    /// Creates a config without any limits
+    pub fn new() -> RunConfig {
+        RunConfig::default()
+    }
+
+    /// Creates a config that only limits the number of steps
+    pub fn with_max_steps(max_steps: u64) -> RunConfig {
+        RunConfig {
+            max_steps: Some(max_steps),
+            ..RunConfig::default()
+        }
+    }
+
+    /// Returns a new cancellation flag that is attached to the config
+    pub fn cancellation_flag(&mut self) -> Arc<AtomicBool> {
+        let flag = Arc::new(AtomicBool::new(false));
+        self.cancel = Some(flag.clone());
+        flag
+    }
+
+    /// Checks whether a run that has executed `steps` instructions for `cost` has to stop
+    pub fn check(&self, steps: u64, cost: &Cost) -> Option<Termination> {
+        if let Some(flag) = &self.cancel {
+            if flag.load(Ordering::Relaxed) {
+                return Some(Termination::Cancelled);
+            }
+        }
+
+        if let Some(max) = self.max_steps {
+            if steps >= max {
+                return Some(Termination::BudgetExhausted(Budget::Steps(max)));
+            }
+        }
+
+        if let Some(max) = self.max_cost {
+            if cost.get(self.cost_measure) >= max {
+                return Some(Termination::BudgetExhausted(Budget::Cost(self.cost_measure, max)));
+            }
+        }
+
+        None
+    }
+}
+
+impl Termination {
+    /// Checks whether the machine reached an END instruction
+    pub fn is_halted(&self) -> bool {
+        *self == Termination::Halted
+    }
+}
+
+impl<W: Word> StepReport<W> {
+    /// Returns the written registers whose contents have actually changed
+    pub fn changed(&self) -> impl Iterator<Item = &RegisterChange<W>> {
+        self.changes.iter().filter(|change| change.old != change.new)
+    }
+}
+
+impl<W: Word> Display for StepReport<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // PCs are shown 1-based, like in errors
+        match self.line {
+            Some(line) => write!(f, "Line {} ({})", line, StringRepr::to_string(&self.instruction))?,
+            None => write!(f, "Instruction {} ({})", self.pc + 1, StringRepr::to_string(&self.instruction))?,
+        }
+        for (n, change) in self.changes.iter().enumerate() {
+            f.write_str(if n == 0 { ": " } else { ", " })?;
+            write!(f, "r{}: {} -> {}", change.register, change.old, change.new)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Budget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Budget::Steps(max) => write!(f, "{} steps", max),
+            Budget::Cost(measure, max) => write!(f, "{} cost of {}", measure, max),
+        }
+    }
+}
+
+impl Display for Termination {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Termination::Halted => write!(f, "The machine halted"),
+            Termination::BudgetExhausted(budget) =>
+                write!(f, "The machine didn't halt within its budget ({})", budget),
+            Termination::Cancelled => write!(f, "The run was cancelled"),
+            Termination::Loop(repetition) => write!(f, "{}", repetition),
+            Termination::Breakpoint(hit) => write!(f, "{}", hit),
+            Termination::Fault(err) => write!(f, "{}", err),
+        }
+    }
+}
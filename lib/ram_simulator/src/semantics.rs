@@ -0,0 +1,167 @@
+use std::fmt::{Display, Formatter};
+
+use crate::error::Fault;
+use crate::word::Word;
+
+/// How results of `ADD`/`SUB`/`MULT`/`DIV` that leave the range of the machine get handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Arithmetic {
+    /// Natural numbers: subtraction is modified (`a - b = 0` if `a < b`), overflow traps
+    #[default]
+    Monus,
+    /// Integers: negative results are allowed (needs a signed word type), overflow traps
+    Signed,
+    /// Results wrap around at the bounds of the word type (which needs to be bounded)
+    Wrapping,
+    /// Results are clamped to the bounds of the word type. Unbounded word types only have a lower
+    /// bound if they're unsigned (0), so only subtraction can saturate with them.
+    Saturating,
+    /// Every result that isn't representable traps
+    Trapping,
+}
+
+/// What `DIV` does when the divisor is 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DivisionByZero {
+    /// The result is 0
+    #[default]
+    Zero,
+    /// The instruction traps
+    Trap,
+}
+
+/// Arithmetic semantics of a machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Semantics {
+    pub arithmetic: Arithmetic,
+    pub division_by_zero: DivisionByZero,
+}
+
+impl Semantics {
+    /// Creates a new semantics configuration
+    pub const fn new(arithmetic: Arithmetic, division_by_zero: DivisionByZero) -> Semantics {
+        Semantics { arithmetic, division_by_zero }
+    }
+
+    /// Checks whether the semantics can be used with registers of type `W`
+    pub fn check<W: Word>(&self) -> Result<(), String> {
+        if self.arithmetic == Arithmetic::Signed && !W::SIGNED {
+            return Err("Signed arithmetic needs a signed word type".to_string());
+        }
+        if self.arithmetic == Arithmetic::Wrapping && !W::BOUNDED {
+            return Err("Wrapping arithmetic needs a bounded word type, unbounded registers have nothing to wrap around at".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Computes `a + b`
+    pub fn add<W: Word>(&self, a: &W, b: &W) -> Result<W, Fault> {
+        match self.arithmetic {
+            Arithmetic::Monus => Ok(natural(a.checked_add(b).ok_or(Fault::Overflow)?)),
+            Arithmetic::Signed |
+            Arithmetic::Trapping => a.checked_add(b).ok_or(Fault::Overflow),
+            Arithmetic::Wrapping => a.wrapping_add(b).ok_or(Fault::Overflow),
+            Arithmetic::Saturating => Ok(a.saturating_add(b)),
+        }
+    }
+
+    /// Computes `a - b`
+    pub fn sub<W: Word>(&self, a: &W, b: &W) -> Result<W, Fault> {
+        match self.arithmetic {
+            Arithmetic::Monus => if a < b {
+                Ok(W::zero())
+            } else {
+                a.checked_sub(b).ok_or(Fault::Overflow)
+            },
+            Arithmetic::Signed |
+            Arithmetic::Trapping => a.checked_sub(b).ok_or(Fault::Overflow),
+            Arithmetic::Wrapping => a.wrapping_sub(b).ok_or(Fault::Overflow),
+            Arithmetic::Saturating => Ok(a.saturating_sub(b)),
+        }
+    }
+
+    /// Computes `a * b`
+    pub fn mul<W: Word>(&self, a: &W, b: &W) -> Result<W, Fault> {
+        match self.arithmetic {
+            Arithmetic::Monus => Ok(natural(a.checked_mul(b).ok_or(Fault::Overflow)?)),
+            Arithmetic::Signed |
+            Arithmetic::Trapping => a.checked_mul(b).ok_or(Fault::Overflow),
+            Arithmetic::Wrapping => a.wrapping_mul(b).ok_or(Fault::Overflow),
+            Arithmetic::Saturating => Ok(a.saturating_mul(b)),
+        }
+    }
+
+    /// Computes `a / b` (rounded towards 0)
+    pub fn div<W: Word>(&self, a: &W, b: &W) -> Result<W, Fault> {
+        if b.is_zero() {
+            return match self.division_by_zero {
+                DivisionByZero::Zero => Ok(W::zero()),
+                DivisionByZero::Trap => Err(Fault::DivisionByZero),
+            };
+        }
+
+        match self.arithmetic {
+            Arithmetic::Monus => Ok(natural(a.checked_div(b).ok_or(Fault::Overflow)?)),
+            Arithmetic::Signed |
+            Arithmetic::Trapping => a.checked_div(b).ok_or(Fault::Overflow),
+            Arithmetic::Wrapping => a.wrapping_div(b).ok_or(Fault::Overflow),
+            Arithmetic::Saturating => Ok(a.saturating_div(b)),
+        }
+    }
+}
+
+/// Clamps negative values to 0
+fn natural<W: Word>(value: W) -> W {
+    if value.is_negative() {
+        W::zero()
+    } else {
+        value
+    }
+}
+
+impl Arithmetic {
+    /// Parses the name of an arithmetic mode (as printed by `Display`)
+    pub fn from_name(name: &str) -> Option<Arithmetic> {
+        match name.trim().to_lowercase().as_str() {
+            "monus"      => Some(Arithmetic::Monus),
+            "signed"     => Some(Arithmetic::Signed),
+            "wrapping"   => Some(Arithmetic::Wrapping),
+            "saturating" => Some(Arithmetic::Saturating),
+            "trapping"   => Some(Arithmetic::Trapping),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Arithmetic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Arithmetic::Monus      => "monus",
+            Arithmetic::Signed     => "signed",
+            Arithmetic::Wrapping   => "wrapping",
+            Arithmetic::Saturating => "saturating",
+            Arithmetic::Trapping   => "trapping",
+        })
+    }
+}
+
+impl DivisionByZero {
+    /// Parses the name of a division by zero policy (as printed by `Display`)
+    pub fn from_name(name: &str) -> Option<DivisionByZero> {
+        match name.trim().to_lowercase().as_str() {
+            "zero" => Some(DivisionByZero::Zero),
+            "trap" => Some(DivisionByZero::Trap),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DivisionByZero {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DivisionByZero::Zero => "zero",
+            DivisionByZero::Trap => "trap",
+        })
+    }
+}
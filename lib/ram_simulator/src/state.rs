@@ -1,59 +1,130 @@
+use std::collections::BTreeMap;
 use std::io::Write;
 
+use crate::cost::{self, Cost, Space};
+use crate::cycle::Configuration;
+use crate::error::Fault;
+use crate::history::StepRecord;
+use crate::semantics::{Arithmetic, DivisionByZero, Semantics};
 use crate::text::Serializable;
+use crate::word::{Value, Word};
 
 /// Keeps track of the RAM's current state.
-#[derive(Debug, Clone, Copy)]
-pub struct State {
+#[derive(Debug, Clone)]
+pub struct State<W: Word = Value> {
     /// `Program Counter`: the offset of the next instruction to be executed
     pc: usize,
-    /// Registers (128-bit * 1024 = 16KiB)
-    registers: [u128; 1024],
-    /// Highest register used (read from or written to)
+    /// Registers that have been written to. All others hold 0.
+    registers: BTreeMap<usize, W>,
+    /// Highest register written to
     highest_register: usize,
     /// Machine running? (Is END reached?)
     running: bool,
     /// Steps up to now
     steps: usize,
+    /// Time spent up to now (under both cost measures)
+    cost: Cost,
+    /// Summed bit lengths of the used registers
+    live_length: u64,
+    /// Maximum of `live_length` up to now
+    max_live_length: u64,
+    /// Arithmetic semantics (configuration, survives resets)
+    semantics: Semantics,
 }
 
 /// Methods for the State struct.
-impl State {
+impl<W: Word> State<W> {
     /// Creates an empty (new) state.
-    pub const fn initial() -> State {
+    pub const fn initial() -> State<W> {
         State {
             pc: 0,
-            registers: [0; 1024],
+            registers: BTreeMap::new(),
             highest_register: 0,
             running: false,
             steps: 0,
+            cost: Cost { uniform: 0, logarithmic: 0 },
+            live_length: 0,
+            max_live_length: 0,
+            semantics: Semantics::new(Arithmetic::Monus, DivisionByZero::Zero),
         }
     }
 
     /// Returns the contents of the accumulator (r0).
-    pub fn get_acc(&self) -> u128 {
-        self.registers[0]
+    pub fn get_acc(&self) -> W {
+        self.peek_reg(0)
     }
 
     /// Sets the contents of the accumulator (r0).
-    pub fn set_acc(&mut self, value: u128) {
-        self.registers[0] = value;
+    pub fn set_acc(&mut self, value: W) {
+        self.set_reg(0, value);
+    }
+
+    /// Returns the contents of the given register (for inspecting the state).
+    pub fn peek_reg(&self, index: usize) -> W {
+        match self.registers.get(&index) {
+            Some(value) => value.clone(),
+            None => W::zero(),
+        }
     }
 
     /// Returns the contents of the given register.
-    pub fn get_reg(&mut self, index: usize) -> u128 {
-        if self.highest_register < index {
-            self.highest_register = index;
+    /// Reading a register that has never been written to doesn't store it: only writes use space.
+    pub fn get_reg(&self, index: usize) -> W {
+        self.peek_reg(index)
+    }
+
+    /// Returns the contents of the given register as a register index (for indirect addressing).
+    pub fn get_pointer(&self, index: usize) -> Result<usize, Fault> {
+        let pointer = self.get_reg(index);
+        match pointer.to_usize() {
+            Some(address) => Ok(address),
+            None => Err(Fault::PointerOutOfRange(pointer.to_string())),
         }
-        self.registers[index]
     }
 
     /// Sets a register to the given value.
-    pub fn set_reg(&mut self, index: usize, value: u128) {
+    pub fn set_reg(&mut self, index: usize, value: W) {
         if self.highest_register < index {
             self.highest_register = index;
         }
-        self.registers[index] = value;
+        let length = cost::length(&value);
+        let old_length = match self.registers.insert(index, value) {
+            Some(old) => cost::length(&old),
+            None => 0,
+        };
+        self.grow_live_length(old_length, length);
+    }
+
+    /// Replaces `old` bits of the written registers by `new` ones and records the maximum
+    fn grow_live_length(&mut self, old: u64, new: u64) {
+        self.live_length = self.live_length - old + new;
+        if self.max_live_length < self.live_length {
+            self.max_live_length = self.live_length;
+        }
+    }
+
+    /// Returns the used registers in ascending order.
+    /// The accumulator (r0) is always included.
+    pub fn used_registers(&self) -> impl Iterator<Item = (usize, W)> + '_ {
+        let acc = match self.registers.contains_key(&0) {
+            true => None,
+            false => Some((0, W::zero())),
+        };
+
+        acc.into_iter().chain(
+            self.registers.iter().map(|(&i, v)| (i, v.clone()))
+        )
+    }
+
+    /// Returns the PC and the registers that don't hold 0
+    pub fn configuration(&self) -> Configuration<W> {
+        Configuration {
+            pc: self.pc,
+            registers: self.registers.iter()
+                .filter(|(_, v)| !v.is_zero())
+                .map(|(&i, v)| (i, v.clone()))
+                .collect(),
+        }
     }
 
     /// Sets the program counter to the given value.
@@ -71,7 +142,17 @@ impl State {
         self.pc += 1;
     }
 
-    /// Returns the highest register used.
+    /// Returns the arithmetic semantics.
+    pub fn semantics(&self) -> Semantics {
+        self.semantics
+    }
+
+    /// Sets the arithmetic semantics.
+    pub fn set_semantics(&mut self, semantics: Semantics) {
+        self.semantics = semantics;
+    }
+
+    /// Returns the highest register written to.
     pub fn get_highest_register(&self) -> usize {
         self.highest_register
     }
@@ -101,42 +182,122 @@ impl State {
         self.steps
     }
 
-    /// Prints registers up to the highest register used
-    pub fn print_registers<T: Write>(&self, output: &mut T) -> Result<(), String> {
-        for i in 0..self.highest_register+1 {
-            match output.write(
-                format!("r{:}: {}", i, self.registers[i]).as_bytes()
-            ) {
-                Ok(_) => {},
-                Err(u) => return Err(
-                    format!("Could not write to buffer: {}", u.to_string())
-                )
-            };
+    /// Charges an executed instruction that costs `logarithmic` under the logarithmic measure
+    pub fn charge(&mut self, logarithmic: u64) {
+        self.cost.charge(logarithmic);
+    }
+
+    /// Get the time spent up to now
+    pub fn get_cost(&self) -> Cost {
+        self.cost
+    }
+
+    /// Get the space used up to now
+    pub fn get_space(&self) -> Space {
+        Space {
+            uniform: self.registers.len() as u64,
+            logarithmic: self.max_live_length,
+        }
+    }
 
-            if i != self.highest_register {
-                match output.write(b", ") {
-                    Ok(_) => {},
-                    Err(u) => return Err(
-                        format!("Could not write to buffer: {}", u.to_string())
-                    )
-                };
+    /// Prints the used registers
+    pub fn print_registers<T: Write>(&self, output: &mut T) -> Result<(), String> {
+        for (n, (i, value)) in self.used_registers().enumerate() {
+            if n != 0 {
+                output.write_all(b", ")
+                    .map_err(|u| format!("Could not write to buffer: {}", u))?;
             }
+
+            output.write_all(format!("r{:}: {}", i, value).as_bytes())
+                .map_err(|u| format!("Could not write to buffer: {}", u))?;
         }
 
         Ok(())
     }
 
-    /// Resets the machine's state to the initial one
+    /// Resets the machine's state to the initial one (keeps the semantics)
     pub fn reset(&mut self) {
         self.pc = 0;
-        self.registers = [0; 1024];
+        self.registers.clear();
         self.highest_register = 0;
         self.running = false;
         self.steps = 0;
+        self.cost = Cost::default();
+        self.live_length = 0;
+        self.max_live_length = 0;
+    }
+
+    /// Saves what's needed to undo a step that touches the `touched` registers
+    pub fn step_record(&self, touched: &[usize]) -> StepRecord<W> {
+        StepRecord {
+            pc: self.pc,
+            steps: self.steps,
+            running: self.running,
+            highest_register: self.highest_register,
+            cost: self.cost,
+            live_length: self.live_length,
+            max_live_length: self.max_live_length,
+            registers: touched.iter().map(|&i| (i, self.registers.get(&i).cloned())).collect(),
+        }
+    }
+
+    /// Undoes the step `record` has been saved for
+    pub fn undo(&mut self, record: StepRecord<W>) {
+        self.pc = record.pc;
+        self.steps = record.steps;
+        self.running = record.running;
+        self.highest_register = record.highest_register;
+        self.cost = record.cost;
+        self.live_length = record.live_length;
+        self.max_live_length = record.max_live_length;
+
+        for (index, value) in record.registers {
+            match value {
+                Some(value) => { self.registers.insert(index, value); },
+                None => { self.registers.remove(&index); },
+            }
+        }
+    }
+
+    /// Returns the registers that have been used (without the accumulator if it hasn't been)
+    #[cfg(feature = "jit")]
+    pub(crate) fn registers(&self) -> &BTreeMap<usize, W> {
+        &self.registers
+    }
+
+    /// Returns the summed bit lengths of the used registers and their maximum up to now
+    #[cfg(feature = "jit")]
+    pub(crate) fn live_lengths(&self) -> (u64, u64) {
+        (self.live_length, self.max_live_length)
     }
 
-    /// Overwrites the machine's state
-    pub fn overwrite(&mut self, new: &State) {
+    /// Takes over the registers and counters of steps that have been executed outside of
+    /// the interpreter (by the JIT). `registers` are the used registers after the steps.
+    #[cfg(feature = "jit")]
+    pub(crate) fn sync(
+        &mut self,
+        pc: usize,
+        steps: usize,
+        logarithmic: u64,
+        live_lengths: (u64, u64),
+        registers: impl Iterator<Item = (usize, W)>,
+    ) {
+        self.cost.uniform += (steps - self.steps) as u64;
+        self.cost.logarithmic = logarithmic;
+        self.pc = pc;
+        self.steps = steps;
+        (self.live_length, self.max_live_length) = live_lengths;
+
+        for (index, value) in registers {
+            if self.highest_register < index {
+                self.highest_register = index;
+            }
+            self.registers.insert(index, value);
+        }
+    }
+
+    /// Overwrites the machine's state (keeps the semantics)
+    pub fn overwrite(&mut self, new: &State<W>) {
         self.reset();
 
 
@@ -144,48 +305,52 @@ impl State {
         self.highest_register = new.highest_register;
         self.running = new.running;
         self.steps = new.steps;
+        self.cost = new.cost;
+        self.live_length = new.live_length;
+        self.max_live_length = new.max_live_length;
         
-        for i in 0..self.highest_register+1 {
-            self.registers[i] = new.registers[i];
-        }
+        self.registers.clone_from(&new.registers);
     }
 }
 
 /// Implement Serialization funcs for state objects
-impl Serializable for State {
+impl<W: Word> Serializable for State<W> {
     fn to_string(&self) -> String {
         let mut res = String::new();
         res.push_str(
-            // The state we get has already has its step/pc incremented internally
-            format!("Step {:2} -- PC: {:2}, ", self.steps-1, self.pc).as_str()
+            format!("Step {:2} -- PC: {:2}, ", self.steps, self.pc).as_str()
         );
 
-        for rn in 0..self.highest_register+1 {
-            res.push_str(
-                format!("r{}: {}", rn, self.registers[rn]).as_str()
-            );
-
-            if rn != self.highest_register {
+        for (n, (rn, value)) in self.used_registers().enumerate() {
+            if n != 0 {
                 res.push_str(", ");
             }
+
+            res.push_str(
+                format!("r{}: {}", rn, value).as_str()
+            );
         }
 
         res
     }
 
     fn dump(&self) {
-        print!("Step {:2} -- PC: {:2}, ", self.steps-1, self.pc);
+        print!("Step {:2} -- PC: {:2}, ", self.steps, self.pc);
 
-        for rn in 0..self.highest_register+1 {
-            print!("r{}: {}, ", rn, self.registers[rn])
+        for (rn, value) in self.used_registers() {
+            print!("r{}: {}, ", rn, value)
         }
 
         print!("\x08\x08\x20\x20");
     }
 }
 
-impl State {
+impl<W: Word> State<W> {
     const SEPARATOR: char = '<';
+    const ASSIGNMENT: char = '=';
+
+    /// Serializes the state for the WebAssembly interface:
+    /// `r|s < steps < uniform cost < logarithmic cost < pc < highest register < index=value ...`
     pub fn to_wasm_comm_str(&self) -> String {
         let mut res = String::new();
         res.push(match self.running {
@@ -197,101 +362,94 @@ impl State {
         res.push_str(&self.steps.to_string());
         res.push(Self::SEPARATOR);
 
+        res.push_str(&self.cost.uniform.to_string());
+        res.push(Self::SEPARATOR);
+
+        res.push_str(&self.cost.logarithmic.to_string());
+        res.push(Self::SEPARATOR);
+
         res.push_str(&self.pc.to_string());
         res.push(Self::SEPARATOR);
 
         res.push_str(&self.highest_register.to_string());
-        res.push(Self::SEPARATOR);
 
-        for i in 0..self.highest_register+1 {
-            res.push_str(&self.registers[i].to_string());
-            
-            if i < self.highest_register {
-                res.push(Self::SEPARATOR);
-            }
+        for (i, value) in self.used_registers() {
+            res.push(Self::SEPARATOR);
+            res.push_str(&i.to_string());
+            res.push(Self::ASSIGNMENT);
+            res.push_str(&value.to_string());
         }
 
         res
     }
 
-    pub fn from_wasm_comm_str(istr:  &str) -> Result<State, String> {
+    /// Deserializes a state produced by `to_wasm_comm_str`
+    pub fn from_wasm_comm_str(istr:  &str) -> Result<State<W>, String> {
         let mut res = State::initial();
 
         let mut tokens = istr.split(Self::SEPARATOR);
 
-        let mut next_token = tokens.next();
-
-        if next_token.is_none() {
+        let Some(token) = tokens.next() else {
             return Err("Expected a running/stopped at pos 0".to_string());
-        } else {
-            match next_token.unwrap() {
-                "r" => res.running = true,
-                "s" => res.running = false,
-                _ => return Err(
-                    format!("r? {}", next_token.unwrap())
-                )
-            }
+        };
+        match token {
+            "r" => res.running = true,
+            "s" => res.running = false,
+            _ => return Err(format!("r? {}", token)),
         }
 
-        next_token = tokens.next();
-        if next_token.is_none() {
+        let Some(token) = tokens.next() else {
             return Err("Expected steps at pos 1".to_string());
-        } else {
-            match next_token.unwrap().parse::<usize>() {
-                Ok(steps) => res.steps = steps,
+        };
+        res.steps = token.parse::<usize>()
+            .map_err(|pie| format!("step number {} -- {}", token, pie))?;
+
+        let Some(token) = tokens.next() else {
+            return Err("Expected the uniform cost at pos 2".to_string());
+        };
+        res.cost.uniform = token.parse::<u64>()
+            .map_err(|pie| format!("uniform cost {} -- {}", token, pie))?;
+
+        let Some(token) = tokens.next() else {
+            return Err("Expected the logarithmic cost at pos 3".to_string());
+        };
+        res.cost.logarithmic = token.parse::<u64>()
+            .map_err(|pie| format!("logarithmic cost {} -- {}", token, pie))?;
+
+        let Some(token) = tokens.next() else {
+            return Err("Expected a PC at pos 4".to_string());
+        };
+        res.set_pc(token.parse::<usize>().map_err(|pie| format!("pc {}: {}", token, pie))?);
+
+        let Some(token) = tokens.next() else {
+            return Err("Expected Highest Register at pos 5".to_string());
+        };
+        res.highest_register = token.parse::<usize>()
+            .map_err(|pie| format!("highest register {} - {}", token, pie))?;
+
+        for token in tokens {
+            let (index, value) = match token.split_once(Self::ASSIGNMENT) {
+                Some(pair) => pair,
+                None => return Err(format!("Expected index=value, got {}", token)),
+            };
+
+            let index = match index.parse::<usize>() {
+                Ok(index) => index,
                 Err(pie) => {
-                    return Err(
-                        format!("step number {} -- {}", next_token.unwrap(), pie.to_string())
-                    );
+                    return Err(format!("register index {} -- {}", index, pie));
                 }
-            }
-        }
-        
-        next_token = tokens.next();
-        if next_token.is_none() {
-            return Err("Expected a PC at pos 2".to_string());
-        } else {
-            match next_token.unwrap().parse::<usize>() {
-                Ok(pc) => res.set_pc(pc),
-                Err(pie) => {
-                    return Err(
-                        format!("pc {}: {}", next_token.unwrap(), pie.to_string())
-                    );
-                }
-            }
-        }
-
+            };
 
-        next_token = tokens.next();
-        if next_token.is_none() {
-            return Err("Expected Highest Register at pos 3".to_string());
-        } else {
-            match next_token.unwrap().parse::<usize>() {
-                Ok(highest) => res.highest_register = highest,
+            match W::parse_word(value) {
+                Ok(rv) => res.set_reg(index, rv),
                 Err(pie) => {
                     return Err(
-                        format!("highest register {} - {}", next_token.unwrap(), pie.to_string())
+                        format!("register value {}: {} -- {}", index, value, pie)
                     );
                 }
             }
         }
 
-        for i in 0..res.highest_register+1 {
-            next_token = tokens.next();
-            if next_token.is_none() {
-                return Err("Expected more register values".to_string());
-            } else {
-                match next_token.unwrap().parse::<u128>() {
-                    Ok(rv) => res.registers[i] = rv,
-                    Err(pie) => {
-                        return Err(
-                            format!("register value {}: {} -- {}", i, next_token.unwrap(), pie.to_string())
-                        );
-                    }
-                }
-            }
-        }
-
         Ok(res)
     }
 }
\ No newline at end of file
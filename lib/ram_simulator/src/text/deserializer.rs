@@ -1,166 +1,1063 @@
+use std::collections::HashMap;
 use std::io::{BufReader, BufRead};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs::File;
 
-use regex::{Regex, Captures};
+use regex::Regex;
 use lazy_static::lazy_static;
 use crate::RegisterMachine;
 
 use crate::comparison::Comparison;
+use crate::error::{ParseError, ParseErrorKind, ParseErrors};
+use crate::text::expression::{self, ExpressionError};
+use crate::text::lexer::{self, Lexer};
+use crate::text::macros::{self, Macro, Replacement};
+use crate::text::source_map::{SourceMap, Span};
 use crate::instruction::*;
+use crate::word::{Value, Word};
 
 lazy_static!{
-    static ref COND_JMP_PARSER: Regex = Regex::new(r"IF\s+[cC]\(0\)\s*(<|>|=|>=|<=)\s*([0-9]+)\s*THEN\s*GOTO\s*([0-9]+)")
-        .unwrap();
+    /// `IF c(0) <comparison> <value> THEN GOTO <target>`, in any case, at the start of a line
+    pub(crate) static ref COND_JMP_PARSER: Regex = Regex::new(
+        r"(?i)^IF\s+c\(0\)\s*(<=|>=|<|>|=)\s*(\S+?)\s*THEN\s*GOTO\s*([0-9]+\b|[A-Za-z_][A-Za-z0-9_]*\b)"
+    ).unwrap();
+    /// The name of a label, register or constant: `loop`, `counter`, `BASE_2`
+    static ref NAME: Regex = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+    /// The path of `.include "lib.ram"`
+    static ref INCLUDE: Regex = Regex::new(r#"^"([^"]+)""#).unwrap();
+    /// A register written like in the instruction set's description: `c(i)` or `c(c(i))`
+    static ref REGISTER: Regex = Regex::new(r"^[cC]\(([cC]\()?([0-9]+)\)?\)$").unwrap();
 }
 
+/// All instructions (and `INIT`), for suggestions
+pub(crate) const INSTRUCTIONS: [&str; 22] = [
+    "INIT", "LOAD", "INDLOAD", "CLOAD", "STORE", "INDSTORE", "ADD", "INDADD", "CADD", "SUB", "INDSUB",
+    "CSUB", "MULT", "INDMULT", "CMULT", "DIV", "INDDIV", "CDIV", "GOTO", "JMP", "END", "IF",
+];
+
+/// All directives
+pub(crate) const DIRECTIVES: [&str; 5] = [".reg", ".const", ".macro", ".endm", ".include"];
+
+/// How deep macro expansions and includes can be nested
+const MAX_DEPTH: usize = 64;
+
+/// An example of a valid `IF`
+const IF_EXAMPLE: &str = "e.g. IF c(0) > 0 THEN GOTO 1";
+
+/// Parses an instruction whose argument is a register address
 macro_rules! gen_single_arg_instr {
-    ($isv:expr, $instr:ident, $argtype:ident, $tkiter:expr, $line:expr) => {
-        {
-            match $tkiter.next() {
-                    Some(s) => {
-                        match s.parse::<$argtype>() {
-                            Ok(arg) => $isv.push_instruction($instr::new(arg)),
-                            Err(err) => return Err(
-                                format!("Line {}: {} needs an argument of type {} [{}]", 
-                                    $line, 
-                                    stringify!($instr), 
-                                    stringify!($argtype),
-                                    err.to_string(),
-                                )
-                            ),
-                        }
-                    },
-                    None =>{
-                        return Err(format!("Line {}: {} needs an argument", $line, stringify!($instr)));
-                    },
-                }
-        }
+    ($line:expr, $symbols:expr, $instr:ident, $example:expr) => {
+        $line.register_operand(&stringify!($instr).to_uppercase(), $example, $symbols).map(|arg| Op::from($instr::new(arg)))
     };
 }
 
-pub fn parse_buf<R>(br: BufReader<R>) -> Result<RegisterMachine, String> where R: std::io::Read {
-    let mut isv = InstructionVec::new();
-    let mut initial_state = Vec::new();
+/// Parses an instruction whose argument is a constant of the machine's word type
+macro_rules! gen_constant_instr {
+    ($line:expr, $symbols:expr, $instr:ident, $example:expr) => {
+        $line.constant_operand(&stringify!($instr).to_uppercase(), $example, $symbols).map(|arg| Op::from($instr::new(arg)))
+    };
+}
 
-    let mut init_cmd = false;
-    let mut end_cmd = false;
+/// Where a jump goes: an instruction number or a label that is resolved once the whole
+/// program has been parsed
+enum Target {
+    Address(usize),
+    Label(String, Span),
+}
 
-    for (i, line) in br.lines().enumerate() {
+/// A jump whose target isn't known yet
+#[derive(Clone)]
+enum Jump<W: Word> {
+    Always,
+    If(Comparison, W),
+}
 
-        let current_line = line.unwrap();
-        let mut tokens = current_line.split_whitespace();
-        
-        let next_token = tokens.next();
-        if next_token.is_none() {
-            return Err(format!("Expected an instruction on line {}", i));
+impl<W: Word> Jump<W> {
+    fn to(self, address: usize) -> Op<W> {
+        match self {
+            Jump::Always => Op::from(Jmp::new(address)),
+            Jump::If(comp, value) => Op::from(CondJmp::new(comp, value, address)),
         }
+    }
+}
 
-        let instruction = next_token.unwrap().trim();
+/// A jump to a label, which replaces the placeholder at `pc` once the label is known
+struct Fixup<W: Word> {
+    pc: usize,
+    jump: Jump<W>,
+    label: String,
+    /// The error if the label isn't defined
+    error: ParseError,
+}
 
-        match instruction.trim().to_uppercase().as_str() {
-            "INIT" => if i == 0 {
-                    init_cmd = true;
-                    for tkn in tokens {
-                        match tkn.trim().parse::<u128>() {
-                            Ok(val) => initial_state.push(val),
-                            Err(u) => return Err(
-                                format!("INIT args must be numbers [{}]", u.to_string())
-                            )
-                        }
-                    }
-                } else {
-                    return Err("INIT called inside program".to_string())
-                },
+/// Where the lines that are being parsed come from: the program, an included file or a macro
+struct Context {
+    /// The included file (`None` for the program itself)
+    file: Option<String>,
+    /// The directory relative includes are resolved in
+    dir: PathBuf,
+    /// The line of the program the instructions are on in the source map: the `.include` or the
+    /// macro invocation (`None` for the program itself)
+    mapped: Option<Span>,
+    /// The macro invocation an expansion comes from
+    note: Option<String>,
+    /// The names replaced in the current line of an expansion
+    replacements: Vec<Replacement>,
+    lexer: Lexer,
+    /// Where the `/* */` comment that is still open starts
+    comment: Option<Span>,
+}
+
+/// The registers and constants the program has named with `.reg` and `.const`
+#[derive(Default)]
+struct Symbols<W: Word> {
+    registers: HashMap<String, usize>,
+    constants: HashMap<String, W>,
+    /// Where every name is defined
+    defined: HashMap<String, String>,
+}
+
+/// A line of the program, split into tokens
+struct Line<'a> {
+    number: usize,
+    text: &'a str,
+    tokens: std::str::SplitWhitespace<'a>,
+    /// The last token that belongs to the instruction (the rest is a comment)
+    last: &'a str,
+}
+
+impl<'a> Line<'a> {
+    /// Where `token` (a slice of the line) is
+    fn span(&self, token: &str) -> Span {
+        let start = column(self.text, token);
+        Span { line: self.number, start, end: start + token.chars().count() }
+    }
+
+    /// An error about `token`
+    fn error(&self, kind: ParseErrorKind, token: &str, message: String) -> ParseError {
+        ParseError::new(kind, Some(self.span(token)), message)
+    }
+
+    /// Returns the next token as the argument of `instruction`
+    fn argument(&mut self, instruction: &str, example: &str) -> Result<&'a str, Box<ParseError>> {
+        match self.tokens.next() {
+            Some(token) => {
+                self.last = token;
+                Ok(token)
+            },
+            None => Err(Box::new(
+                self.error(ParseErrorKind::MissingArgument, self.last, format!("{} needs an argument", instruction))
+                    .suggest(format!("e.g. {} {}", instruction.to_uppercase(), example))
+            )),
+        }
+    }
+
+    /// Parses the argument of an instruction whose argument is a register address: a register
+    /// name or a constant expression
+    fn register_operand<W: Word>(&mut self, instruction: &str, example: &str, symbols: &Symbols<W>) -> Result<usize, Box<ParseError>> {
+        let token = self.argument(instruction, example)?;
+        if let Some(&register) = symbols.registers.get(token) {
+            return Ok(register);
+        }
+
+        let error = match expression::evaluate(token, &symbols.constants) {
+            Ok(value) => match value.to_usize() {
+                Some(register) => return Ok(register),
+                None => self.error(
+                    ParseErrorKind::InvalidArgument,
+                    token,
+                    format!("{} needs a register number, {} isn't one", instruction, value),
+                ),
+            },
+            Err(ExpressionError::Undefined(name)) => self.undefined(token, &name, symbols),
+            Err(err) => self.error(
+                ParseErrorKind::InvalidArgument,
+                token,
+                format!("{} needs a register number or name [{}]", instruction, err),
+            ),
+        };
+
+        // `LOAD c(1)` and `LOAD c(c(1))`
+        Err(Box::new(match REGISTER.captures(token) {
+            Some(captures) if captures.get(1).is_some() =>
+                error.suggest(format!("did you mean IND{} {}?", instruction.to_uppercase(), &captures[2])),
+            Some(captures) => error.suggest(format!("did you mean {} {}?", instruction.to_uppercase(), &captures[2])),
+            None => error,
+        }))
+    }
+
+    /// Parses the argument of an instruction whose argument is a constant
+    fn constant_operand<W: Word>(&mut self, instruction: &str, example: &str, symbols: &Symbols<W>) -> Result<W, Box<ParseError>> {
+        let token = self.argument(instruction, example)?;
+        self.constant(token, instruction, symbols).map_err(|mut error| {
+            // `CLOAD c(1)` means `LOAD 1`, and `CLOAD counter` means `LOAD counter`
+            let register = match REGISTER.captures(token) {
+                Some(captures) if captures.get(1).is_none() => Some(captures[2].to_string()),
+                _ if symbols.registers.contains_key(token) => Some(token.to_string()),
+                _ => None,
+            };
+            if let Some(register) = register {
+                error.suggestion = Some(format!("did you mean {} {}?", instruction[1..].to_uppercase(), register));
+            }
+            error
+        })
+    }
+
+    /// Evaluates `token`, a constant expression `instruction` needs
+    fn constant<W: Word>(&self, token: &str, instruction: &str, symbols: &Symbols<W>) -> Result<W, Box<ParseError>> {
+        expression::evaluate(token, &symbols.constants).map_err(|err| Box::new(match err {
+            ExpressionError::Undefined(name) if symbols.registers.contains_key(&name) => self.error(
+                ParseErrorKind::InvalidArgument,
+                token,
+                format!("{} needs a constant, but {} is a register", instruction, name),
+            ),
+            ExpressionError::Undefined(name) => self.undefined(token, &name, symbols),
+            err => self.error(
+                ParseErrorKind::InvalidArgument,
+                token,
+                format!("{} needs an integer constant [{}]", instruction, err),
+            ),
+        }))
+    }
+
+    /// An error about `name` (in `token`), which isn't defined
+    fn undefined<W: Word>(&self, token: &str, name: &str, symbols: &Symbols<W>) -> ParseError {
+        let start = token.find(name).unwrap_or(0);
+        let error = self.error(ParseErrorKind::UndefinedName, &token[start..start + name.len()], format!("{} isn't defined", name));
+        match closest(name, symbols.defined.keys().map(String::as_str)) {
+            Some(closest) => error.suggest(format!("did you mean {}?", closest)),
+            None => error.suggest(format!("define it with .reg {} = <register> or .const {} = <value>", name, name)),
+        }
+    }
+
+    /// Parses a jump target, an instruction number or a label
+    fn target(&self, token: &str, instruction: &str) -> Result<Target, Box<ParseError>> {
+        match token.parse::<usize>() {
+            Ok(address) => Ok(Target::Address(address)),
+            Err(_) if NAME.is_match(token) => Ok(Target::Label(token.to_string(), self.span(token))),
+            Err(err) => Err(Box::new(self.error(
+                ParseErrorKind::InvalidArgument,
+                token,
+                format!("{} needs an instruction number or a label [{}]", instruction, err),
+            ))),
+        }
+    }
 
-            "LOAD"      => gen_single_arg_instr!(isv, Load,     usize,  tokens, i),
-            "INDLOAD"   => gen_single_arg_instr!(isv, IndLoad,  usize,  tokens, i),
-            "CLOAD"     => gen_single_arg_instr!(isv, CLoad,    u128,   tokens, i),
+    /// Parses an unconditional jump
+    fn jump<W: Word>(&mut self, instruction: &str) -> Result<(Jump<W>, Target), Box<ParseError>> {
+        let token = self.argument(instruction, "1")?;
+        Ok((Jump::Always, self.target(token, instruction)?))
+    }
+
+    /// An error about the first token after the operands, if there is one (comments have been
+    /// removed from the line already). `expression` tells whether the operand is an expression,
+    /// which may have been written with spaces.
+    fn unexpected(&mut self, instruction: &str, expression: bool) -> Option<ParseError> {
+        let token = self.tokens.next()?;
+        let start = column_offset(self.text, instruction);
+        let end = column_offset(self.text, self.last) + self.last.len();
+        let error = self.error(
+            ParseErrorKind::UnexpectedToken,
+            token,
+            format!("Unexpected {} after {}", token, &self.text[start..end]),
+        );
+
+        let operator = |c: char| "+-*/()".contains(c);
+        Some(match expression && (token.starts_with(operator) || self.last.ends_with(operator)) {
+            true => {
+                let operand: String = [self.last, token].into_iter().chain(self.tokens.by_ref()).collect();
+                error.suggest(format!("did you mean {}{}?", &self.text[start..column_offset(self.text, self.last)], operand))
+            },
+            false => error.suggest("comments start with ;, #, // or /*".to_string()),
+        })
+    }
+
+    /// Parses a conditional jump
+    fn condition<W: Word>(&mut self, instruction: &'a str, symbols: &Symbols<W>) -> Result<(Jump<W>, Target), Box<ParseError>> {
+        // The rest of the line, which can't be told apart from a comment if it's invalid
+        let rest = self.text[column_offset(self.text, instruction)..].trim_end();
 
-            "STORE"     => gen_single_arg_instr!(isv, Store,    usize,  tokens, i),
-            "INDSTORE"  => gen_single_arg_instr!(isv, IndStore, usize,  tokens, i),
+        let captures = match COND_JMP_PARSER.captures(rest) {
+            Some(captures) => captures,
+            None => {
+                let error = self.error(ParseErrorKind::InvalidCondition, rest, "IF statement invalid".to_string());
+                let upper = rest.to_uppercase();
+                return Err(Box::new(match () {
+                    _ if !upper.contains("C(0)") => error.suggest(format!("IF needs c(0), {}", IF_EXAMPLE)),
+                    _ if !upper.contains("THEN") => error.suggest(format!("IF needs THEN GOTO <line>, {}", IF_EXAMPLE)),
+                    _ if !upper.contains("GOTO") => error.suggest(format!("IF needs GOTO after THEN, {}", IF_EXAMPLE)),
+                    _ => error.suggest(format!("IF compares c(0) with <, <=, =, >= or > to a number, {}", IF_EXAMPLE)),
+                }));
+            },
+        };
 
-            "ADD"       => gen_single_arg_instr!(isv, Add,      usize,  tokens, i),
-            "INDADD"    => gen_single_arg_instr!(isv, IndAdd,   usize,  tokens, i),
-            "CADD"      => gen_single_arg_instr!(isv, CAdd,     u128,   tokens, i),
+        let whole = captures.get(0).unwrap();
+        self.last = &rest[whole.range()];
+        self.tokens = rest[whole.end()..].split_whitespace();
 
-            "SUB"       => gen_single_arg_instr!(isv, Sub,      usize,  tokens, i),
-            "INDSUB"    => gen_single_arg_instr!(isv, IndSub,   usize,  tokens, i),
-            "CSUB"      => gen_single_arg_instr!(isv, CSub,     u128,   tokens, i),
+        let comp = Comparison::str_to_comp(&captures[1]);
 
-            "MULT"      => gen_single_arg_instr!(isv, Mult,     usize,  tokens, i),
-            "INDMULT"   => gen_single_arg_instr!(isv, IndMult,  usize,  tokens, i),
-            "CMULT"     => gen_single_arg_instr!(isv, CMult,    u128,   tokens, i),
+        let value = self.constant(&rest[captures.get(2).unwrap().range()], "IF", symbols)?;
 
-            "DIV"       => gen_single_arg_instr!(isv, Div,      usize,  tokens, i),
-            "INDDIV"    => gen_single_arg_instr!(isv, IndDiv,   usize,  tokens, i),
-            "CDIV"      => gen_single_arg_instr!(isv, CDiv,     u128,   tokens, i),
+        let target = self.target(&rest[captures.get(3).unwrap().range()], "IF")?;
+
+        Ok((Jump::If(comp, value), target))
+    }
+}
+
+/// Collects the program while the lines get parsed
+struct Parser<W: Word> {
+    isv: InstructionVec<W>,
+    initial_state: Vec<W>,
+    source_map: SourceMap,
+    /// The instruction every label is on (as a 1-based jump target) and where it's defined
+    labels: HashMap<String, (usize, String)>,
+    fixups: Vec<Fixup<W>>,
+    symbols: Symbols<W>,
+    macros: HashMap<String, Macro>,
+    /// The macro whose body is being read, and whether its definition is valid
+    recording: Option<(Macro, bool)>,
+    expansions: usize,
+    /// The program and the included files being parsed (the innermost last)
+    contexts: Vec<Context>,
+    /// The canonical paths (and names) of the files being parsed, to detect include cycles
+    including: Vec<(PathBuf, String)>,
+    sources: HashMap<String, String>,
+    /// The number of errors and fixups whose spans have been mapped to their files
+    located: (usize, usize),
+    /// The first line of the program that isn't blank or a comment, which has to be `INIT`
+    first: Option<usize>,
+    init_cmd: bool,
+    end_cmd: bool,
+    errors: Vec<ParseError>,
+}
+
+impl<W: Word> Parser<W> {
+    fn new(dir: PathBuf) -> Parser<W> {
+        Parser {
+            isv: InstructionVec::new(),
+            initial_state: Vec::new(),
+            source_map: SourceMap::new(),
+            labels: HashMap::new(),
+            fixups: Vec::new(),
+            symbols: Symbols::default(),
+            macros: HashMap::new(),
+            recording: None,
+            expansions: 0,
+            contexts: vec![Context { file: None, dir, mapped: None, note: None, replacements: Vec::new(), lexer: Lexer::new(), comment: None }],
+            including: Vec::new(),
+            sources: HashMap::new(),
+            located: (0, 0),
+            first: None,
+            init_cmd: false,
+            end_cmd: false,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Parses a line of a file and maps the spans of its errors to the file
+    fn parse_located(&mut self, number: usize, text: &str) {
+        let code = self.lex(number, text);
+        self.parse_line(number, &code);
+        self.locate();
+    }
+
+    /// Removes the comments from a line of the current file
+    fn lex(&mut self, number: usize, text: &str) -> String {
+        let context = self.contexts.last_mut().unwrap();
+        let open = context.lexer.in_comment();
+        let tokens = context.lexer.tokens(text);
+
+        if !context.lexer.in_comment() {
+            context.comment = None;
+        } else if !open {
+            // A comment that is still open is the line's last token
+            let start = column(text, tokens.last().unwrap().text);
+            context.comment = Some(Span { line: number, start, end: start + 2 });
+        }
+
+        lexer::code(text, &tokens)
+    }
+
+    /// Maps the spans of the errors found since the last call to the file they're in (and back
+    /// through a macro's parameters)
+    fn locate(&mut self) {
+        let context = self.contexts.last().unwrap();
+        let (errors, fixups) = self.located;
+        let new = self.errors[errors..].iter_mut().chain(self.fixups[fixups..].iter_mut().map(|fixup| &mut fixup.error));
+
+        for error in new {
+            error.file = context.file.clone();
+            error.span = error.span.map(|span| macros::unexpand(span, &context.replacements));
+            if error.note.is_none() {
+                error.note = context.note.clone();
+            }
+        }
+
+        self.located = (self.errors.len(), self.fixups.len());
+    }
+
+    /// Describes where line `number` of the current context is, e.g. `line 3 of lib.ram`
+    fn describe(&self, number: usize) -> String {
+        match &self.contexts.last().unwrap().file {
+            Some(file) => format!("line {} of {}", number, file),
+            None => format!("line {}", number),
+        }
+    }
+
+    /// Checks whether the lines being parsed are the program's own
+    fn in_program(&self) -> bool {
+        self.contexts.len() == 1
+    }
+
+    /// Parses a line without comments. Errors are collected, so the next line can be parsed anyway.
+    fn parse_line(&mut self, number: usize, text: &str) {
+        if self.record(number, text) {
+            return;
+        }
+
+        let mut tokens = text.split_whitespace();
+        // Blank lines (and comments) don't count, not even as the first line
+        let mut instruction = match tokens.next() {
+            Some(token) => token,
+            None => return,
+        };
+
+        if self.in_program() && self.first.is_none() {
+            self.first = Some(number);
+        }
+        if self.first == Some(number) && self.in_program() && !instruction.eq_ignore_ascii_case("INIT") {
+            let start = column(text, instruction);
+            let span = Span { line: number, start, end: start + instruction.chars().count() };
+            self.errors.push(
+                ParseError::new(ParseErrorKind::MissingInit, Some(span), "The program must start with INIT, even if it's empty".to_string())
+                    .suggest("add a line with INIT before it".to_string())
+            );
+        }
+
+        // `loop:` labels the instruction after it, on the same line or on the next ones
+        if let Some(label) = instruction.strip_suffix(':') {
+            self.define(number, text, label);
+            instruction = match tokens.next() {
+                Some(token) => token,
+                None => return,
+            };
+        }
+
+        let mut line = Line { number, text, tokens, last: instruction };
+
+        if self.macros.contains_key(instruction) {
+            self.expand(&mut line, instruction);
+            return;
+        }
+
+        let symbols = &self.symbols;
+        let name = instruction.to_uppercase();
+        let op = match name.as_str() {
+            "INIT" => {
+                self.init(&mut line, instruction);
+                return;
+            },
+
+            directive if directive.starts_with('.') => {
+                self.directive(&mut line, instruction);
+                return;
+            },
+
+            "LOAD"      => gen_single_arg_instr!(line, symbols, Load,     "1"),
+            "INDLOAD"   => gen_single_arg_instr!(line, symbols, IndLoad,  "1"),
+            "CLOAD"     => gen_constant_instr!(line, symbols, CLoad,      "1"),
+
+            "STORE"     => gen_single_arg_instr!(line, symbols, Store,    "1"),
+            "INDSTORE"  => gen_single_arg_instr!(line, symbols, IndStore, "1"),
+
+            "ADD"       => gen_single_arg_instr!(line, symbols, Add,      "1"),
+            "INDADD"    => gen_single_arg_instr!(line, symbols, IndAdd,   "1"),
+            "CADD"      => gen_constant_instr!(line, symbols, CAdd,       "1"),
+
+            "SUB"       => gen_single_arg_instr!(line, symbols, Sub,      "1"),
+            "INDSUB"    => gen_single_arg_instr!(line, symbols, IndSub,   "1"),
+            "CSUB"      => gen_constant_instr!(line, symbols, CSub,       "1"),
+
+            "MULT"      => gen_single_arg_instr!(line, symbols, Mult,     "1"),
+            "INDMULT"   => gen_single_arg_instr!(line, symbols, IndMult,  "1"),
+            "CMULT"     => gen_constant_instr!(line, symbols, CMult,      "1"),
+
+            "DIV"       => gen_single_arg_instr!(line, symbols, Div,      "1"),
+            "INDDIV"    => gen_single_arg_instr!(line, symbols, IndDiv,   "1"),
+            "CDIV"      => gen_constant_instr!(line, symbols, CDiv,       "1"),
 
             "GOTO" |
-            "JMP" => gen_single_arg_instr!(isv, Jmp, usize, tokens, i),
+            "JMP" => line.jump(instruction).map(|(jump, target)| self.jump(jump, target)),
 
             "END" => {
-                end_cmd = true;
-                isv.push_instruction(
-                    End::new()
-                )
-            },
-
-            "IF" => {
-                let tokens: Captures;
-                match COND_JMP_PARSER.captures(&current_line) { 
-                    Some(val) => tokens = val,
-                    None => return Err(
-                        format!("Line {}: IF statement invalid", i)
-                    )
-                };
-
-                let comp = Comparison::str_to_comp(&tokens[1]);
-                let value: u128;
-                match tokens[2].parse::<u128>() {
-                    Ok(val) => value = val,
-                    Err(u) => return Err(
-                        format!("Line {}: IF condition must have an integer value [{}]", i, u.to_string())
-                    )
-                };
-
-                let addr: usize;
-                match tokens[3].parse::<usize>() {
-                    Ok(val) => addr = val,
-                    Err(u) => return Err(
-                        format!("Line {}: IF condition must have an integer goto address [{}]", i, u.to_string())
-                    )
-                };
-
-                isv.push_instruction(
-                    CondJmp::new(comp, value, addr)
-                )
+                self.end_cmd = true;
+                Ok(Op::from(End::new()))
+            },
+
+            "IF" => line.condition(instruction, symbols).map(|(jump, target)| self.jump(jump, target)),
+
+            _ => {
+                let error = line.error(
+                    ParseErrorKind::UnknownInstruction,
+                    instruction,
+                    format!("Unknown instruction {}", instruction),
+                );
+                Err(Box::new(match suggest_instruction(instruction) {
+                    Some(suggestion) => error.suggest(format!("did you mean {}?", suggestion)),
+                    None => error,
+                }))
+            },
+        };
+
+        match op {
+            Ok(op) => {
+                let span = line.span(instruction);
+                let end = column(text, line.last) + line.last.chars().count();
+                self.isv.push_instruction(op);
+                self.source_map.push(self.contexts.last().unwrap().mapped.unwrap_or(Span { end, ..span }));
+
+                // Jump targets and IF's operands can't be expressions
+                let expression = !matches!(name.as_str(), "GOTO" | "JMP" | "END" | "IF");
+                if let Some(error) = line.unexpected(instruction, expression) {
+                    self.errors.push(error);
+                }
+            },
+            Err(error) => self.errors.push(*error),
+        }
+    }
+
+    /// Defines `label` (without its colon) as the next instruction
+    fn define(&mut self, number: usize, text: &str, label: &str) {
+        let start = column(text, label);
+        let span = Span { line: number, start, end: start + label.chars().count() + 1 };
+
+        if !NAME.is_match(label) {
+            self.errors.push(
+                ParseError::new(ParseErrorKind::InvalidLabel, Some(span), format!("Invalid label {}:", label))
+                    .suggest("labels start with a letter or _, followed by letters, digits or _".to_string())
+            );
+            return;
+        }
+
+        match self.labels.get(label) {
+            Some((_, first)) => self.errors.push(
+                ParseError::new(ParseErrorKind::DuplicateLabel, Some(span), format!("Label {} is defined more than once", label))
+                    .suggest(format!("it's first defined on {}", first))
+            ),
+            None => {
+                let defined = self.describe(number);
+                self.labels.insert(label.to_string(), (self.isv.instructions.len() + 1, defined));
+            },
+        }
+    }
+
+    /// Builds a jump, with a placeholder target if it's a label
+    fn jump(&mut self, jump: Jump<W>, target: Target) -> Op<W> {
+        match target {
+            Target::Address(address) => jump.to(address),
+            Target::Label(label, span) => {
+                let pc = self.isv.instructions.len();
+                let error = ParseError::new(ParseErrorKind::UndefinedLabel, Some(span), format!("Label {} isn't defined", label));
+                self.fixups.push(Fixup { pc, jump: jump.clone(), label, error });
+                jump.to(0)
+            },
+        }
+    }
+
+    /// Replaces the placeholders of jumps to labels with their targets
+    fn resolve(&mut self) {
+        for fixup in std::mem::take(&mut self.fixups) {
+            match self.labels.get(&fixup.label) {
+                Some(&(address, _)) => self.isv.instructions[fixup.pc] = fixup.jump.to(address),
+                None => {
+                    let error = fixup.error;
+                    self.errors.push(match closest(&fixup.label, self.labels.keys().map(String::as_str)) {
+                        Some(label) => error.suggest(format!("did you mean {}?", label)),
+                        None => error.suggest(format!("define it with {}: before an instruction", fixup.label)),
+                    });
+                },
             }
+        }
+    }
+
+    /// Parses a directive: `.reg counter = 2` names register 2 `counter`, `.const BASE = 10`
+    /// names the constant 10 `BASE`
+    fn directive(&mut self, line: &mut Line, directive: &str) {
+        let example = match directive.to_lowercase().as_str() {
+            ".reg" => ".reg counter = 2",
+            ".const" => ".const BASE = 10",
+            ".macro" => return self.define_macro(line, directive),
+            ".include" => return self.include(line, directive),
+            ".endm" => {
+                self.errors.push(line.error(ParseErrorKind::InvalidMacro, directive, ".endm without .macro".to_string()));
+                return;
+            },
+            _ => {
+                self.errors.push(
+                    line.error(ParseErrorKind::InvalidDirective, directive, format!("Unknown directive {}", directive))
+                        .suggest("the directives are .reg, .const, .macro and .include".to_string())
+                );
+                return;
+            },
+        };
+
+        let (name, value) = match (line.tokens.next(), line.tokens.next(), line.tokens.next()) {
+            (Some(name), Some("="), Some(value)) => {
+                line.last = value;
+                (name, value)
+            },
+            _ => {
+                self.errors.push(
+                    line.error(ParseErrorKind::InvalidDirective, directive, format!("{} needs a name and a value", directive))
+                        .suggest(format!("e.g. {}", example))
+                );
+                return;
+            },
+        };
+
+        if !NAME.is_match(name) {
+            self.errors.push(
+                line.error(ParseErrorKind::InvalidDirective, name, format!("Invalid name {}", name))
+                    .suggest("names start with a letter or _, followed by letters, digits or _".to_string())
+            );
+            return;
+        }
+
+        if let Some(first) = self.symbols.defined.get(name) {
+            self.errors.push(
+                line.error(ParseErrorKind::DuplicateName, name, format!("{} is defined more than once", name))
+                    .suggest(format!("it's first defined on {}", first))
+            );
+            return;
+        }
+
+        let value = match line.constant(value, directive, &self.symbols) {
+            Ok(value) => value,
+            Err(error) => {
+                self.errors.push(*error);
+                return;
+            },
+        };
+        if let Some(error) = line.unexpected(directive, true) {
+            self.errors.push(error);
+            return;
+        }
+
+        if directive.eq_ignore_ascii_case(".reg") {
+            let register = match value.to_usize() {
+                Some(register) => register,
+                None => {
+                    self.errors.push(line.error(
+                        ParseErrorKind::InvalidArgument,
+                        name,
+                        format!("{} isn't a register number", value),
+                    ));
+                    return;
+                },
+            };
+            self.symbols.registers.insert(name.to_string(), register);
+            self.source_map.name_register(register, name);
+        } else {
+            self.symbols.constants.insert(name.to_string(), value);
+        }
+        let defined = self.describe(line.number);
+        self.symbols.defined.insert(name.to_string(), defined);
+    }
+
+    /// Starts reading the body of a macro: `.macro swap a b`
+    fn define_macro(&mut self, line: &mut Line, directive: &str) {
+        let name = match line.tokens.next() {
+            Some(name) => name,
+            None => {
+                self.errors.push(
+                    line.error(ParseErrorKind::InvalidMacro, directive, ".macro needs a name".to_string())
+                        .suggest("e.g. .macro swap a b".to_string())
+                );
+                return;
+            },
+        };
+
+        let error = match () {
+            _ if !NAME.is_match(name) => Some((ParseErrorKind::InvalidMacro, format!("Invalid macro name {}", name))),
+            _ if INSTRUCTIONS.iter().any(|instruction| instruction.eq_ignore_ascii_case(name)) =>
+                Some((ParseErrorKind::InvalidMacro, format!("{} is an instruction, it can't be a macro", name))),
+            _ if self.macros.contains_key(name) => Some((ParseErrorKind::DuplicateName, format!("Macro {} is defined more than once", name))),
+            _ => None,
+        };
+        if let Some((kind, message)) = &error {
+            self.errors.push(line.error(*kind, name, message.clone()));
+        }
+
+        // The parameters are the names after it
+        let mut params = Vec::new();
+        for param in line.tokens.by_ref().collect::<Vec<&str>>() {
+            match () {
+                _ if !NAME.is_match(param) => self.errors.push(
+                    line.error(ParseErrorKind::InvalidMacro, param, format!("Invalid parameter name {}", param))
+                        .suggest("names start with a letter or _, followed by letters, digits or _".to_string())
+                ),
+                // They'd be replaced in `IF c(0) = 0 THEN GOTO 1`
+                _ if ["c", "THEN", "GOTO"].iter().any(|word| word.eq_ignore_ascii_case(param)) => self.errors.push(
+                    line.error(ParseErrorKind::InvalidMacro, param, format!("{} is part of IF, it can't be a parameter", param))
+                ),
+                _ => params.push(param.to_string()),
+            }
+        }
+
+        // The body is read (and thrown away) even if the definition is invalid
+        let context = self.contexts.last().unwrap();
+        let definition = Macro::new(name, params, context.file.clone(), context.dir.clone(), line.number);
+        self.recording = Some((definition, error.is_none()));
+    }
+
+    /// Adds a line to the body of the macro that is being defined, if there is one.
+    /// Returns whether the line belongs to a macro.
+    fn record(&mut self, number: usize, text: &str) -> bool {
+        let recording = match &mut self.recording {
+            Some((recording, _)) => recording,
+            None => return false,
+        };
+
+        match text.split_whitespace().next() {
+            Some(token) if token.eq_ignore_ascii_case(".endm") => {
+                if let Some((definition, true)) = self.recording.take() {
+                    self.macros.insert(definition.name.clone(), definition);
+                }
+            },
+            Some(token) if token.eq_ignore_ascii_case(".macro") => {
+                let start = column(text, token);
+                let span = Span { line: number, start, end: start + token.len() };
+                self.errors.push(
+                    ParseError::new(ParseErrorKind::InvalidMacro, Some(span), "Macros can't be defined inside macros".to_string())
+                        .suggest(format!("end {} with .endm first", recording.name))
+                );
+            },
+            _ => recording.body.push((number, text.to_string())),
+        }
+        true
+    }
+
+    /// Ends a file: comments and a macro that is being defined must end in the same file
+    fn end_of_file(&mut self) {
+        if let Some(span) = self.contexts.last_mut().unwrap().comment.take() {
+            self.errors.push(
+                ParseError::new(ParseErrorKind::UnterminatedComment, Some(span), "Comment isn't closed".to_string())
+                    .suggest("close it with */".to_string())
+            );
+            self.locate();
+        }
+        if let Some((recording, _)) = self.recording.take() {
+            let span = Span { line: recording.line, start: 1, end: ".macro".len() + 1 };
+            self.errors.push(
+                ParseError::new(ParseErrorKind::InvalidMacro, Some(span), format!("Macro {} has no .endm", recording.name))
+                    .suggest("add .endm after its last line".to_string())
+            );
+            self.locate();
+        }
+    }
+
+    /// Expands an invocation of the macro `name`
+    fn expand<'a>(&mut self, line: &mut Line<'a>, name: &'a str) {
+        let definition = self.macros[name].clone();
+
+        let args: Vec<&str> = line.tokens.by_ref().take(definition.params.len()).collect();
+        if args.len() < definition.params.len() {
+            self.errors.push(
+                line.error(
+                    ParseErrorKind::InvalidMacro,
+                    name,
+                    format!(
+                        "{} needs {} argument{}, but it got {}",
+                        name,
+                        definition.params.len(),
+                        if definition.params.len() == 1 { "" } else { "s" },
+                        args.len(),
+                    ),
+                ).suggest(format!("e.g. {} {}", name, definition.params.join(" ")))
+            );
+            return;
+        }
+
+        line.last = args.last().copied().unwrap_or(name);
+        if let Some(error) = line.unexpected(name, false) {
+            self.errors.push(error.suggest(format!("e.g. {} {}", name, definition.params.join(" "))));
+            return;
+        }
+
+        if self.contexts.len() > MAX_DEPTH {
+            self.errors.push(line.error(
+                ParseErrorKind::InvalidMacro,
+                name,
+                format!("Macros are nested too deeply, does {} invoke itself?", name),
+            ));
+            return;
+        }
+
+        let end = column(line.text, line.last) + line.last.chars().count();
+        let outer = self.contexts.last().unwrap();
+        let context = Context {
+            file: definition.file.clone(),
+            dir: definition.dir.clone(),
+            mapped: outer.mapped.or(Some(Span { end, ..line.span(name) })),
+            note: Some(format!("in the expansion of {} on {}", name, self.describe(line.number))),
+            replacements: Vec::new(),
+            lexer: Lexer::new(),
+            comment: None,
+        };
+
+        self.expansions += 1;
+        let names = definition.names(&args, self.expansions);
+
+        self.locate();
+        self.contexts.push(context);
+        for (number, text) in &definition.body {
+            let (expanded, replacements) = macros::substitute(text, &names);
+            self.contexts.last_mut().unwrap().replacements = replacements;
+            // The body has been lexed while it was defined
+            self.parse_line(*number, &expanded);
+            self.locate();
+        }
+        self.contexts.pop();
+    }
+
+    /// Parses an included file: `.include "lib.ram"`. Its path is relative to the including file.
+    fn include(&mut self, line: &mut Line, directive: &str) {
+        let rest = line.text[column_offset(line.text, directive) + directive.len()..].trim_start();
+        let (quoted, path) = match INCLUDE.captures(rest) {
+            Some(captures) => (&rest[captures.get(0).unwrap().range()], captures[1].to_string()),
+            None => {
+                self.errors.push(
+                    line.error(ParseErrorKind::InvalidInclude, directive, ".include needs a path in quotes".to_string())
+                        .suggest("e.g. .include \"lib.ram\"".to_string())
+                );
+                return;
+            },
+        };
+
+        line.last = quoted;
+        line.tokens = rest[quoted.len()..].split_whitespace();
+        if let Some(error) = line.unexpected(directive, false) {
+            self.errors.push(error);
+            return;
+        }
+
+        let outer = self.contexts.last().unwrap();
+        let path = outer.dir.join(path);
+        let name = path.display().to_string();
+
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                self.errors.push(line.error(ParseErrorKind::InvalidInclude, quoted, format!("Could not include {} [{}]", name, err)));
+                return;
+            },
+        };
+
+        let canonical = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if let Some(n) = self.including.iter().position(|(file, _)| *file == canonical) {
+            let cycle: Vec<&str> = self.including[n..].iter()
+                .map(|(_, name)| name.as_str())
+                .chain(std::iter::once(name.as_str()))
+                .collect();
+            self.errors.push(
+                line.error(ParseErrorKind::IncludeCycle, quoted, format!("{} includes itself", name))
+                    .suggest(cycle.join(" -> "))
+            );
+            return;
+        }
+
+        if self.contexts.len() > MAX_DEPTH {
+            self.errors.push(line.error(ParseErrorKind::InvalidInclude, quoted, "Includes are nested too deeply".to_string()));
+            return;
+        }
 
-            _ => return Err(format!("Line {}: Unknown instruction {}", i, instruction)),
+        let context = Context {
+            file: Some(name.clone()),
+            dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            mapped: outer.mapped.or(Some(Span { end: column(line.text, quoted) + quoted.chars().count(), ..line.span(directive) })),
+            note: outer.note.clone(),
+            replacements: Vec::new(),
+            lexer: Lexer::new(),
+            comment: None,
         };
+
+        self.locate();
+        self.contexts.push(context);
+        self.including.push((canonical, name.clone()));
+        for (i, text) in source.lines().enumerate() {
+            self.parse_located(i + 1, text);
+        }
+        self.end_of_file();
+        self.including.pop();
+        self.contexts.pop();
+        self.sources.insert(name, source);
     }
 
-    if !init_cmd {
-        return Err("The program must start with INIT, even if it's empty".to_string())
+    /// Parses the initial values of the registers
+    fn init(&mut self, line: &mut Line, instruction: &str) {
+        if self.first != Some(line.number) || !self.in_program() {
+            self.errors.push(
+                line.error(ParseErrorKind::MisplacedInit, instruction, "INIT called inside program".to_string())
+                    .suggest("INIT can only be on the first line that isn't blank or a comment".to_string())
+            );
+            return;
+        }
+
+        self.init_cmd = true;
+        for token in line.tokens.by_ref() {
+            match expression::evaluate(token, &self.symbols.constants) {
+                Ok(val) => self.initial_state.push(val),
+                Err(u) => {
+                    let error = line.error(ParseErrorKind::InvalidArgument, token, format!("INIT args must be numbers [{}]", u));
+                    let number = token.trim_start_matches('-').starts_with(|c: char| c.is_ascii_digit());
+                    self.errors.push(match number {
+                        true => error,
+                        false => error.suggest("comments start with ;, #, // or /*".to_string()),
+                    });
+                    return;
+                },
+            }
+        }
     }
-    if !end_cmd {
-        return Err("The program must have an END command somewhere".to_string())
+
+    /// Checks the program as a whole, once all `lines` lines of `last_line` have been parsed
+    fn finish(mut self, lines: usize, last_line: &str) -> Result<RegisterMachine<W>, ParseErrors> {
+        self.end_of_file();
+        self.resolve();
+
+        if self.first.is_none() {
+            self.errors.push(
+                ParseError::new(ParseErrorKind::MissingInit, None, "The program must start with INIT, even if it's empty".to_string())
+                    .suggest("add a line with INIT and one with END".to_string())
+            );
+        } else if !self.end_cmd {
+            let end = last_line.chars().count() + 1;
+            let span = Span { line: lines.max(1), start: end, end };
+            self.errors.push(
+                ParseError::new(ParseErrorKind::MissingEnd, Some(span), "The program must have an END command somewhere".to_string())
+                    .suggest("add END after the last instruction".to_string())
+            );
+        }
+
+        if !self.errors.is_empty() {
+            // Undefined labels are only found at the end. The program's errors come first.
+            self.errors.sort_by(|a, b| {
+                (a.file.is_some(), &a.file, a.span.map(|span| (span.line, span.start)))
+                    .cmp(&(b.file.is_some(), &b.file, b.span.map(|span| (span.line, span.start))))
+            });
+            return Err(ParseErrors { errors: self.errors, sources: self.sources });
+        }
+
+        let mut res = RegisterMachine::new(self.isv);
+        res.push_vec(self.initial_state);
+        res.set_source_map(Some(self.source_map));
+        Ok(res)
     }
+}
+
+/// Parses a program into a machine that uses the default word type
+pub fn parse_buf<R>(br: BufReader<R>) -> Result<RegisterMachine, ParseErrors> where R: std::io::Read {
+    parse_buf_as::<Value, R>(br)
+}
+
+/// Parses a program into a machine whose registers hold values of type `W`.
+/// Returns all errors in the program if there are any. Includes are relative to the current directory.
+pub fn parse_buf_as<W, R>(br: BufReader<R>) -> Result<RegisterMachine<W>, ParseErrors>
+    where W: Word, R: std::io::Read {
+    parse_reader(br, Parser::new(PathBuf::new()))
+}
+
+/// Parses the lines of the program with `parser`
+fn parse_reader<W, R>(br: BufReader<R>, mut parser: Parser<W>) -> Result<RegisterMachine<W>, ParseErrors>
+    where W: Word, R: std::io::Read {
+    let mut lines = 0;
+    let mut last_line = String::new();
+
+    for (i, current_line) in br.lines().enumerate() {
+        // Lines are reported 1-based, like editors count them
+        let number = i + 1;
+        lines = number;
+
+        match current_line {
+            Ok(text) => {
+                parser.parse_located(number, &text);
+                last_line = text;
+            },
+            Err(err) => {
+                parser.errors.push(ParseError::new(
+                    ParseErrorKind::Io,
+                    Some(Span { line: number, start: 1, end: 1 }),
+                    format!("Could not read the line [{}]", err),
+                ));
+                last_line.clear();
+            },
+        }
+    }
+
+    parser.finish(lines, &last_line)
+}
 
-    let mut res = RegisterMachine::new(isv);
-    res.push_vec(initial_state);
-    Ok(res)
+/// Parses a program file into a machine that uses the default word type
+pub fn parse_file(path: PathBuf) -> Result<RegisterMachine, ParseErrors> {
+    parse_file_as::<Value>(path)
 }
 
-pub fn parse_file(path: PathBuf) -> Result<RegisterMachine, String> {
-    match File::open(path) {
+/// Parses a program file into a machine whose registers hold values of type `W`.
+/// Includes are relative to the file's directory.
+pub fn parse_file_as<W: Word>(path: PathBuf) -> Result<RegisterMachine<W>, ParseErrors> {
+    match File::open(&path) {
         Ok(f) => {
             let br = BufReader::new(f);
-            parse_buf(br)
+            let mut parser = Parser::new(path.parent().map(Path::to_path_buf).unwrap_or_default());
+            let name = path.display().to_string();
+            parser.including.push((std::fs::canonicalize(&path).unwrap_or(path), name));
+            parse_reader(br, parser)
         },
         Err(u) => {
-            Err(u.to_string())
+            let errors = vec![ParseError::new(ParseErrorKind::Io, None, u.to_string())];
+            Err(ParseErrors { errors, ..ParseErrors::default() })
         }
     }
 }
+
+/// The instruction `word` is most likely a misspelling of, if any
+fn suggest_instruction(word: &str) -> Option<&'static str> {
+    closest(&word.to_uppercase(), INSTRUCTIONS.iter().copied())
+}
+
+/// The candidate `word` is most likely a misspelling of, if any
+fn closest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (distance(word, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= 2 && distance < candidate.len())
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// The byte offset of `token`, a slice of `line`
+fn column_offset(line: &str, token: &str) -> usize {
+    token.as_ptr() as usize - line.as_ptr() as usize
+}
+
+/// The (1-based) column at which `token`, a slice of `line`, starts
+fn column(line: &str, token: &str) -> usize {
+    line[..column_offset(line, token)].chars().count() + 1
+}
@@ -0,0 +1,156 @@
+//! Constant expressions in programs: `BASE*2`, `(0x10+1)/2`, `-0b101`.
+//! They're evaluated in the machine's word type, so they fail where the machine would overflow.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::word::Word;
+
+/// Why an expression couldn't be evaluated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ExpressionError {
+    /// The expression uses a constant that isn't defined
+    Undefined(String),
+    /// Anything else
+    Invalid(String),
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpressionError::Undefined(name) => write!(f, "{} isn't defined", name),
+            ExpressionError::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Evaluates `expression`, whose names refer to `constants`
+pub(crate) fn evaluate<W: Word>(expression: &str, constants: &HashMap<String, W>) -> Result<W, ExpressionError> {
+    let mut evaluator = Evaluator { chars: expression.chars().peekable(), constants };
+    let value = evaluator.sum()?;
+    match evaluator.chars.next() {
+        None => Ok(value),
+        Some(c) => Err(ExpressionError::Invalid(format!("unexpected '{}'", c))),
+    }
+}
+
+/// Recursive descent over `sum := product (('+' | '-') product)*`,
+/// `product := factor (('*' | '/') factor)*` and `factor := '-' factor | '(' sum ')' | number | name`
+struct Evaluator<'a, W: Word> {
+    chars: Peekable<Chars<'a>>,
+    constants: &'a HashMap<String, W>,
+}
+
+impl<'a, W: Word> Evaluator<'a, W> {
+    fn sum(&mut self) -> Result<W, ExpressionError> {
+        let mut value = self.product()?;
+        loop {
+            value = match self.chars.peek() {
+                Some('+') => { self.chars.next(); apply(value.checked_add(&self.product()?), "overflows")? },
+                Some('-') => { self.chars.next(); apply(value.checked_sub(&self.product()?), "is out of range")? },
+                _ => return Ok(value),
+            };
+        }
+    }
+
+    fn product(&mut self) -> Result<W, ExpressionError> {
+        let mut value = self.factor()?;
+        loop {
+            value = match self.chars.peek() {
+                Some('*') => { self.chars.next(); apply(value.checked_mul(&self.factor()?), "overflows")? },
+                Some('/') => { self.chars.next(); apply(value.checked_div(&self.factor()?), "divides by zero")? },
+                _ => return Ok(value),
+            };
+        }
+    }
+
+    fn factor(&mut self) -> Result<W, ExpressionError> {
+        match self.chars.peek().copied() {
+            Some('-') => {
+                self.chars.next();
+                if self.chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    return self.number(true);
+                }
+                let value = self.factor()?;
+                apply(W::zero().checked_sub(&value), "is out of range")
+            },
+            Some('(') => {
+                self.chars.next();
+                let value = self.sum()?;
+                match self.chars.next() {
+                    Some(')') => Ok(value),
+                    _ => Err(ExpressionError::Invalid("missing ')'".to_string())),
+                }
+            },
+            Some(c) if c.is_ascii_digit() => self.number(false),
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                match self.constants.get(&name) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(ExpressionError::Undefined(name)),
+                }
+            },
+            Some(c) => Err(ExpressionError::Invalid(format!("unexpected '{}'", c))),
+            None => Err(ExpressionError::Invalid("expected a number".to_string())),
+        }
+    }
+
+    /// A decimal, hexadecimal (`0x1f`) or binary (`0b101`) literal
+    fn number(&mut self, negative: bool) -> Result<W, ExpressionError> {
+        let literal = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        if negative {
+            // Keeps the most negative value of a signed word in range
+            if let Ok(value) = W::parse_word(&format!("-{}", literal.replace('_', ""))) {
+                return Ok(value);
+            }
+        }
+
+        let value = parse_literal(&literal).map_err(ExpressionError::Invalid)?;
+        match negative {
+            true => apply(W::zero().checked_sub(&value), "is out of range"),
+            false => Ok(value),
+        }
+    }
+
+    fn take_while(&mut self, predicate: fn(char) -> bool) -> String {
+        let mut res = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !predicate(c) {
+                break;
+            }
+            res.push(c);
+            self.chars.next();
+        }
+        res
+    }
+}
+
+/// Parses a decimal, hexadecimal (`0x1f`) or binary (`0b101`) literal. `_` can separate digits.
+pub(crate) fn parse_literal<W: Word>(literal: &str) -> Result<W, String> {
+    let digits = literal.replace('_', "");
+    let (digits, radix) = match digits.get(..2) {
+        Some("0x") | Some("0X") => (&digits[2..], 16),
+        Some("0b") | Some("0B") => (&digits[2..], 2),
+        _ => return W::parse_word(&digits),
+    };
+
+    if digits.is_empty() {
+        return Err(format!("{} has no digits", literal));
+    }
+
+    let mut value = W::zero();
+    for c in digits.chars() {
+        let digit = c.to_digit(radix).ok_or_else(|| format!("invalid digit '{}' in {}", c, literal))?;
+        value = value.checked_mul(&W::from_usize(radix as usize))
+            .and_then(|value| value.checked_add(&W::from_usize(digit as usize)))
+            .ok_or_else(|| format!("{} doesn't fit into a register", literal))?;
+    }
+    Ok(value)
+}
+
+/// The result of a checked operation, or an error saying the expression `problem`
+fn apply<W: Word>(result: Option<W>, problem: &str) -> Result<W, ExpressionError> {
+    result.ok_or_else(|| ExpressionError::Invalid(format!("the expression {}", problem)))
+}
@@ -0,0 +1,361 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, Write};
+
+use crate::instruction::StringRepr;
+use crate::run::StepReport;
+use crate::state::State;
+use crate::text::source_map::RegisterNames;
+use crate::word::Word;
+
+/// Writes the trace of a run (see `RegisterMachine::run_formatted`).
+///
+/// All formats number configurations by the steps executed up to them (`State::get_steps`):
+/// the initial configuration is step 0 and the one after the first instruction is step 1.
+pub trait TraceFormat<W: Word> {
+    /// Writes the configuration the run starts in
+    fn initial(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()>;
+
+    /// Writes the configuration after a step, given what the step did
+    fn step(&mut self, output: &mut dyn Write, report: &StepReport<W>, state: &State<W>) -> io::Result<()>;
+
+    /// Writes the cost and space of a run that has halted
+    fn summary(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()>;
+
+    /// Gives the format the names of registers (see `SourceMap::register_names`), which the
+    /// text formats show instead of `rN`. Machine-readable formats keep the numbers.
+    fn name_registers(&mut self, _names: &RegisterNames) {}
+}
+
+/// The built-in trace formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ansi,
+    Plain,
+    JsonLines,
+    Csv,
+}
+
+impl Format {
+    /// Gets a format by the name `rscli` uses for it
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "ansi" => Some(Format::Ansi),
+            "plain" => Some(Format::Plain),
+            "jsonl" => Some(Format::JsonLines),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    /// The format to use if none has been chosen: plain text if the `NO_COLOR` environment
+    /// variable is set (and not empty), coloured text otherwise
+    pub fn from_env() -> Format {
+        match std::env::var_os("NO_COLOR") {
+            Some(value) if !value.is_empty() => Format::Plain,
+            _ => Format::Ansi,
+        }
+    }
+
+    /// Checks whether the format is meant to be read by programs rather than people
+    pub fn is_machine_readable(&self) -> bool {
+        matches!(self, Format::JsonLines | Format::Csv)
+    }
+
+    /// Creates the formatter. CSV needs to know the highest register it has a column for.
+    pub fn formatter<W: Word>(&self, highest_register: usize) -> Box<dyn TraceFormat<W>> {
+        match self {
+            Format::Ansi => Box::new(Ansi::default()),
+            Format::Plain => Box::new(Plain::default()),
+            Format::JsonLines => Box::new(JsonLines),
+            Format::Csv => Box::new(Csv::new(highest_register)),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Format::Ansi => "ansi",
+            Format::Plain => "plain",
+            Format::JsonLines => "jsonl",
+            Format::Csv => "csv",
+        })
+    }
+}
+
+/// `Step NN -- PC: NN, r0: ...` lines, with the initial configuration and the summary in yellow
+#[derive(Debug, Clone, Default)]
+pub struct Ansi {
+    names: RegisterNames,
+}
+
+/// Like `Ansi`, but without escape codes
+#[derive(Debug, Clone, Default)]
+pub struct Plain {
+    names: RegisterNames,
+}
+
+/// Text lines that also show the instruction that ran (and its source line, if the machine has a
+/// source map) and the registers it changed (`-` if there are none):
+/// `Step  1 --  1: LOAD 1           | r0: 0 -> 5 | PC:  1, r0: 5, r1: 5`.
+/// With `changes_only`, the registers after the step are left out.
+#[derive(Debug, Clone)]
+pub struct Annotated {
+    colors: bool,
+    changes_only: bool,
+    names: RegisterNames,
+}
+
+/// One JSON object per line: `{"step":1,"pc":1,"registers":{"0":5,"1":5}}` for every
+/// configuration (step 0 is the initial one) and `{"cost":{..},"space":{..}}` once the machine halts.
+/// Steps also get the instruction (and its source line, if known) and the changes, e.g.
+/// `"line":2,"instruction":"LOAD 1","changes":[{"register":0,"old":0,"new":5}]`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonLines;
+
+/// A `step,pc,r0,...` header and one row per configuration, with a column for every register
+/// up to the highest one. Registers above it are left out, so it should cover every register the
+/// program addresses (see `InstructionVec::highest_register`) and the ones `INIT` sets.
+#[derive(Debug, Clone, Copy)]
+pub struct Csv {
+    highest_register: usize,
+    header_written: bool,
+}
+
+/// Writes a register's name, or `rN` if it has none
+fn write_register(output: &mut dyn Write, register: usize, names: &RegisterNames) -> io::Result<()> {
+    match names.get(&register) {
+        Some(name) => output.write_all(name.as_bytes()),
+        None => write!(output, "r{}", register),
+    }
+}
+
+/// Writes the registers like the text trace does: `r0: 5, r1: 3` (or `r0: 5, counter: 3`)
+fn write_registers<W: Word>(output: &mut dyn Write, state: &State<W>, names: &RegisterNames) -> io::Result<()> {
+    for (n, (i, value)) in state.used_registers().enumerate() {
+        if n != 0 {
+            output.write_all(b", ")?;
+        }
+        write_register(output, i, names)?;
+        write!(output, ": {}", value)?;
+    }
+    Ok(())
+}
+
+/// Writes the initial configuration of the text formats, in yellow if `colors` is set
+fn write_initial<W: Word>(output: &mut dyn Write, state: &State<W>, names: &RegisterNames, colors: bool) -> io::Result<()> {
+    output.write_all(if colors { b"\x1b[33mInitial Configuration -- " } else { b"Initial Configuration -- " })?;
+    write_registers(output, state, names)?;
+    output.write_all(if colors { b"\x1b[0m\n" } else { b"\n" })
+}
+
+/// Writes the cost and space of the text formats, in yellow if `colors` is set
+fn write_summary<W: Word>(output: &mut dyn Write, state: &State<W>, colors: bool) -> io::Result<()> {
+    match colors {
+        true => writeln!(output, "\x1b[33mCost -- {}\nSpace -- {}\x1b[0m", state.get_cost(), state.get_space()),
+        false => writeln!(output, "Cost -- {}\nSpace -- {}", state.get_cost(), state.get_space()),
+    }
+}
+
+/// Writes a configuration after a step: `Step NN -- PC: NN, r0: ...`
+fn write_configuration<W: Word>(output: &mut dyn Write, state: &State<W>, names: &RegisterNames) -> io::Result<()> {
+    write!(output, "Step {:2} -- PC: {:2}, ", state.get_steps(), state.get_pc())?;
+    write_registers(output, state, names)?;
+    output.write_all(b"\n")
+}
+
+impl<W: Word> TraceFormat<W> for Ansi {
+    fn initial(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()> {
+        write_initial(output, state, &self.names, true)
+    }
+
+    fn step(&mut self, output: &mut dyn Write, _report: &StepReport<W>, state: &State<W>) -> io::Result<()> {
+        write_configuration(output, state, &self.names)
+    }
+
+    fn summary(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()> {
+        write_summary(output, state, true)
+    }
+
+    fn name_registers(&mut self, names: &RegisterNames) {
+        self.names = names.clone();
+    }
+}
+
+impl<W: Word> TraceFormat<W> for Plain {
+    fn initial(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()> {
+        write_initial(output, state, &self.names, false)
+    }
+
+    fn step(&mut self, output: &mut dyn Write, _report: &StepReport<W>, state: &State<W>) -> io::Result<()> {
+        write_configuration(output, state, &self.names)
+    }
+
+    fn summary(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()> {
+        write_summary(output, state, false)
+    }
+
+    fn name_registers(&mut self, names: &RegisterNames) {
+        self.names = names.clone();
+    }
+}
+
+impl Annotated {
+    /// Creates an annotated text format, with escape codes if `colors` is set
+    pub fn new(colors: bool, changes_only: bool) -> Annotated {
+        Annotated { colors, changes_only, names: RegisterNames::new() }
+    }
+}
+
+impl<W: Word> TraceFormat<W> for Annotated {
+    fn initial(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()> {
+        write_initial(output, state, &self.names, self.colors)
+    }
+
+    fn step(&mut self, output: &mut dyn Write, report: &StepReport<W>, state: &State<W>) -> io::Result<()> {
+        // Source lines if there's a source map, instruction numbers (like jump targets) otherwise
+        let line = report.line.unwrap_or(report.pc + 1);
+        write!(output, "Step {:2} -- {:>2}: {:<16} | ", state.get_steps(), line, StringRepr::to_string(&report.instruction))?;
+
+        let mut unchanged = true;
+        for change in report.changed() {
+            if !unchanged {
+                output.write_all(b", ")?;
+            }
+            if self.colors {
+                output.write_all(b"\x1b[32m")?;
+            }
+            write_register(output, change.register, &self.names)?;
+            write!(output, ": {} -> {}", change.old, change.new)?;
+            if self.colors {
+                output.write_all(b"\x1b[0m")?;
+            }
+            unchanged = false;
+        }
+        if unchanged {
+            output.write_all(b"-")?;
+        }
+
+        write!(output, " | PC: {:2}", state.get_pc())?;
+        if !self.changes_only {
+            output.write_all(b", ")?;
+            write_registers(output, state, &self.names)?;
+        }
+        output.write_all(b"\n")
+    }
+
+    fn summary(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()> {
+        write_summary(output, state, self.colors)
+    }
+
+    fn name_registers(&mut self, names: &RegisterNames) {
+        self.names = names.clone();
+    }
+}
+
+impl JsonLines {
+    fn configuration<W: Word>(output: &mut dyn Write, report: Option<&StepReport<W>>, state: &State<W>) -> io::Result<()> {
+        write!(output, "{{\"step\":{},\"pc\":{},", state.get_steps(), state.get_pc())?;
+
+        if let Some(line) = report.and_then(|report| report.line) {
+            write!(output, "\"line\":{},", line)?;
+        }
+
+        if let Some(report) = report {
+            output.write_all(b"\"instruction\":")?;
+            write_json_string(output, &StringRepr::to_string(&report.instruction))?;
+            output.write_all(b",\"changes\":[")?;
+            for (n, change) in report.changed().enumerate() {
+                if n != 0 {
+                    output.write_all(b",")?;
+                }
+                write!(output, "{{\"register\":{},\"old\":{},\"new\":{}}}", change.register, change.old, change.new)?;
+            }
+            output.write_all(b"],")?;
+        }
+
+        output.write_all(b"\"registers\":{")?;
+        for (n, (i, value)) in state.used_registers().enumerate() {
+            if n != 0 {
+                output.write_all(b",")?;
+            }
+            write!(output, "\"{}\":{}", i, value)?;
+        }
+        output.write_all(b"}}\n")
+    }
+}
+
+/// Writes `text` as a JSON string, escaping quotes, backslashes and control characters
+fn write_json_string(output: &mut dyn Write, text: &str) -> io::Result<()> {
+    output.write_all(b"\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => output.write_all(b"\\\"")?,
+            '\\' => output.write_all(b"\\\\")?,
+            '\n' => output.write_all(b"\\n")?,
+            '\r' => output.write_all(b"\\r")?,
+            '\t' => output.write_all(b"\\t")?,
+            c if c.is_control() => write!(output, "\\u{:04x}", c as u32)?,
+            c => write!(output, "{}", c)?,
+        }
+    }
+    output.write_all(b"\"")
+}
+
+impl<W: Word> TraceFormat<W> for JsonLines {
+    fn initial(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()> {
+        JsonLines::configuration(output, None, state)
+    }
+
+    fn step(&mut self, output: &mut dyn Write, report: &StepReport<W>, state: &State<W>) -> io::Result<()> {
+        JsonLines::configuration(output, Some(report), state)
+    }
+
+    fn summary(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()> {
+        let (cost, space) = (state.get_cost(), state.get_space());
+        writeln!(
+            output,
+            "{{\"cost\":{{\"uniform\":{},\"logarithmic\":{}}},\"space\":{{\"uniform\":{},\"logarithmic\":{}}}}}",
+            cost.uniform, cost.logarithmic, space.uniform, space.logarithmic
+        )
+    }
+}
+
+impl Csv {
+    /// Creates a CSV formatter with columns for the registers 0 to `highest_register`
+    pub fn new(highest_register: usize) -> Csv {
+        Csv { highest_register, header_written: false }
+    }
+
+    fn row<W: Word>(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()> {
+        if !self.header_written {
+            output.write_all(b"step,pc")?;
+            for i in 0..=self.highest_register {
+                write!(output, ",r{}", i)?;
+            }
+            output.write_all(b"\n")?;
+            self.header_written = true;
+        }
+
+        write!(output, "{},{}", state.get_steps(), state.get_pc())?;
+        for i in 0..=self.highest_register {
+            write!(output, ",{}", state.peek_reg(i))?;
+        }
+        output.write_all(b"\n")
+    }
+}
+
+impl<W: Word> TraceFormat<W> for Csv {
+    fn initial(&mut self, output: &mut dyn Write, state: &State<W>) -> io::Result<()> {
+        self.row(output, state)
+    }
+
+    fn step(&mut self, output: &mut dyn Write, _report: &StepReport<W>, state: &State<W>) -> io::Result<()> {
+        self.row(output, state)
+    }
+
+    /// CSV has no place for it
+    fn summary(&mut self, _output: &mut dyn Write, _state: &State<W>) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+//! The canonical layout of programs (`rscli fmt`), built on the syntax tree. `INIT`, directives and
+//! labels start at the beginning of the line, instructions are indented:
+//!
+//! ```text
+//! INIT 5 3
+//! .reg n = 1
+//!
+//! loop:   LOAD     n  ; instructions are indented,
+//!         IF c(0) = 0 THEN GOTO done
+//!         CSUB     1  ; and their operands and the comments
+//!         INDSTORE n  ; of consecutive lines are aligned
+//! done:   END
+//! ```
+//!
+//! Mnemonics are upper case and directives lower case, `IF`s are spaced like above, and runs of
+//! blank lines become one. Formatting a formatted program doesn't change it.
+
+use crate::text::deserializer::{COND_JMP_PARSER, DIRECTIVES, INSTRUCTIONS};
+use crate::text::syntax::{Node, NodeKind, SyntaxLine, SyntaxTree};
+
+/// The column (0-based) instructions start at. Longer labels get their own line.
+const INDENT: usize = 8;
+
+/// A line of the formatted program
+enum Layout {
+    Blank,
+    /// A comment-only line (indented or not), or a line of a `/* */` comment that spans lines
+    Verbatim(String),
+    Code { code: String, comment: Option<String> },
+}
+
+/// Formats `source`, which doesn't have to be a valid program
+pub fn format(source: &str) -> String {
+    format_tree(&SyntaxTree::parse(source))
+}
+
+/// Formats the program `tree` has been built from
+pub fn format_tree(tree: &SyntaxTree) -> String {
+    // Operands start at the same column, except for IF's (which reads as a sentence)
+    let width = tree.lines.iter()
+        .filter(|line| !line.continued && is_indented(line) && line.operands().next().is_some())
+        .filter_map(|line| line.find(NodeKind::Mnemonic))
+        .filter(|mnemonic| !mnemonic.text.eq_ignore_ascii_case("IF"))
+        .map(|mnemonic| mnemonic.text.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    // The newline at the end of the last line doesn't start another one
+    let lines = match tree.lines.split_last() {
+        Some((last, lines)) if last.to_string().is_empty() => lines,
+        _ => &tree.lines[..],
+    };
+
+    let mut layouts: Vec<Layout> = Vec::new();
+    for (n, line) in lines.iter().enumerate() {
+        // The lines of a `/* */` comment can't be indented without moving the ones after the first
+        let opens = lines.get(n + 1).is_some_and(|next| next.continued);
+        if line.continued || (opens && line.is_trivia()) {
+            layouts.push(Layout::Verbatim(line.to_string().trim_end().to_string()));
+        } else if line.nodes.is_empty() {
+            // Runs of blank lines and blank lines at the start become one, and none respectively
+            if !matches!(layouts.last(), None | Some(Layout::Blank)) {
+                layouts.push(Layout::Blank);
+            }
+        } else if line.is_trivia() {
+            let indent = if line.nodes[0].leading.is_empty() { 0 } else { INDENT };
+            layouts.push(Layout::Verbatim(format!("{}{}", " ".repeat(indent), join(&line.nodes))));
+        } else {
+            code(line, width, &mut layouts);
+        }
+    }
+    while let Some(Layout::Blank) = layouts.last() {
+        layouts.pop();
+    }
+
+    let mut res = String::new();
+    for (n, layout) in layouts.iter().enumerate() {
+        match layout {
+            Layout::Blank => {},
+            Layout::Verbatim(text) => res.push_str(text),
+            Layout::Code { code, comment: None } => res.push_str(code),
+            Layout::Code { code, comment: Some(comment) } => {
+                let column = comment_column(&layouts, n);
+                res.push_str(&format!("{:<column$}  {}", code, comment));
+            },
+        }
+        res.push('\n');
+    }
+    res
+}
+
+/// Lays out a line with code
+fn code(line: &SyntaxLine, width: usize, layouts: &mut Vec<Layout>) {
+    // Comments after the last operand are aligned, comments in between stay where they are
+    let last = line.nodes.iter().rposition(|node| node.kind != NodeKind::Comment).unwrap();
+    let (nodes, comments) = line.nodes.split_at(last + 1);
+    let comment = match comments.is_empty() {
+        true => None,
+        false => Some(join(comments)),
+    };
+
+    let label = line.find(NodeKind::Label).map(|label| label.text.as_str());
+    let position = match nodes.iter().position(|node| node.kind == NodeKind::Mnemonic) {
+        Some(position) => position,
+        None => {
+            layouts.push(Layout::Code { code: label.unwrap_or_default().to_string(), comment });
+            return;
+        },
+    };
+
+    let name = normalize(&nodes[position].text);
+    let rest = &nodes[position + 1..];
+    let operands = join(rest);
+    let inline = rest.iter().any(|node| node.kind == NodeKind::Comment);
+
+    let body = match () {
+        _ if rest.is_empty() => name,
+        _ if name == "IF" && !inline => condition(&operands),
+        _ if is_indented(line) => format!("{:<width$} {}", name, operands),
+        _ => format!("{} {}", name, operands),
+    };
+
+    // Comments before the mnemonic stay in front of it
+    let leading: Vec<Node> = nodes[..position].iter().filter(|node| node.kind == NodeKind::Comment).cloned().collect();
+    let body = match leading.is_empty() {
+        true => body,
+        false => format!("{} {}", join(&leading), body),
+    };
+
+    let code = match label {
+        Some(label) if label.chars().count() < INDENT => format!("{:<INDENT$}{}", label, body),
+        Some(label) => {
+            layouts.push(Layout::Code { code: label.to_string(), comment: None });
+            format!("{}{}", " ".repeat(INDENT), body)
+        },
+        None if is_indented(line) => format!("{}{}", " ".repeat(INDENT), body),
+        None => body,
+    };
+    layouts.push(Layout::Code { code, comment });
+}
+
+/// Formats the operands of an `IF` like `c(0) > 0 THEN GOTO 1`, if they can be parsed
+fn condition(operands: &str) -> String {
+    let text = format!("IF {}", operands);
+    let captures = match COND_JMP_PARSER.captures(&text) {
+        Some(captures) => captures,
+        None => return text,
+    };
+
+    // Whatever follows the target is an error for the parser, but kept
+    let rest = text[captures.get(0).unwrap().end()..].trim();
+    let res = format!("IF c(0) {} {} THEN GOTO {}", &captures[1], &captures[2], &captures[3]);
+    match rest.is_empty() {
+        true => res,
+        false => format!("{} {}", res, rest),
+    }
+}
+
+/// The column the comment of `layouts[n]` starts at: after the longest code of the lines with
+/// comments around it (up to blank and comment-only lines)
+fn comment_column(layouts: &[Layout], n: usize) -> usize {
+    let is_code = |layout: &&Layout| matches!(layout, Layout::Code { .. });
+    let start = n - layouts[..n].iter().rev().take_while(is_code).count();
+    let end = n + layouts[n..].iter().take_while(is_code).count();
+
+    layouts[start..end].iter()
+        .filter_map(|layout| match layout {
+            Layout::Code { code, comment: Some(_) } => Some(code.chars().count()),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Whether a line with code is indented: all but `INIT`, directives and lone labels are
+fn is_indented(line: &SyntaxLine) -> bool {
+    match (line.find(NodeKind::Label), line.find(NodeKind::Mnemonic)) {
+        (_, None) => false,
+        (Some(_), Some(_)) => true,
+        (None, Some(mnemonic)) => !(mnemonic.text.eq_ignore_ascii_case("INIT") || mnemonic.text.starts_with('.')),
+    }
+}
+
+/// Instructions in upper case, directives in lower case and macros as they are
+fn normalize(mnemonic: &str) -> String {
+    let upper = mnemonic.to_uppercase();
+    let lower = mnemonic.to_lowercase();
+    match () {
+        _ if INSTRUCTIONS.contains(&upper.as_str()) => upper,
+        _ if DIRECTIVES.contains(&lower.as_str()) => lower,
+        _ => mnemonic.to_string(),
+    }
+}
+
+/// The texts of `nodes`, separated by spaces
+fn join(nodes: &[Node]) -> String {
+    nodes.iter().map(|node| node.text.as_str()).collect::<Vec<&str>>().join(" ")
+}
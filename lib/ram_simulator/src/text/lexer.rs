@@ -0,0 +1,113 @@
+//! Splits the lines of a program into words, quoted strings and comments. Comments start with `;`,
+//! `#` or `//` and go to the end of the line, or are enclosed in `/* */`, which can span lines.
+
+/// What a token is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TokenKind {
+    /// An instruction, operand, label, directive, ...: anything up to whitespace or a comment
+    Word,
+    /// A string in double quotes, e.g. the path of an `.include` (comments can't start inside it)
+    Quoted,
+    /// A comment, with its delimiters (a part of a `/* */` comment on lines it spans)
+    Comment,
+}
+
+/// A token, a slice of the line it's on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+}
+
+/// Tokenizes the lines of one file, keeping track of `/* */` comments that span lines
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Lexer {
+    in_comment: bool,
+}
+
+impl Lexer {
+    pub fn new() -> Lexer {
+        Lexer::default()
+    }
+
+    /// Whether a `/* */` comment is still open after the last line
+    pub fn in_comment(&self) -> bool {
+        self.in_comment
+    }
+
+    /// Splits the next line into tokens (whitespace between them is dropped)
+    pub fn tokens<'a>(&mut self, line: &'a str) -> Vec<Token<'a>> {
+        let mut tokens = Vec::new();
+        let mut i = 0;
+
+        while i < line.len() {
+            let rest = &line[i..];
+
+            if self.in_comment {
+                let end = match rest.find("*/") {
+                    Some(end) => {
+                        self.in_comment = false;
+                        end + 2
+                    },
+                    None => rest.len(),
+                };
+                tokens.push(Token { kind: TokenKind::Comment, text: &rest[..end] });
+                i += end;
+                continue;
+            }
+
+            let c = rest.chars().next().unwrap();
+            if c.is_whitespace() {
+                i += c.len_utf8();
+                continue;
+            }
+
+            let (kind, end) = if let Some(body) = rest.strip_prefix("/*") {
+                match body.find("*/") {
+                    Some(end) => (TokenKind::Comment, end + 4),
+                    None => {
+                        self.in_comment = true;
+                        (TokenKind::Comment, rest.len())
+                    },
+                }
+            } else if starts_comment(rest) {
+                (TokenKind::Comment, rest.len())
+            } else if c == '"' {
+                (TokenKind::Quoted, rest[1..].find('"').map_or(rest.len(), |end| end + 2))
+            } else {
+                let end = rest.char_indices()
+                    .find(|&(n, c)| c.is_whitespace() || c == '"' || starts_comment(&rest[n..]) || rest[n..].starts_with("/*"))
+                    .map_or(rest.len(), |(n, _)| n);
+                (TokenKind::Word, end)
+            };
+
+            tokens.push(Token { kind, text: &rest[..end] });
+            i += end;
+        }
+
+        tokens
+    }
+}
+
+/// `line` without the comments among its `tokens`, which are replaced by spaces so the columns stay the same
+pub(crate) fn code(line: &str, tokens: &[Token]) -> String {
+    let comments: Vec<(usize, usize)> = tokens.iter()
+        .filter(|token| token.kind == TokenKind::Comment)
+        .map(|token| {
+            let start = token.text.as_ptr() as usize - line.as_ptr() as usize;
+            (start, start + token.text.len())
+        })
+        .collect();
+
+    line.char_indices()
+        .map(|(i, c)| match comments.iter().any(|&(start, end)| (start..end).contains(&i)) {
+            true => ' ',
+            false => c,
+        })
+        .collect()
+}
+
+/// Whether `text` starts with a comment that goes to the end of the line
+fn starts_comment(text: &str) -> bool {
+    text.starts_with(';') || text.starts_with('#') || text.starts_with("//")
+}
@@ -0,0 +1,138 @@
+//! Macros: `.macro swap a b` ... `.endm` defines `swap`, and `swap 1 2` expands to its body,
+//! with `a` and `b` replaced by `1` and `2` and the labels defined in the body made unique.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::text::source_map::Span;
+
+/// A macro definition
+#[derive(Debug, Clone)]
+pub(crate) struct Macro {
+    pub name: String,
+    pub params: Vec<String>,
+    /// The lines between `.macro` and `.endm`, with their line numbers
+    pub body: Vec<(usize, String)>,
+    /// The included file the macro is defined in (`None` for the program itself)
+    pub file: Option<String>,
+    /// The directory includes in the body are resolved in
+    pub dir: PathBuf,
+    /// The line of `.macro`
+    pub line: usize,
+}
+
+/// A name that has been replaced in a line, in (0-based) char offsets
+#[derive(Debug, Clone)]
+pub(crate) struct Replacement {
+    original: Range<usize>,
+    expanded: Range<usize>,
+}
+
+impl Macro {
+    pub fn new(name: &str, params: Vec<String>, file: Option<String>, dir: PathBuf, line: usize) -> Macro {
+        Macro { name: name.to_string(), params, body: Vec::new(), file, dir, line }
+    }
+
+    /// The labels defined in the body, which every expansion gets its own copy of
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.body.iter().filter_map(|(_, text)| {
+            text.split_whitespace().next().and_then(|token| token.strip_suffix(':'))
+        })
+    }
+
+    /// The names to replace in the `n`th expansion with `args`: the parameters and the labels
+    pub fn names(&self, args: &[&str], n: usize) -> HashMap<String, String> {
+        let mut names: HashMap<String, String> = self.labels()
+            .map(|label| (label.to_string(), format!("{}__{}", label, n)))
+            .collect();
+        for (param, arg) in self.params.iter().zip(args) {
+            names.insert(param.clone(), arg.to_string());
+        }
+        names
+    }
+}
+
+/// Replaces every name (a run of letters, digits and `_` that doesn't start with a digit) in `text`
+/// that is a key of `names`, except for the instruction (or macro or directive) the line starts
+/// with, after its label. Returns the expanded text and where the replacements are.
+pub(crate) fn substitute(text: &str, names: &HashMap<String, String>) -> (String, Vec<Replacement>) {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut words = text.split_whitespace();
+    let mnemonic = match words.next() {
+        Some(label) if label.ends_with(':') => words.next(),
+        word => word,
+    };
+    let mnemonic = mnemonic.map_or(0..0, |word| {
+        let start = word.as_ptr() as usize - text.as_ptr() as usize;
+        start..start + word.len()
+    });
+
+    let mut res = String::with_capacity(text.len());
+    let mut replacements = Vec::new();
+    let (mut original, mut expanded) = (0, 0);
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if !is_name_char(c) {
+            res.push(c);
+            original += 1;
+            expanded += 1;
+            continue;
+        }
+
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, c)) = chars.peek() {
+            if !is_name_char(c) {
+                break;
+            }
+            end = i + c.len_utf8();
+            chars.next();
+        }
+
+        let word = &text[start..end];
+        let length = word.chars().count();
+        match names.get(word) {
+            Some(replacement) if !c.is_ascii_digit() && !mnemonic.contains(&start) => {
+                let replaced = replacement.chars().count();
+                replacements.push(Replacement {
+                    original: original..original + length,
+                    expanded: expanded..expanded + replaced,
+                });
+                res.push_str(replacement);
+                expanded += replaced;
+            },
+            _ => {
+                res.push_str(word);
+                expanded += length;
+            },
+        }
+        original += length;
+    }
+
+    (res, replacements)
+}
+
+/// Maps a span in an expanded line back to the line before the expansion. A span in a
+/// replacement covers the name that has been replaced.
+pub(crate) fn unexpand(span: Span, replacements: &[Replacement]) -> Span {
+    let start = unexpand_column(span.start - 1, false, replacements) + 1;
+    let end = unexpand_column(span.end - 1, true, replacements) + 1;
+    Span { start, end: end.max(start), ..span }
+}
+
+fn unexpand_column(column: usize, is_end: bool, replacements: &[Replacement]) -> usize {
+    let mut shift = 0isize;
+    for replacement in replacements {
+        let (start, end) = (replacement.expanded.start, replacement.expanded.end);
+        match is_end {
+            false if column < start => break,
+            true if column <= start => break,
+            false if column < end => return replacement.original.start,
+            true if column <= end => return replacement.original.end,
+            _ => shift = replacement.original.end as isize - end as isize,
+        }
+    }
+    (column as isize + shift) as usize
+}
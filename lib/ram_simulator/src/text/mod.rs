@@ -1,4 +1,12 @@
 pub mod deserializer;
+pub(crate) mod expression;
+pub mod format;
+pub mod formatter;
+pub(crate) mod lexer;
+pub(crate) mod macros;
+pub mod source_map;
+pub mod syntax;
+pub mod transpiler;
 
 pub trait Serializable {
     fn to_string(&self) -> String;
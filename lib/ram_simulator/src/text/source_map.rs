@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Where an instruction is in the program's source. Lines and columns are 1-based (like in
+/// editors), `end` is the column after the instruction's last character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The names registers have been given in the source (`.reg counter = 2`), by register
+pub type RegisterNames = BTreeMap<usize, String>;
+
+/// Maps instruction indices (PCs) to where the instructions are in the source, and back.
+/// Built by the parser, since PCs don't match file lines (`INIT` isn't an instruction).
+/// Also keeps the names of registers, so traces can use them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SourceMap {
+    /// The span of every instruction, in program order (so ordered by line, too)
+    spans: Vec<Span>,
+    registers: RegisterNames,
+}
+
+impl SourceMap {
+    /// Creates an empty source map
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Adds the span of the next instruction
+    pub fn push(&mut self, span: Span) {
+        self.spans.push(span);
+    }
+
+    /// Returns the span of the instruction at `pc`
+    pub fn span(&self, pc: usize) -> Option<Span> {
+        self.spans.get(pc).copied()
+    }
+
+    /// Returns the source line of the instruction at `pc`
+    pub fn line(&self, pc: usize) -> Option<usize> {
+        self.span(pc).map(|span| span.line)
+    }
+
+    /// Returns the PC of the (first) instruction on source line `line`, if there is one.
+    /// A macro invocation or an `.include` puts several instructions on one line.
+    pub fn pc(&self, line: usize) -> Option<usize> {
+        let pc = self.spans.partition_point(|span| span.line < line);
+        match self.spans.get(pc) {
+            Some(span) if span.line == line => Some(pc),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of instructions in the map
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Checks whether the map has no instructions
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Names `register`, unless it already has a name
+    pub fn name_register(&mut self, register: usize, name: &str) {
+        self.registers.entry(register).or_insert_with(|| name.to_string());
+    }
+
+    /// Returns the name of `register`, if it has one
+    pub fn register_name(&self, register: usize) -> Option<&str> {
+        self.registers.get(&register).map(String::as_str)
+    }
+
+    /// Returns the names of all named registers
+    pub fn register_names(&self) -> &RegisterNames {
+        &self.registers
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}-{}", self.line, self.start, self.end)
+    }
+}
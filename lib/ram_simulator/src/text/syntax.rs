@@ -0,0 +1,116 @@
+//! A concrete syntax tree of a program: every line split into labels, mnemonics, operands and
+//! comments, with the whitespace around them. Unlike a parsed machine it keeps everything, so
+//! printing it gives back the exact source, and it can be built from programs that don't parse.
+
+use std::fmt::{Display, Formatter};
+
+use crate::text::lexer::{Lexer, TokenKind};
+
+/// What a node of a line is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// `loop:`, with its colon
+    Label,
+    /// An instruction, `INIT`, a directive or a macro invocation
+    Mnemonic,
+    /// A word (or quoted string) after the mnemonic
+    Operand,
+    /// A comment with its delimiters, or the part of a `/* */` comment on a line it spans
+    Comment,
+}
+
+/// A token of a line and the whitespace before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub leading: String,
+    pub text: String,
+}
+
+/// A line of a program
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SyntaxLine {
+    pub nodes: Vec<Node>,
+    /// The whitespace after the last node
+    pub trailing: String,
+    /// Whether the line starts inside a `/* */` comment
+    pub continued: bool,
+}
+
+/// All lines of a program
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SyntaxTree {
+    pub lines: Vec<SyntaxLine>,
+}
+
+impl SyntaxLine {
+    /// The node of `kind`, if the line has one (labels and mnemonics appear at most once)
+    pub fn find(&self, kind: NodeKind) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.kind == kind)
+    }
+
+    /// The operands, in order
+    pub fn operands(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().filter(|node| node.kind == NodeKind::Operand)
+    }
+
+    /// Whether the line has nothing but whitespace and comments
+    pub fn is_trivia(&self) -> bool {
+        self.nodes.iter().all(|node| node.kind == NodeKind::Comment)
+    }
+}
+
+impl SyntaxTree {
+    /// Builds the tree of `source`. This never fails: lines are classified only by where their
+    /// tokens are, whether they make sense is up to the parser.
+    pub fn parse(source: &str) -> SyntaxTree {
+        let mut lexer = Lexer::new();
+        let lines = source.split('\n')
+            .map(|text| {
+                let continued = lexer.in_comment();
+                let mut line = SyntaxLine { continued, ..SyntaxLine::default() };
+                let mut end = 0;
+
+                for token in lexer.tokens(text) {
+                    let start = token.text.as_ptr() as usize - text.as_ptr() as usize;
+                    let mut code = line.nodes.iter().filter(|node| node.kind != NodeKind::Comment);
+                    let kind = match (token.kind, code.next_back().map(|node| node.kind)) {
+                        (TokenKind::Comment, _) => NodeKind::Comment,
+                        (TokenKind::Word, None) if token.text.ends_with(':') => NodeKind::Label,
+                        (_, None) | (_, Some(NodeKind::Label)) => NodeKind::Mnemonic,
+                        _ => NodeKind::Operand,
+                    };
+                    line.nodes.push(Node { kind, leading: text[end..start].to_string(), text: token.text.to_string() });
+                    end = start + token.text.len();
+                }
+
+                line.trailing = text[end..].to_string();
+                line
+            })
+            .collect();
+
+        SyntaxTree { lines }
+    }
+}
+
+impl Display for SyntaxLine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for node in &self.nodes {
+            write!(f, "{}{}", node.leading, node.text)?;
+        }
+        f.write_str(&self.trailing)
+    }
+}
+
+/// Prints the exact source the tree has been built from
+impl Display for SyntaxTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (n, line) in self.lines.iter().enumerate() {
+            if n != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
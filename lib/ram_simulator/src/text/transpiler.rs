@@ -0,0 +1,588 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+use crate::comparison::Comparison;
+use crate::instruction::{InstructionVec, Op, StringRepr};
+use crate::semantics::Semantics;
+use crate::state::State;
+use crate::word::Word;
+
+/// Languages a program can be transpiled to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Target {
+    #[default]
+    C,
+    Rust,
+}
+
+impl Target {
+    /// Parses the name of a target language (as printed by `Display`)
+    pub fn from_name(name: &str) -> Option<Target> {
+        match name.trim().to_lowercase().as_str() {
+            "c" => Some(Target::C),
+            "rust" | "rs" => Some(Target::Rust),
+            _ => None,
+        }
+    }
+
+    /// Returns the usual file extension of source files in the language
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Target::C => "c",
+            Target::Rust => "rs",
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Target::C => "c",
+            Target::Rust => "rust",
+        })
+    }
+}
+
+/// Translates `program` into a single, standalone C or Rust source file.
+///
+/// The generated program has unsigned 128-bit registers and the default semantics: `SUB` is the
+/// modified subtraction (monus), `DIV` by 0 yields 0 and an overflow stops the program with an
+/// error. Registers get set up like `initial` (the state after `INIT`), unless values are passed
+/// on the command line, which go into r1, r2, ... instead. When the program reaches `END`, it
+/// prints the used registers the way `State::print_registers` does.
+pub fn transpile<W: Word>(program: &InstructionVec<W>, initial: &State<W>, target: Target) -> Result<String, String> {
+    if initial.semantics() != Semantics::default() {
+        return Err(format!(
+            "Only the default semantics ({}, division by zero yields {}) can be transpiled",
+            Semantics::default().arithmetic, Semantics::default().division_by_zero,
+        ));
+    }
+
+    let init = initial.used_registers()
+        .filter(|(i, v)| *i != 0 || !v.is_zero())
+        .map(|(i, v)| Ok((i, constant(&v)?)))
+        .collect::<Result<Vec<(usize, u128)>, String>>()?;
+
+    let mut instructions = Vec::new();
+    for op in &program.instructions {
+        instructions.push((lower(op)?, StringRepr::to_string(op)));
+    }
+
+    Ok(match target {
+        Target::C => c::source(&instructions, &init),
+        Target::Rust => rust::source(&instructions, &init),
+    })
+}
+
+/// An instruction with its constants converted to `u128`
+enum Lowered {
+    Load(Operand),
+    Store { register: usize, indirect: bool },
+    Arithmetic(Operation, Operand),
+    Jump(usize),
+    CondJump(Comparison, u128, usize),
+    End,
+}
+
+enum Operand {
+    Direct(usize),
+    Indirect(usize),
+    Constant(u128),
+}
+
+#[derive(Clone, Copy)]
+enum Operation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+fn lower<W: Word>(op: &Op<W>) -> Result<Lowered, String> {
+    Ok(match op {
+        Op::Load(i) => Lowered::Load(Operand::Direct(i.operand())),
+        Op::IndLoad(i) => Lowered::Load(Operand::Indirect(i.operand())),
+        Op::CLoad(c) => Lowered::Load(Operand::Constant(constant(c.operand())?)),
+        Op::Store(i) => Lowered::Store { register: i.operand(), indirect: false },
+        Op::IndStore(i) => Lowered::Store { register: i.operand(), indirect: true },
+        Op::Add(i) => Lowered::Arithmetic(Operation::Add, Operand::Direct(i.operand())),
+        Op::IndAdd(i) => Lowered::Arithmetic(Operation::Add, Operand::Indirect(i.operand())),
+        Op::CAdd(c) => Lowered::Arithmetic(Operation::Add, Operand::Constant(constant(c.operand())?)),
+        Op::Sub(i) => Lowered::Arithmetic(Operation::Sub, Operand::Direct(i.operand())),
+        Op::IndSub(i) => Lowered::Arithmetic(Operation::Sub, Operand::Indirect(i.operand())),
+        Op::CSub(c) => Lowered::Arithmetic(Operation::Sub, Operand::Constant(constant(c.operand())?)),
+        Op::Mult(i) => Lowered::Arithmetic(Operation::Mul, Operand::Direct(i.operand())),
+        Op::IndMult(i) => Lowered::Arithmetic(Operation::Mul, Operand::Indirect(i.operand())),
+        Op::CMult(c) => Lowered::Arithmetic(Operation::Mul, Operand::Constant(constant(c.operand())?)),
+        Op::Div(i) => Lowered::Arithmetic(Operation::Div, Operand::Direct(i.operand())),
+        Op::IndDiv(i) => Lowered::Arithmetic(Operation::Div, Operand::Indirect(i.operand())),
+        Op::CDiv(c) => Lowered::Arithmetic(Operation::Div, Operand::Constant(constant(c.operand())?)),
+        Op::Jmp(j) => Lowered::Jump(j.operand()),
+        Op::CondJmp(j) => Lowered::CondJump(j.comparison(), constant(j.value())?, j.jmp_address()),
+        Op::End(_) => Lowered::End,
+    })
+}
+
+/// Converts a register value or constant of the program to the registers of the generated code
+fn constant<W: Word>(value: &W) -> Result<u128, String> {
+    value.to_string().parse::<u128>()
+        .map_err(|_| format!("{} doesn't fit into an unsigned 128-bit register", value))
+}
+
+/// Returns the 1-based jump targets of the instructions that don't exist
+fn missing_targets(instructions: &[(Lowered, String)]) -> BTreeSet<usize> {
+    instructions.iter()
+        .filter_map(|(instruction, _)| match instruction {
+            Lowered::Jump(target) | Lowered::CondJump(_, _, target) => Some(*target),
+            _ => None,
+        })
+        .filter(|&target| target > instructions.len())
+        .collect()
+}
+
+fn comparison_operator(comparison: Comparison) -> &'static str {
+    match comparison {
+        Comparison::Eq => "==",
+        Comparison::Ne => "!=",
+        Comparison::Lt => "<",
+        Comparison::Le => "<=",
+        Comparison::Gt => ">",
+        Comparison::Ge => ">=",
+    }
+}
+
+/// C (GCC or Clang, for `unsigned __int128` and the overflow builtins)
+mod c {
+    use std::collections::BTreeSet;
+
+    use super::{comparison_operator, missing_targets, Lowered, Operand, Operation};
+
+    const PRELUDE: &str = r#"#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef unsigned __int128 word;
+
+/* Registers below DENSE live in an array, the others in a (short) list */
+#define DENSE ((size_t)1 << 20)
+
+static word *registers = NULL;
+static unsigned char *used = NULL;
+static size_t size = 0;
+
+static size_t *sparse_indices = NULL;
+static word *sparse_values = NULL;
+static size_t sparse_count = 0;
+static size_t sparse_capacity = 0;
+
+/* Formats a word in decimal */
+static inline const char *format(word value, char *buffer) {
+    char *p = buffer + 39;
+    *p = '\0';
+    do {
+        *--p = (char)('0' + (int)(value % 10));
+        value /= 10;
+    } while (value != 0);
+    return p;
+}
+
+static inline void fail(int line, const char *instruction, const char *cause) {
+    fprintf(stderr, "Error: Instruction %d (%s): %s\n", line, instruction, cause);
+    exit(1);
+}
+
+static inline void missing(int line) {
+    fprintf(stderr, "Error: Instruction %d doesn't exist (missing END?)\n", line);
+    exit(1);
+}
+
+static inline void *grow(void *array, size_t count, size_t element) {
+    array = realloc(array, count * element);
+    if (array == NULL) {
+        fprintf(stderr, "Error: Out of memory\n");
+        exit(1);
+    }
+    return array;
+}
+
+/* Returns a register (below DENSE) and marks it as used */
+static inline word *dense(size_t index) {
+    if (index >= size) {
+        size_t new_size = size < 16 ? 16 : size;
+        while (new_size <= index) {
+            new_size *= 2;
+        }
+        registers = grow(registers, new_size, sizeof(word));
+        used = grow(used, new_size, 1);
+        memset(registers + size, 0, (new_size - size) * sizeof(word));
+        memset(used + size, 0, new_size - size);
+        size = new_size;
+    }
+    used[index] = 1;
+    return &registers[index];
+}
+
+/* Returns a register and marks it as used */
+static inline word *slot(size_t index) {
+    if (index < DENSE) {
+        return dense(index);
+    }
+    for (size_t i = 0; i < sparse_count; i++) {
+        if (sparse_indices[i] == index) {
+            return &sparse_values[i];
+        }
+    }
+    if (sparse_count == sparse_capacity) {
+        sparse_capacity = sparse_capacity == 0 ? 16 : 2 * sparse_capacity;
+        sparse_indices = grow(sparse_indices, sparse_capacity, sizeof(size_t));
+        sparse_values = grow(sparse_values, sparse_capacity, sizeof(word));
+    }
+    sparse_indices[sparse_count] = index;
+    sparse_values[sparse_count] = 0;
+    return &sparse_values[sparse_count++];
+}
+
+/* Reads a register (only writes mark registers as used) */
+static inline word peek(size_t index) {
+    if (index < DENSE) {
+        return index < size ? registers[index] : 0;
+    }
+    for (size_t i = 0; i < sparse_count; i++) {
+        if (sparse_indices[i] == index) {
+            return sparse_values[i];
+        }
+    }
+    return 0;
+}
+
+static inline void set(size_t index, word value) {
+    *slot(index) = value;
+}
+
+static inline size_t pointer(int line, const char *instruction, size_t index) {
+    word value = peek(index);
+    if (value > SIZE_MAX) {
+        char buffer[40], cause[80];
+        snprintf(cause, sizeof cause, "pointer %s is not a valid register index", format(value, buffer));
+        fail(line, instruction, cause);
+    }
+    return (size_t)value;
+}
+
+static inline word add(int line, const char *instruction, word a, word b) {
+    word result;
+    if (__builtin_add_overflow(a, b, &result)) {
+        fail(line, instruction, "arithmetic overflow");
+    }
+    return result;
+}
+
+static inline word sub(word a, word b) {
+    return a < b ? 0 : a - b;
+}
+
+static inline word mul(int line, const char *instruction, word a, word b) {
+    word result;
+    if (__builtin_mul_overflow(a, b, &result)) {
+        fail(line, instruction, "arithmetic overflow");
+    }
+    return result;
+}
+
+static inline word divide(word a, word b) {
+    return b == 0 ? 0 : a / b;
+}
+
+static inline word parse(const char *str) {
+    word value = 0;
+    const char *p = str;
+    do {
+        if (*p < '0' || *p > '9' || value > (~(word)0 - (word)(*p - '0')) / 10) {
+            fprintf(stderr, "Error: INIT args must be numbers [%s]\n", str);
+            exit(1);
+        }
+        value = value * 10 + (word)(*p - '0');
+    } while (*++p != '\0');
+    return value;
+}
+
+/* Prints the used registers (the accumulator is always included) */
+static inline void print_registers(void) {
+    char buffer[40];
+    for (size_t i = 0; i < size || i == 0; i++) {
+        if (i == 0 || used[i]) {
+            printf(i == 0 ? "r%zu: %s" : ", r%zu: %s", i, format(peek(i), buffer));
+        }
+    }
+    /* The list isn't sorted: print the smallest index greater than the previous one */
+    size_t previous = DENSE - 1;
+    for (size_t n = 0; n < sparse_count; n++) {
+        size_t next = SIZE_MAX;
+        for (size_t i = 0; i < sparse_count; i++) {
+            if (sparse_indices[i] > previous && sparse_indices[i] <= next) {
+                next = sparse_indices[i];
+            }
+        }
+        printf(", r%zu: %s", next, format(peek(next), buffer));
+        previous = next;
+    }
+    printf("\n");
+}
+"#;
+
+    pub(super) fn source(instructions: &[(Lowered, String)], init: &[(usize, u128)]) -> String {
+        let targets: BTreeSet<usize> = instructions.iter()
+            .filter_map(|(instruction, _)| match instruction {
+                Lowered::Jump(target) | Lowered::CondJump(_, _, target) => Some(*target),
+                _ => None,
+            })
+            .collect();
+
+        let mut res = String::new();
+        res.push_str("/* Generated from a RAM program by ram_simulator */\n");
+        res.push_str("/* Usage: ./program [r1 r2 ...] (replaces the values of INIT) */\n\n");
+        res.push_str(PRELUDE);
+
+        res.push_str("\nint main(int argc, char **argv) {\n");
+        res.push_str("    if (argc > 1) {\n");
+        res.push_str("        for (int i = 1; i < argc; i++) {\n");
+        res.push_str("            set((size_t)i, parse(argv[i]));\n");
+        res.push_str("        }\n");
+        res.push_str("    } else {\n");
+        for (index, value) in init {
+            res.push_str(&format!("        set({}, {});\n", index, word(*value)));
+        }
+        res.push_str("    }\n");
+
+        for (pc, (instruction, text)) in instructions.iter().enumerate() {
+            let line = pc + 1;
+            if targets.contains(&line) {
+                res.push_str(&format!("\nL{}: /* {} */\n", line, text));
+            } else {
+                res.push_str(&format!("\n    /* {}: {} */\n", line, text));
+            }
+            res.push_str(&statement(instruction, line, text));
+        }
+
+        // Running past the last instruction or jumping to one that doesn't exist
+        let end = instructions.len() + 1;
+        if targets.contains(&end) {
+            res.push_str(&format!("\nL{}:\n", end));
+        } else {
+            res.push('\n');
+        }
+        res.push_str(&format!("    missing({});\n", end));
+        for target in missing_targets(instructions).into_iter().filter(|&t| t != end) {
+            res.push_str(&format!("L{}:\n    missing({});\n", target, target));
+        }
+
+        res.push_str("    return 1;\n}\n");
+        res
+    }
+
+    fn statement(instruction: &Lowered, line: usize, text: &str) -> String {
+        let context = format!("{}, \"{}\"", line, text);
+        match instruction {
+            Lowered::Load(operand) => format!("    set(0, {});\n", operand_value(operand, &context)),
+            Lowered::Store { register, indirect: false } => format!("    set({}, peek(0));\n", register),
+            Lowered::Store { register, indirect: true } =>
+                format!("    set(pointer({}, {}), peek(0));\n", context, register),
+            Lowered::Arithmetic(operation, operand) => {
+                let value = operand_value(operand, &context);
+                let result = match operation {
+                    Operation::Add => format!("add({}, acc, {})", context, value),
+                    Operation::Sub => format!("sub(acc, {})", value),
+                    Operation::Mul => format!("mul({}, acc, {})", context, value),
+                    Operation::Div => format!("divide(acc, {})", value),
+                };
+                format!("    {{\n        word acc = peek(0);\n        set(0, {});\n    }}\n", result)
+            },
+            Lowered::Jump(target) => jump(*target, &context),
+            Lowered::CondJump(comparison, value, target) => format!(
+                "    if (peek(0) {} {}) {{\n    {}    }}\n",
+                comparison_operator(*comparison), word(*value), jump(*target, &context),
+            ),
+            Lowered::End => "    print_registers();\n    return 0;\n".to_string(),
+        }
+    }
+
+    fn operand_value(operand: &Operand, context: &str) -> String {
+        match operand {
+            Operand::Direct(register) => format!("peek({})", register),
+            Operand::Indirect(register) => format!("peek(pointer({}, {}))", context, register),
+            Operand::Constant(value) => word(*value),
+        }
+    }
+
+    fn jump(target: usize, context: &str) -> String {
+        match target {
+            0 => format!("    fail({}, \"invalid jump target 0\");\n", context),
+            _ => format!("    goto L{};\n", target),
+        }
+    }
+
+    /// A word literal (C has no 128-bit integer literals)
+    fn word(value: u128) -> String {
+        match u64::try_from(value) {
+            Ok(value) => format!("(word){}u", value),
+            Err(_) => format!("((word){:#x}u << 64 | (word){:#x}u)", (value >> 64) as u64, value as u64),
+        }
+    }
+}
+
+/// Rust (a single file for `rustc`)
+mod rust {
+    use super::{comparison_operator, Lowered, Operand, Operation};
+
+    const PRELUDE: &str = r#"#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::process::exit;
+
+/// The registers that have been written to. All others hold 0.
+struct Registers(BTreeMap<usize, u128>);
+
+impl Registers {
+    fn peek(&self, index: usize) -> u128 {
+        self.0.get(&index).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, index: usize, value: u128) {
+        self.0.insert(index, value);
+    }
+
+    fn pointer(&self, line: usize, instruction: &str, index: usize) -> usize {
+        let value = self.peek(index);
+        if value > usize::MAX as u128 {
+            fail(line, instruction, &format!("pointer {} is not a valid register index", value));
+        }
+        value as usize
+    }
+
+    /// Prints the used registers (the accumulator is always included)
+    fn print(&self) {
+        let mut res = format!("r0: {}", self.peek(0));
+        for (i, value) in self.0.range(1..) {
+            res.push_str(&format!(", r{}: {}", i, value));
+        }
+        println!("{}", res);
+    }
+}
+
+fn fail(line: usize, instruction: &str, cause: &str) -> ! {
+    eprintln!("Error: Instruction {} ({}): {}", line, instruction, cause);
+    exit(1);
+}
+
+fn add(line: usize, instruction: &str, a: u128, b: u128) -> u128 {
+    a.checked_add(b).unwrap_or_else(|| fail(line, instruction, "arithmetic overflow"))
+}
+
+fn sub(a: u128, b: u128) -> u128 {
+    a.saturating_sub(b)
+}
+
+fn mul(line: usize, instruction: &str, a: u128, b: u128) -> u128 {
+    a.checked_mul(b).unwrap_or_else(|| fail(line, instruction, "arithmetic overflow"))
+}
+
+fn div(a: u128, b: u128) -> u128 {
+    a.checked_div(b).unwrap_or(0)
+}
+"#;
+
+    pub(super) fn source(instructions: &[(Lowered, String)], init: &[(usize, u128)]) -> String {
+        let mut res = String::new();
+        res.push_str("//! Generated from a RAM program by ram_simulator\n");
+        res.push_str("//! Usage: ./program [r1 r2 ...] (replaces the values of INIT)\n\n");
+        res.push_str(PRELUDE);
+
+        res.push_str("\nfn main() {\n");
+        res.push_str("    let mut r = Registers(BTreeMap::new());\n\n");
+        res.push_str("    let args: Vec<String> = std::env::args().skip(1).collect();\n");
+        res.push_str("    if args.is_empty() {\n");
+        for (index, value) in init {
+            res.push_str(&format!("        r.set({}, {});\n", index, value));
+        }
+        res.push_str("    }\n");
+        res.push_str("    for (i, arg) in args.iter().enumerate() {\n");
+        res.push_str("        match arg.parse::<u128>() {\n");
+        res.push_str("            Ok(value) => r.set(i + 1, value),\n");
+        res.push_str("            Err(err) => {\n");
+        res.push_str("                eprintln!(\"Error: INIT args must be numbers [{}]\", err);\n");
+        res.push_str("                exit(1);\n");
+        res.push_str("            },\n");
+        res.push_str("        }\n");
+        res.push_str("    }\n\n");
+
+        res.push_str("    // The offset of the next instruction (jump targets are 1-based)\n");
+        res.push_str("    let mut pc: usize = 0;\n");
+        res.push_str("    loop {\n");
+        res.push_str("        match pc {\n");
+        for (pc, (instruction, text)) in instructions.iter().enumerate() {
+            res.push_str(&format!("            // {}\n", text));
+            res.push_str(&format!("            {} => {{\n", pc));
+            res.push_str(&statement(instruction, pc, text));
+            res.push_str("            },\n");
+        }
+        res.push_str("            _ => {\n");
+        res.push_str("                eprintln!(\"Error: Instruction {} doesn't exist (missing END?)\", pc + 1);\n");
+        res.push_str("                exit(1);\n");
+        res.push_str("            },\n");
+        res.push_str("        }\n");
+        res.push_str("    }\n");
+        res.push_str("}\n");
+        res
+    }
+
+    fn statement(instruction: &Lowered, pc: usize, text: &str) -> String {
+        let indent = "                ";
+        let context = format!("{}, \"{}\"", pc + 1, text);
+        match instruction {
+            Lowered::Load(operand) => format!(
+                "{indent}let value = {};\n{indent}r.set(0, value);\n{indent}pc = {};\n",
+                operand_value(operand, &context), pc + 1,
+            ),
+            Lowered::Store { register, indirect: false } =>
+                format!("{indent}r.set({}, r.peek(0));\n{indent}pc = {};\n", register, pc + 1),
+            Lowered::Store { register, indirect: true } => format!(
+                "{indent}let address = r.pointer({}, {});\n{indent}r.set(address, r.peek(0));\n{indent}pc = {};\n",
+                context, register, pc + 1,
+            ),
+            Lowered::Arithmetic(operation, operand) => {
+                let result = match operation {
+                    Operation::Add => format!("add({}, acc, value)", context),
+                    Operation::Sub => "sub(acc, value)".to_string(),
+                    Operation::Mul => format!("mul({}, acc, value)", context),
+                    Operation::Div => "div(acc, value)".to_string(),
+                };
+                format!(
+                    "{indent}let acc = r.peek(0);\n{indent}let value = {};\n{indent}r.set(0, {});\n{indent}pc = {};\n",
+                    operand_value(operand, &context), result, pc + 1,
+                )
+            },
+            Lowered::Jump(target) => format!("{indent}{}\n", jump(*target, &context)),
+            Lowered::CondJump(comparison, value, target) => format!(
+                "{indent}if r.peek(0) {} {} {{\n{indent}    {}\n{indent}}} else {{\n{indent}    pc = {};\n{indent}}}\n",
+                comparison_operator(*comparison), value, jump(*target, &context), pc + 1,
+            ),
+            Lowered::End => format!("{indent}r.print();\n{indent}return;\n"),
+        }
+    }
+
+    fn operand_value(operand: &Operand, context: &str) -> String {
+        match operand {
+            Operand::Direct(register) => format!("r.peek({})", register),
+            Operand::Indirect(register) => format!("{{\n                    let address = r.pointer({}, {});\n                    r.peek(address)\n                }}", context, register),
+            Operand::Constant(value) => format!("{}u128", value),
+        }
+    }
+
+    fn jump(target: usize, context: &str) -> String {
+        match target {
+            0 => format!("fail({}, \"invalid jump target 0\");", context),
+            _ => format!("pc = {};", target - 1),
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+/// Values a register can hold.
+///
+/// Everything that touches register contents (the state, the instructions and the parser)
+/// is generic over this trait, so the machine can run on fixed-size integers as well as on
+/// arbitrary-precision ones (see the `bigint` feature).
+pub trait Word: Clone + Debug + Display + Default + Eq + Ord + Hash + 'static {
+    /// Whether the type can hold negative values
+    const SIGNED: bool;
+
+    /// Whether the type has an upper bound (wrapping arithmetic needs one to wrap around at)
+    const BOUNDED: bool;
+
+    /// The value 0
+    fn zero() -> Self;
+
+    /// Checks whether the value is 0
+    fn is_zero(&self) -> bool {
+        *self == Self::zero()
+    }
+
+    /// Checks whether the value is less than 0
+    fn is_negative(&self) -> bool {
+        *self < Self::zero()
+    }
+
+    /// Converts a register index or jump address into a word
+    fn from_usize(value: usize) -> Self;
+
+    /// Converts a word into a register index. Returns `None` if it doesn't fit into a `usize`.
+    fn to_usize(&self) -> Option<usize>;
+
+    /// Parses a decimal number
+    fn parse_word(str: &str) -> Result<Self, String>;
+
+    /// Number of bits of the absolute value (0 for 0)
+    fn bits(&self) -> u64;
+
+    /// Addition. Returns `None` on overflow.
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    /// Subtraction. Returns `None` if the result isn't representable.
+    fn checked_sub(&self, other: &Self) -> Option<Self>;
+
+    /// Multiplication. Returns `None` on overflow.
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    /// Integer division. Returns `None` if `other` is 0 or on overflow.
+    fn checked_div(&self, other: &Self) -> Option<Self>;
+
+    /// Addition modulo the type's range. Returns `None` if the type has no bounds to wrap at.
+    fn wrapping_add(&self, other: &Self) -> Option<Self>;
+
+    /// Subtraction modulo the type's range. Returns `None` if the type has no bounds to wrap at.
+    fn wrapping_sub(&self, other: &Self) -> Option<Self>;
+
+    /// Multiplication modulo the type's range. Returns `None` if the type has no bounds to wrap at.
+    fn wrapping_mul(&self, other: &Self) -> Option<Self>;
+
+    /// Division modulo the type's range (`other` must not be 0). Returns `None` if the type has
+    /// no bounds to wrap at.
+    fn wrapping_div(&self, other: &Self) -> Option<Self>;
+
+    /// Addition clamped to the type's range. Unbounded types only clamp at 0 (if they're unsigned).
+    fn saturating_add(&self, other: &Self) -> Self;
+
+    /// Subtraction clamped to the type's range
+    fn saturating_sub(&self, other: &Self) -> Self;
+
+    /// Multiplication clamped to the type's range
+    fn saturating_mul(&self, other: &Self) -> Self;
+
+    /// Division clamped to the type's range (`other` must not be 0).
+    fn saturating_div(&self, other: &Self) -> Self;
+}
+
+/// Implements `Word` for primitive integer types
+macro_rules! impl_primitive_word {
+    ( $( $type:ident, $signed:expr ), * ) => {
+        $(
+            impl Word for $type {
+                const SIGNED: bool = $signed;
+                const BOUNDED: bool = true;
+
+                fn zero() -> Self {
+                    0
+                }
+
+                fn from_usize(value: usize) -> Self {
+                    value as $type
+                }
+
+                fn to_usize(&self) -> Option<usize> {
+                    usize::try_from(*self).ok()
+                }
+
+                fn parse_word(str: &str) -> Result<Self, String> {
+                    str.parse::<$type>().map_err(|e| e.to_string())
+                }
+
+                fn bits(&self) -> u64 {
+                    (128 - self.abs_diff(0).leading_zeros()) as u64
+                }
+
+                fn checked_add(&self, other: &Self) -> Option<Self> {
+                    $type::checked_add(*self, *other)
+                }
+
+                fn checked_sub(&self, other: &Self) -> Option<Self> {
+                    $type::checked_sub(*self, *other)
+                }
+
+                fn checked_mul(&self, other: &Self) -> Option<Self> {
+                    $type::checked_mul(*self, *other)
+                }
+
+                fn checked_div(&self, other: &Self) -> Option<Self> {
+                    $type::checked_div(*self, *other)
+                }
+
+                fn wrapping_add(&self, other: &Self) -> Option<Self> {
+                    Some($type::wrapping_add(*self, *other))
+                }
+
+                fn wrapping_sub(&self, other: &Self) -> Option<Self> {
+                    Some($type::wrapping_sub(*self, *other))
+                }
+
+                fn wrapping_mul(&self, other: &Self) -> Option<Self> {
+                    Some($type::wrapping_mul(*self, *other))
+                }
+
+                fn wrapping_div(&self, other: &Self) -> Option<Self> {
+                    Some($type::wrapping_div(*self, *other))
+                }
+
+                fn saturating_add(&self, other: &Self) -> Self {
+                    $type::saturating_add(*self, *other)
+                }
+
+                fn saturating_sub(&self, other: &Self) -> Self {
+                    $type::saturating_sub(*self, *other)
+                }
+
+                fn saturating_mul(&self, other: &Self) -> Self {
+                    $type::saturating_mul(*self, *other)
+                }
+
+                fn saturating_div(&self, other: &Self) -> Self {
+                    $type::saturating_div(*self, *other)
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive_word![
+    u128,   false,
+    i128,   true
+];
+
+#[cfg(feature = "bigint")]
+mod bigint {
+    use num_bigint::{BigInt, BigUint};
+    use num_traits::{CheckedSub, ToPrimitive, Zero};
+
+    use super::Word;
+
+    /// Implements the operations that can't overflow for arbitrary-precision types
+    macro_rules! impl_unbounded_ops {
+        () => {
+            fn zero() -> Self {
+                <Self as Zero>::zero()
+            }
+
+            fn is_zero(&self) -> bool {
+                Zero::is_zero(self)
+            }
+
+            fn from_usize(value: usize) -> Self {
+                Self::from(value)
+            }
+
+            fn to_usize(&self) -> Option<usize> {
+                ToPrimitive::to_usize(self)
+            }
+
+            fn parse_word(str: &str) -> Result<Self, String> {
+                str.parse::<Self>().map_err(|e| e.to_string())
+            }
+
+            fn bits(&self) -> u64 {
+                Self::bits(self)
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                Some(self + other)
+            }
+
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                Some(self * other)
+            }
+
+            fn checked_div(&self, other: &Self) -> Option<Self> {
+                if Zero::is_zero(other) {
+                    None
+                } else {
+                    Some(self / other)
+                }
+            }
+
+            // There are no bounds to wrap around at
+            fn wrapping_add(&self, _other: &Self) -> Option<Self> {
+                None
+            }
+
+            fn wrapping_sub(&self, _other: &Self) -> Option<Self> {
+                None
+            }
+
+            fn wrapping_mul(&self, _other: &Self) -> Option<Self> {
+                None
+            }
+
+            fn wrapping_div(&self, _other: &Self) -> Option<Self> {
+                None
+            }
+
+            fn saturating_add(&self, other: &Self) -> Self {
+                self + other
+            }
+
+            fn saturating_mul(&self, other: &Self) -> Self {
+                self * other
+            }
+
+            fn saturating_div(&self, other: &Self) -> Self {
+                self / other
+            }
+        };
+    }
+
+    impl Word for BigUint {
+        const SIGNED: bool = false;
+        const BOUNDED: bool = false;
+
+        impl_unbounded_ops!();
+
+        fn checked_sub(&self, other: &Self) -> Option<Self> {
+            CheckedSub::checked_sub(self, other)
+        }
+
+        fn saturating_sub(&self, other: &Self) -> Self {
+            CheckedSub::checked_sub(self, other).unwrap_or_default()
+        }
+    }
+
+    impl Word for BigInt {
+        const SIGNED: bool = true;
+        const BOUNDED: bool = false;
+
+        impl_unbounded_ops!();
+
+        fn checked_sub(&self, other: &Self) -> Option<Self> {
+            Some(self - other)
+        }
+
+        fn saturating_sub(&self, other: &Self) -> Self {
+            self - other
+        }
+    }
+}
+
+/// The default register value type.
+///
+/// `u128` unless the `bigint` feature is enabled, in which case registers are unbounded.
+#[cfg(not(feature = "bigint"))]
+pub type Value = u128;
+
+/// The default register value type.
+///
+/// `u128` unless the `bigint` feature is enabled, in which case registers are unbounded.
+#[cfg(feature = "bigint")]
+pub type Value = num_bigint::BigUint;
+
+/// The default register value type for signed arithmetic.
+///
+/// `i128` unless the `bigint` feature is enabled, in which case registers are unbounded.
+#[cfg(not(feature = "bigint"))]
+pub type SignedValue = i128;
+
+/// The default register value type for signed arithmetic.
+///
+/// `i128` unless the `bigint` feature is enabled, in which case registers are unbounded.
+#[cfg(feature = "bigint")]
+pub type SignedValue = num_bigint::BigInt;
@@ -0,0 +1,74 @@
+use std::io::BufReader;
+
+use ram_simulator::breakpoint::Breakpoint;
+use ram_simulator::run::{RunConfig, Termination};
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::RegisterMachine;
+
+/// Counts c(1) down from 3
+const COUNTDOWN: &str = "INIT 3\nLOAD 1\nCSUB 1\nSTORE 1\nIF c(0) > 0 THEN GOTO 1\nEND\n";
+
+fn machine(breakpoint: &str) -> RegisterMachine<u128> {
+    let mut ram = parse_buf_as::<u128, _>(BufReader::new(COUNTDOWN.as_bytes())).unwrap();
+    ram.add_breakpoint(Breakpoint::parse(breakpoint).unwrap());
+    ram
+}
+
+/// The steps the breakpoint stops at until the machine halts
+fn stops(ram: &mut RegisterMachine<u128>) -> Vec<usize> {
+    let mut stops = Vec::new();
+    loop {
+        match ram.run_until(std::io::sink(), &RunConfig::new()) {
+            Termination::Breakpoint(hit) => stops.push(hit.step),
+            Termination::Halted => return stops,
+            other => panic!("unexpected termination {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn line_breakpoints_stop_before_the_first_instruction() {
+    // `LOAD 1` is on line 2
+    assert_eq!(stops(&mut machine("line 2")), vec![0, 4, 8]);
+    assert_eq!(stops(&mut machine("line 1")), vec![]);
+}
+
+#[test]
+fn line_breakpoints_use_lines_of_the_source() {
+    let source = "; counts down\nINIT 3\n\nLOAD 1\nCSUB 1\nSTORE 1\nIF c(0) > 0 THEN GOTO 1\nEND\n";
+    let mut ram = parse_buf_as::<u128, _>(BufReader::new(source.as_bytes())).unwrap();
+    ram.add_breakpoint(Breakpoint::parse("line 5").unwrap());
+    assert_eq!(stops(&mut ram), vec![1, 5, 9]);
+}
+
+#[test]
+fn instruction_breakpoints_count_like_jump_targets() {
+    assert_eq!(stops(&mut machine("instr 1")), vec![0, 4, 8]);
+    assert_eq!(stops(&mut machine("instr 4")), vec![3, 7, 11]);
+    assert!(Breakpoint::<u128>::parse("instr 0").is_err());
+}
+
+#[test]
+fn conditions_are_checked_before_every_instruction() {
+    // c(1) = 3 holds from the start until the first STORE
+    assert_eq!(stops(&mut machine("if c(1) = 3")), vec![0, 1, 2]);
+}
+
+#[test]
+fn watchpoints_stop_after_the_access() {
+    assert_eq!(stops(&mut machine("watch write c(1)")), vec![3, 7, 11]);
+}
+
+#[test]
+fn breakpoints_fire_again_after_a_reset() {
+    let mut ram = machine("instr 1");
+    let initial = ram.get_state().clone();
+    let first = ram.run_until(std::io::sink(), &RunConfig::new());
+    assert!(matches!(first, Termination::Breakpoint(_)));
+
+    ram.reset();
+    assert_eq!(ram.run_until(std::io::sink(), &RunConfig::new()), first);
+
+    ram.set_state(initial);
+    assert_eq!(ram.run_until(std::io::sink(), &RunConfig::new()), first);
+}
@@ -0,0 +1,57 @@
+use std::io::BufReader;
+
+use ram_simulator::cost::{Cost, Space};
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::RegisterMachine;
+
+/// The `floor(ld(c(1)))` program from the readme
+const LOG2: &str = "INIT 256
+CLOAD 0
+STORE 2
+CLOAD 1
+STORE 3
+LOAD 3
+SUB 1
+IF c(0) > 0 THEN GOTO 15
+LOAD 2
+CADD 1
+STORE 2
+LOAD 3
+CMULT 2
+STORE 3
+GOTO 4
+LOAD 2
+CSUB 1
+STORE 1
+END
+";
+
+fn run(src: &str) -> RegisterMachine<u128> {
+    let mut machine = parse_buf_as(BufReader::new(src.as_bytes())).unwrap();
+    assert!(machine.run(std::io::sink()).is_halted());
+    machine
+}
+
+#[test]
+fn costs_of_single_instructions() {
+    let machine = run("INIT 5\nLOAD 1\nCADD 3\nSTORE 2\nEND\n");
+    // l(1) + l(5), l(5) + l(3), l(8) + l(2), 1
+    assert_eq!(machine.get_state().get_cost(), Cost { uniform: 4, logarithmic: 4 + 5 + 6 + 1 });
+    // r0 = 8, r1 = 5, r2 = 8
+    assert_eq!(machine.get_state().get_space(), Space { uniform: 3, logarithmic: 4 + 3 + 4 });
+}
+
+#[test]
+fn readme_program() {
+    let machine = run(LOG2);
+    let state = machine.get_state();
+    assert_eq!(state.peek_reg(1), 8);
+
+    // 4 instructions to set up, 11 per doubling of c(3) (from 1 to 512, GOTO 4 jumps back to STORE 3)
+    // and 7 to leave the loop and finish
+    assert_eq!(state.get_cost().uniform, 4 + 9 * 11 + 7);
+    assert!(state.get_cost().logarithmic > state.get_cost().uniform);
+
+    // Peaks after c(3) reached 512: r0 = 512, r1 = 256, r2 = 9, r3 = 512
+    assert_eq!(state.get_space(), Space { uniform: 4, logarithmic: 10 + 9 + 4 + 10 });
+}
@@ -0,0 +1,51 @@
+use std::io::BufReader;
+
+use ram_simulator::cycle::{LoopDetection, Repetition};
+use ram_simulator::run::{Budget, RunConfig, Termination};
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::RegisterMachine;
+
+// Returns to its initial configuration (PC 0, all registers 0) every 3 steps
+const SPIN: &str = "INIT 0\nCADD 1\nCSUB 1\nJMP 1\nEND\n";
+
+fn run(src: &str, mode: LoopDetection) -> (Termination, RegisterMachine<u128>) {
+    let mut machine = parse_buf_as(BufReader::new(src.as_bytes())).unwrap();
+    machine.set_loop_detection(Some(mode));
+    let termination = machine.run_with(std::io::sink(), &RunConfig::with_max_steps(1000));
+    (termination, machine)
+}
+
+#[test]
+fn hash_finds_the_first_repetition() {
+    let (termination, machine) = run(SPIN, LoopDetection::Hash);
+    assert_eq!(termination, Termination::Loop(Repetition { step: 3, repeats: 0 }));
+    assert_eq!(
+        termination.to_string(),
+        "The configuration at step 3 repeats step 0, the machine won't halt",
+    );
+    assert_eq!(machine.get_state().get_steps(), 3);
+}
+
+#[test]
+fn brent_finds_a_later_repetition() {
+    // Compares to the configurations of steps 0, 1 and 3, and finds the latter again at step 6
+    let (termination, _) = run(SPIN, LoopDetection::Brent);
+    assert_eq!(termination, Termination::Loop(Repetition { step: 6, repeats: 3 }));
+}
+
+#[test]
+fn growing_registers_dont_repeat() {
+    for mode in [LoopDetection::Hash, LoopDetection::Brent] {
+        let (termination, _) = run("INIT 0\nCADD 1\nJMP 1\nEND\n", mode);
+        assert_eq!(termination, Termination::BudgetExhausted(Budget::Steps(1000)), "{:?}", mode);
+    }
+}
+
+#[test]
+fn halting_programs_halt() {
+    for mode in [LoopDetection::Hash, LoopDetection::Brent] {
+        let (termination, machine) = run("INIT 2\nLOAD 1\nCSUB 1\nIF c(0) > 0 THEN GOTO 2\nEND\n", mode);
+        assert_eq!(termination, Termination::Halted, "{:?}", mode);
+        assert_eq!(machine.get_state().get_acc(), 0);
+    }
+}
@@ -0,0 +1,52 @@
+use std::io::BufReader;
+
+use ram_simulator::error::{ExecError, Fault};
+use ram_simulator::run::Termination;
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::RegisterMachine;
+
+fn machine(src: &str) -> RegisterMachine<u128> {
+    parse_buf_as(BufReader::new(src.as_bytes())).unwrap()
+}
+
+#[test]
+fn overflow_stops_the_machine() {
+    let mut machine = machine("INIT 340282366920938463463374607431768211455\nLOAD 1\nCADD 1\nEND\n");
+    let err = ExecError::Fault { pc: 1, instruction: "CADD 1".to_string(), cause: Fault::Overflow, line: Some(3) };
+    assert_eq!(machine.run(std::io::sink()), Termination::Fault(err.clone()));
+    assert_eq!(err.to_string(), "Line 3 (CADD 1): arithmetic overflow");
+    assert!(!machine.has_not_ended());
+    assert_eq!(machine.get_fault(), Some(&err));
+    assert_eq!(machine.step().unwrap_err(), err);
+}
+
+#[test]
+fn pointers_must_be_register_indices() {
+    let mut machine = machine("INIT 340282366920938463463374607431768211455\nINDLOAD 1\nEND\n");
+    assert_eq!(
+        machine.run(std::io::sink()).to_string(),
+        "Line 2 (INDLOAD 1): pointer 340282366920938463463374607431768211455 is not a valid register index",
+    );
+}
+
+#[test]
+fn jumps_are_one_based() {
+    let mut machine = machine("INIT\nGOTO 0\nEND\n");
+    assert!(matches!(
+        machine.step(),
+        Err(ExecError::Fault { pc: 0, cause: Fault::InvalidJump(0), .. })
+    ));
+}
+
+#[test]
+fn running_off_the_program_is_an_error() {
+    let mut machine = machine("INIT\nGOTO 3\nEND\n");
+    assert_eq!(machine.run(std::io::sink()), Termination::Fault(ExecError::PcOutOfRange { pc: 2 }));
+}
+
+#[test]
+fn halted_machines_dont_step() {
+    let mut machine = machine("INIT\nEND\n");
+    assert!(machine.step().is_ok());
+    assert_eq!(machine.step().unwrap_err(), ExecError::Halted);
+}
@@ -0,0 +1,66 @@
+use std::io::BufReader;
+
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::RegisterMachine;
+
+/// Uses every kind of operand, a taken and a failing jump
+const PROGRAM: &str = "\
+INIT 5 3 2
+LOAD 1
+CADD 3
+INDSUB 3
+STORE 4
+INDSTORE 3
+CMULT 2
+CDIV 0
+IF c(0) > 0 THEN GOTO 10
+IF c(0) = 0 THEN GOTO 11
+END
+JMP 0
+END
+";
+
+/// The explanations of the instructions the machine executes, until it stops
+fn explanations(source: &str) -> Vec<String> {
+    let ram = parse_buf_as::<u128, _>(BufReader::new(source.as_bytes())).unwrap();
+    explain_run(ram)
+}
+
+fn explain_run(mut ram: RegisterMachine<u128>) -> Vec<String> {
+    let mut explanations = Vec::new();
+    while let Some(explanation) = ram.explain_next() {
+        explanations.push(explanation);
+        if ram.step().is_err() {
+            break;
+        }
+    }
+    explanations
+}
+
+#[test]
+fn explanations_count_pcs_like_the_trace() {
+    assert_eq!(explanations(PROGRAM), [
+        "LOAD 1: c(0) := c(1) = 5, PC 0 → 1",
+        "CADD 3: c(0) := c(0) + 3 = 5 + 3 = 8, PC 1 → 2",
+        "INDSUB 3: c(0) := c(0) - c(c(3)) = c(0) - c(2) = 8 - 3 = 5, PC 2 → 3",
+        "STORE 4: c(4) := c(0) = 5, PC 3 → 4",
+        "INDSTORE 3: c(c(3)) = c(2) := c(0) = 5, PC 4 → 5",
+        "CMULT 2: c(0) := c(0) * 2 = 5 * 2 = 10, PC 5 → 6",
+        "CDIV 0: c(0) := c(0) div 0 = 10 div 0 = 0, PC 6 → 7",
+        "IF c(0) > 0: 0 > 0 is false, continue at line 10",
+        "IF c(0) = 0: 0 = 0 is true, continue at line 12",
+        "JMP 0: fails: invalid jump target 0",
+    ]);
+}
+
+#[test]
+fn jumps_continue_at_a_pc_without_a_source_map() {
+    let mut ram = parse_buf_as::<u128, _>(BufReader::new(PROGRAM.as_bytes())).unwrap();
+    ram.set_source_map(None);
+    assert_eq!(explain_run(ram)[7..9], ["IF c(0) > 0: 0 > 0 is false, continue at PC 8", "IF c(0) = 0: 0 = 0 is true, continue at PC 10"]);
+}
+
+#[test]
+fn halting_is_explained() {
+    assert_eq!(explanations("INIT\nCLOAD 7\nEND\n"), ["CLOAD 7: c(0) := 7, PC 0 → 1", "END: the machine halts"]);
+}
@@ -0,0 +1,43 @@
+use std::io::BufReader;
+
+use ram_simulator::run::{RunConfig, Termination};
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::text::format::{Annotated, JsonLines, TraceFormat};
+use ram_simulator::RegisterMachine;
+
+// The second STORE writes r2 again without changing it. Steps show the source lines.
+const COPY: &str = "INIT 5\nLOAD 1\nSTORE 2\nSTORE 2\nEND\n";
+
+fn trace(format: &mut dyn TraceFormat<u128>) -> String {
+    let mut machine: RegisterMachine<u128> = parse_buf_as(BufReader::new(COPY.as_bytes())).unwrap();
+    let mut output = Vec::new();
+    assert_eq!(machine.run_formatted(&mut output, &RunConfig::default(), format), Termination::Halted);
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn annotated_steps_show_instructions_and_changes() {
+    let lines: Vec<String> = trace(&mut Annotated::new(false, false)).lines().map(String::from).collect();
+    assert_eq!(lines[0], "Initial Configuration -- r0: 0, r1: 5");
+    assert_eq!(lines[1], "Step  1 --  2: LOAD 1           | r0: 0 -> 5 | PC:  1, r0: 5, r1: 5");
+    assert_eq!(lines[2], "Step  2 --  3: STORE 2          | r2: 0 -> 5 | PC:  2, r0: 5, r1: 5, r2: 5");
+    assert_eq!(lines[3], "Step  3 --  4: STORE 2          | - | PC:  3, r0: 5, r1: 5, r2: 5");
+}
+
+#[test]
+fn changes_only_leaves_out_the_registers() {
+    let trace = trace(&mut Annotated::new(false, true));
+    assert_eq!(trace.lines().nth(2).unwrap(), "Step  2 --  3: STORE 2          | r2: 0 -> 5 | PC:  2");
+}
+
+#[test]
+fn json_lines_contain_the_instruction_and_changes() {
+    let trace = trace(&mut JsonLines);
+    let lines: Vec<&str> = trace.lines().collect();
+    assert_eq!(lines[0], "{\"step\":0,\"pc\":0,\"registers\":{\"0\":0,\"1\":5}}");
+    assert_eq!(
+        lines[2],
+        "{\"step\":2,\"pc\":2,\"line\":3,\"instruction\":\"STORE 2\",\"changes\":[{\"register\":2,\"old\":0,\"new\":5}],\"registers\":{\"0\":5,\"1\":5,\"2\":5}}"
+    );
+    assert!(lines[3].contains("\"changes\":[]"), "{}", lines[3]);
+}
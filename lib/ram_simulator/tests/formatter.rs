@@ -0,0 +1,75 @@
+use std::io::BufReader;
+
+use ram_simulator::instruction::StringRepr;
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::text::formatter::format;
+
+#[test]
+fn comments_before_the_mnemonic_stay_in_front_of_it() {
+    let source = "INIT\nstart: /* block */ if c(0)=0 then goto start\n/* a */ load /* b */ 1 ; c\nEND\n";
+    let expected = "\
+INIT
+start:  /* block */ IF c(0) = 0 THEN GOTO start
+        /* a */ LOAD /* b */ 1  ; c
+        END
+";
+    assert_eq!(format(source), expected);
+    assert_eq!(format(expected), expected);
+}
+
+/// Programs in every shape the formatter has to handle
+const PROGRAMS: [&str; 4] = [
+    include_str!("../../../src/examples/example3.s"),
+    "\
+; counts down
+
+
+init 3 ; start
+.REG n = 1
+loop: LOAD n   // the counter
+  csub 1
+a_very_long_label: STORE n
+if c(0)>0 then goto loop
+/* done,
+   halt */ END
+",
+    "INIT\n.macro twice r\n  LOAD r\n  ADD r ; double\n  STORE r\n.endm\n\ttwice 1\nEND",
+    "INIT\n/* unterminated\nLOAD 1\n",
+];
+
+#[test]
+fn formatting_is_idempotent() {
+    for program in PROGRAMS {
+        let formatted = format(program);
+        assert_eq!(format(&formatted), formatted, "{}", program);
+    }
+}
+
+#[test]
+fn formatting_keeps_the_program() {
+    let parse = |source: &str| {
+        let ram = parse_buf_as::<u128, _>(BufReader::new(source.as_bytes())).unwrap();
+        ram.get_program().instructions.iter().map(StringRepr::to_string).collect::<Vec<String>>()
+    };
+    for program in &PROGRAMS[..3] {
+        assert_eq!(parse(&format(program)), parse(program), "{}", program);
+    }
+}
+
+#[test]
+fn layout() {
+    let expected = "\
+; counts down
+
+INIT 3           ; start
+.reg n = 1
+loop:   LOAD  n  // the counter
+        CSUB  1
+a_very_long_label:
+        STORE n
+        IF c(0) > 0 THEN GOTO loop
+/* done,
+   halt */ END
+";
+    assert_eq!(format(PROGRAMS[1]), expected);
+}
@@ -0,0 +1,69 @@
+use std::io::BufReader;
+
+use ram_simulator::error::ExecError;
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::RegisterMachine;
+
+/// Counts c(1) down from 3
+const COUNTDOWN: &str = "INIT 3\nLOAD 1\nCSUB 1\nSTORE 1\nIF c(0) > 0 THEN GOTO 1\nEND\n";
+
+fn machine(src: &str, checkpoint_interval: Option<usize>) -> RegisterMachine<u128> {
+    let mut ram = parse_buf_as(BufReader::new(src.as_bytes())).unwrap();
+    ram.set_history(checkpoint_interval);
+    ram
+}
+
+/// Steps the machine until it halts and returns every state on the way
+fn states(ram: &mut RegisterMachine<u128>) -> Vec<String> {
+    // Going back to the start leaves the machine running
+    let mut states = vec![ram.get_state().to_wasm_comm_str().replacen('s', "r", 1)];
+    while ram.step().is_ok() {
+        states.push(ram.get_state().to_wasm_comm_str());
+    }
+    states
+}
+
+#[test]
+fn stepping_back_retraces_the_run() {
+    for interval in [1, 3, 100] {
+        let mut ram = machine(COUNTDOWN, Some(interval));
+        let mut states = states(&mut ram);
+        assert_eq!(states.len(), 14);
+
+        states.pop();
+        while let Some(expected) = states.pop() {
+            assert_eq!(ram.step_back().unwrap().to_wasm_comm_str(), expected, "interval {}", interval);
+        }
+        assert_eq!(ram.step_back().unwrap_err(), ExecError::NoPreviousStep);
+    }
+}
+
+#[test]
+fn seeking_goes_both_ways() {
+    let mut ram = machine(COUNTDOWN, Some(4));
+    let states = states(&mut ram);
+
+    for step in [5, 0, 13, 7, 7, 2] {
+        assert_eq!(ram.seek(step).unwrap().to_wasm_comm_str(), states[step], "step {}", step);
+    }
+
+    // Seeking past the end stops at the END instruction
+    assert_eq!(ram.seek(100).unwrap().get_steps(), 13);
+}
+
+#[test]
+fn stepping_back_out_of_a_fault() {
+    let mut ram = machine("INIT 340282366920938463463374607431768211455\nLOAD 1\nCADD 1\nEND\n", Some(2));
+    ram.step().unwrap();
+    let before = ram.get_state().to_wasm_comm_str();
+    assert!(ram.step().is_err());
+    assert_eq!(ram.step_back().unwrap().to_wasm_comm_str(), before);
+    assert!(ram.get_fault().is_none());
+}
+
+#[test]
+fn nothing_is_recorded_without_a_history() {
+    let mut ram = machine(COUNTDOWN, None);
+    ram.step().unwrap();
+    assert_eq!(ram.step_back().unwrap_err(), ExecError::NotRecorded { step: 0 });
+}
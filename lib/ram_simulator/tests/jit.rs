@@ -0,0 +1,186 @@
+#![cfg(feature = "jit")]
+
+use std::io::BufReader;
+
+use ram_simulator::cost::CostMeasure;
+use ram_simulator::error::{ExecError, Fault};
+use ram_simulator::run::{Budget, RunConfig, Termination};
+use ram_simulator::semantics::{Arithmetic, DivisionByZero, Semantics};
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::RegisterMachine;
+
+/// Computes `ceil(ld(c(1)))`
+const EXAMPLE3: &str = include_str!("../../../src/examples/example3.s");
+
+/// Uses every instruction (direct, indirect and constant operands) in a loop
+const EVERY_OP: &str = "\
+INIT 7 3 9
+CLOAD 10
+STORE 4
+LOAD 4
+CSUB 1
+STORE 4
+LOAD 1
+ADD 2
+CADD 5
+INDADD 3
+STORE 5
+SUB 2
+INDSUB 3
+CMULT 3
+MULT 2
+INDMULT 3
+CDIV 2
+DIV 2
+INDDIV 3
+STORE 6
+INDLOAD 3
+INDSTORE 3
+LOAD 4
+IF c(0) > 0 THEN GOTO 3
+IF c(0) = 0 THEN GOTO 25
+END
+JMP 26
+";
+
+/// Stores to a register beyond the array of the compiled code, which the interpreter has to do
+const FAR_REGISTER: &str = "\
+INIT 2000000
+CLOAD 42
+INDSTORE 1
+CLOAD 7
+STORE 2
+INDLOAD 1
+ADD 2
+STORE 3
+END
+";
+
+fn machine(program: &str, semantics: Semantics) -> RegisterMachine<u128> {
+    let mut ram = parse_buf_as::<u128, _>(BufReader::new(program.as_bytes())).unwrap();
+    ram.set_semantics(semantics).unwrap();
+    ram
+}
+
+/// Runs `program` with the JIT, checked against the interpreter
+fn run(program: &str, semantics: Semantics, config: &RunConfig) -> Termination {
+    machine(program, semantics).run_jit_checked(config).unwrap()
+}
+
+fn all_semantics() -> impl Iterator<Item = Semantics> {
+    let arithmetic = [Arithmetic::Monus, Arithmetic::Wrapping, Arithmetic::Saturating, Arithmetic::Trapping];
+    arithmetic.into_iter().flat_map(|arithmetic| {
+        [DivisionByZero::Zero, DivisionByZero::Trap].map(|division_by_zero| Semantics::new(arithmetic, division_by_zero))
+    })
+}
+
+#[test]
+fn example_programs_match_the_interpreter() {
+    for program in [EXAMPLE3, EVERY_OP] {
+        assert_eq!(run(program, Semantics::default(), &RunConfig::new()), Termination::Halted);
+        // Both count below 0 and divide by 0, so some of these fault (the same way in both)
+        for semantics in all_semantics() {
+            let _ = run(program, semantics, &RunConfig::new());
+        }
+    }
+}
+
+#[test]
+fn registers_match_the_interpreter() {
+    let mut ram = machine(EXAMPLE3, Semantics::default());
+    assert_eq!(ram.run_jit(&RunConfig::new()), Ok(Termination::Halted));
+    // ceil(ld(256))
+    assert_eq!(ram.get_state().peek_reg(1), 8);
+}
+
+#[test]
+fn arithmetic_edge_cases_match_the_interpreter() {
+    let programs = [
+        // Subtraction below 0
+        "INIT 3 5\nLOAD 1\nSUB 2\nSTORE 3\nEND\n",
+        // Overflow of a multiplication
+        "INIT 340282366920938463463374607431768211455\nLOAD 1\nCMULT 2\nSTORE 2\nEND\n",
+        // Overflow of an addition
+        "INIT 340282366920938463463374607431768211455\nLOAD 1\nCADD 1\nSTORE 2\nEND\n",
+        // Division by 0
+        "INIT 9 0\nLOAD 1\nDIV 2\nSTORE 3\nEND\n",
+        // Divisions with operands beyond 64 bits
+        "INIT 340282366920938463463374607431768211455 18446744073709551616\nLOAD 1\nDIV 2\nSTORE 3\nLOAD 1\nCDIV 0\nEND\n",
+    ];
+    for program in programs {
+        for semantics in all_semantics() {
+            // `run` checks the outcome, whatever it is
+            let _ = run(program, semantics, &RunConfig::new());
+        }
+    }
+}
+
+#[test]
+fn multiplications_overflow_exactly_beyond_u128() {
+    // 2^64 - 1, 2^64, 2^63 and 2^127
+    let products = [
+        ("18446744073709551615", "18446744073709551615", true),
+        ("18446744073709551616", "9223372036854775808", true),
+        ("18446744073709551616", "18446744073709551616", false),
+        ("170141183460469231731687303715884105728", "2", false),
+        ("170141183460469231731687303715884105727", "2", true),
+        ("340282366920938463463374607431768211455", "1", true),
+        ("18446744073709551617", "18446744073709551615", true),
+        ("18446744073709551617", "18446744073709551616", false),
+    ];
+    for (a, b, fits) in products {
+        let program = format!("INIT {} {}\nLOAD 1\nMULT 2\nSTORE 3\nEND\n", a, b);
+        let termination = run(&program, Semantics::default(), &RunConfig::new());
+        assert_eq!(termination == Termination::Halted, fits, "{} * {}", a, b);
+    }
+}
+
+#[test]
+fn faults_match_the_interpreter() {
+    let trap = Semantics::new(Arithmetic::Monus, DivisionByZero::Trap);
+    let cause = |termination| match termination {
+        Termination::Fault(ExecError::Fault { pc, cause, .. }) => (pc, cause),
+        other => panic!("unexpected termination {:?}", other),
+    };
+
+    assert_eq!(cause(run("INIT 9 0\nLOAD 1\nDIV 2\nEND\n", trap, &RunConfig::new())), (1, Fault::DivisionByZero));
+    assert_eq!(
+        cause(run("INIT 340282366920938463463374607431768211455\nLOAD 1\nCADD 1\nEND\n", trap, &RunConfig::new())),
+        (1, Fault::Overflow),
+    );
+    assert_eq!(cause(run("INIT\nCLOAD 1\nJMP 0\nEND\n", trap, &RunConfig::new())), (1, Fault::InvalidJump(0)));
+    assert!(matches!(
+        run("INIT\nCLOAD 1\nJMP 9\nEND\n", trap, &RunConfig::new()),
+        Termination::Fault(ExecError::Fault { cause: Fault::InvalidJump(_), .. }) | Termination::Fault(ExecError::PcOutOfRange { .. }),
+    ));
+}
+
+#[test]
+fn budgets_match_the_interpreter() {
+    for steps in [0, 1, 7, 40] {
+        assert_eq!(
+            run(EVERY_OP, Semantics::default(), &RunConfig::with_max_steps(steps)),
+            Termination::BudgetExhausted(Budget::Steps(steps)),
+        );
+    }
+
+    for measure in [CostMeasure::Uniform, CostMeasure::Logarithmic] {
+        let config = RunConfig { max_cost: Some(50), cost_measure: measure, ..RunConfig::new() };
+        assert_eq!(run(EVERY_OP, Semantics::default(), &config), Termination::BudgetExhausted(Budget::Cost(measure, 50)));
+    }
+}
+
+#[test]
+fn far_registers_are_handed_to_the_interpreter() {
+    let mut ram = machine(FAR_REGISTER, Semantics::default());
+    assert_eq!(ram.run_jit_checked(&RunConfig::new()), Ok(Termination::Halted));
+    assert_eq!(ram.get_state().peek_reg(2000000), 42);
+    assert_eq!(ram.get_state().peek_reg(3), 49);
+}
+
+#[test]
+fn runs_can_be_resumed() {
+    let mut ram = machine(EVERY_OP, Semantics::default());
+    while let Ok(Termination::BudgetExhausted(_)) = ram.run_jit_checked(&RunConfig::with_max_steps(13)) {}
+    assert!(!ram.get_state().is_running());
+}
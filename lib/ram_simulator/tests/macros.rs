@@ -0,0 +1,38 @@
+use std::io::BufReader;
+
+use ram_simulator::error::{ParseErrorKind, ParseErrors};
+use ram_simulator::run::{RunConfig, Termination};
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::RegisterMachine;
+
+fn parse(source: &str) -> Result<RegisterMachine<u128>, ParseErrors> {
+    parse_buf_as::<u128, _>(BufReader::new(source.as_bytes()))
+}
+
+#[test]
+fn parameters_are_only_replaced_in_operands() {
+    // `store` and `load` are the instructions in the mnemonic position and parameters after it
+    let source = "\
+INIT 7
+.macro copy load store
+load load
+store store
+.endm
+copy 1 2
+END
+";
+    let mut ram = parse(source).unwrap();
+    assert_eq!(ram.run_to_completion(&RunConfig::new()).termination, Termination::Halted);
+    assert_eq!(ram.get_state().peek_reg(2), 7);
+}
+
+#[test]
+fn parameters_cant_be_part_of_if() {
+    for param in ["c", "C", "then", "GOTO"] {
+        let source = format!("INIT\n.macro skip {}\nIF c(0) = 0 THEN GOTO 1\n.endm\nEND\n", param);
+        let errors = parse(&source).err().unwrap().errors;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ParseErrorKind::InvalidMacro);
+        assert_eq!(errors[0].message, format!("{} is part of IF, it can't be a parameter", param));
+    }
+}
@@ -0,0 +1,231 @@
+use std::io::BufReader;
+
+use ram_simulator::error::{ParseError, ParseErrorKind, ParseErrors};
+use ram_simulator::instruction::StringRepr;
+use ram_simulator::run::{RunConfig, Termination};
+use ram_simulator::text::deserializer::{parse_buf_as, parse_file_as};
+use ram_simulator::text::source_map::Span;
+use ram_simulator::RegisterMachine;
+
+fn parse(source: &str) -> RegisterMachine<u128> {
+    parse_buf_as::<u128, _>(BufReader::new(source.as_bytes())).unwrap()
+}
+
+/// The errors of a program that doesn't parse
+fn errors(source: &str) -> Vec<ParseError> {
+    match parse_buf_as::<u128, _>(BufReader::new(source.as_bytes())) {
+        Ok(_) => panic!("the program parses"),
+        Err(errors) => errors.errors,
+    }
+}
+
+/// The only error of a program that doesn't parse
+fn error(source: &str) -> ParseError {
+    let mut errors = errors(source);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    errors.remove(0)
+}
+
+#[test]
+fn tokens_after_an_operand_are_errors() {
+    let spaced = error("INIT\n.const BASE = 10\nCLOAD BASE * 2\nEND\n");
+    assert_eq!(spaced.kind, ParseErrorKind::UnexpectedToken);
+    assert_eq!(spaced.message, "Unexpected * after CLOAD BASE");
+    assert_eq!(spaced.suggestion.as_deref(), Some("did you mean CLOAD BASE*2?"));
+    assert_eq!(spaced.span, Some(Span { line: 3, start: 12, end: 13 }));
+
+    for source in ["INIT\nLOAD 1 2\nEND\n", "INIT\nJMP 1 2\nEND\n", "INIT\nEND now\n"] {
+        assert_eq!(error(source).kind, ParseErrorKind::UnexpectedToken, "{}", source);
+    }
+}
+
+#[test]
+fn conditions_must_be_the_whole_line() {
+    let junk = error("INIT\nIF c(0) = 0 THEN GOTO 1 junk\nEND\n");
+    assert_eq!(junk.kind, ParseErrorKind::UnexpectedToken);
+    assert_eq!(junk.message, "Unexpected junk after IF c(0) = 0 THEN GOTO 1");
+
+    assert_eq!(error("INIT\nIF c(0) = 0 THEN GOTO 1x\nEND\n").kind, ParseErrorKind::InvalidCondition);
+    parse("INIT\nIF c(0) = 0 THEN GOTO 1 ; comment\nEND\n");
+}
+
+#[test]
+fn tokens_after_directives_and_macro_arguments_are_errors() {
+    let sources = [
+        "INIT\n.const BASE = 10 junk\nEND\n",
+        "INIT\n.reg n = 1 + 1\nEND\n",
+        "INIT\n.include \"lib.ram\" junk\nEND\n",
+        "INIT\n.macro two a b\nLOAD a\n.endm\ntwo 1 2 3\nEND\n",
+    ];
+    for source in sources {
+        assert_eq!(error(source).kind, ParseErrorKind::UnexpectedToken, "{}", source);
+    }
+
+    assert_eq!(error("INIT\n.reg n = 1 + 1\nEND\n").suggestion.as_deref(), Some("did you mean .reg n = 1+1?"));
+    assert_eq!(error("INIT\n.macro m a 1b\n.endm\nEND\n").message, "Invalid parameter name 1b");
+}
+
+/// The instructions of a program, as text
+fn instructions(source: &str) -> Vec<String> {
+    parse(source).get_program().instructions.iter().map(StringRepr::to_string).collect()
+}
+
+#[test]
+fn errors_render_with_an_excerpt() {
+    let source = "INIT\n\tLOADX 1\nEND\n";
+    let errors = parse_buf_as::<u128, _>(BufReader::new(source.as_bytes())).err().unwrap();
+    assert_eq!(errors.errors[0].span, Some(Span { line: 2, start: 2, end: 7 }));
+    assert_eq!(
+        errors.render(source, "program.s"),
+        "error: Unknown instruction LOADX\n --> program.s:2:2\n  |\n2 |     LOADX 1\n  |     ^^^^^ did you mean LOAD?\n",
+    );
+    assert_eq!(errors.to_string(), "Line 2, column 2: Unknown instruction LOADX (did you mean LOAD?)");
+}
+
+#[test]
+fn errors_are_collected_in_order() {
+    let kinds: Vec<ParseErrorKind> = errors("INIT\nGOTO nowhere\nLOAD x\nCLOAD\nEND\n").iter().map(|error| error.kind).collect();
+    assert_eq!(kinds, [ParseErrorKind::UndefinedLabel, ParseErrorKind::UndefinedName, ParseErrorKind::MissingArgument]);
+}
+
+#[test]
+fn operand_errors_suggest_fixes() {
+    let errors = errors("INIT\nLOAD c(c(1))\nCLOAD c(2)\nCADD\nEND\n");
+    assert_eq!(errors[0].suggestion.as_deref(), Some("did you mean INDLOAD 1?"));
+    assert_eq!(errors[1].kind, ParseErrorKind::UndefinedName);
+    assert_eq!(errors[1].suggestion.as_deref(), Some("did you mean LOAD 2?"));
+    assert_eq!(errors[2].message, "CADD needs an argument");
+    assert_eq!(errors[2].suggestion.as_deref(), Some("e.g. CADD 1"));
+}
+
+#[test]
+fn labels_resolve_forwards_and_backwards() {
+    let source = "INIT\nloop: LOAD 1\nIF c(0) = 0 THEN GOTO done\nGOTO loop\ndone:\nEND\n";
+    assert_eq!(instructions(source)[1..3], ["IF c(0) = 0 THEN GOTO 4", "JMP 1"]);
+}
+
+#[test]
+fn undefined_and_duplicate_labels_are_errors() {
+    let undefined = error("INIT\nloop: LOAD 1\nGOTO lop\nEND\n");
+    assert_eq!(undefined.kind, ParseErrorKind::UndefinedLabel);
+    assert_eq!(undefined.span, Some(Span { line: 3, start: 6, end: 9 }));
+    assert_eq!(undefined.suggestion.as_deref(), Some("did you mean loop?"));
+
+    let duplicate = error("INIT\nloop: LOAD 1\nloop: END\n");
+    assert_eq!(duplicate.kind, ParseErrorKind::DuplicateLabel);
+    assert_eq!(duplicate.span, Some(Span { line: 3, start: 1, end: 6 }));
+}
+
+#[test]
+fn conditions_end_with_their_target() {
+    assert_eq!(error("INIT\nIF c(0) = 0 THEN GOTO 1x\nEND\n").kind, ParseErrorKind::InvalidCondition);
+    assert_eq!(instructions("INIT\nif C(0) >= 2 then goto 1\nEND\n")[0], "IF c(0) >= 2 THEN GOTO 1");
+}
+
+#[test]
+fn expressions_follow_precedence() {
+    let source = "\
+INIT 0x10 0b101 1_000
+.const A = 2+3*4
+.const B = (2+3)*4
+CLOAD A
+CLOAD B
+CLOAD B/A-1
+CLOAD 20-A
+CLOAD 0xff
+END
+";
+    assert_eq!(instructions(source)[..5], ["CLOAD 14", "CLOAD 20", "CLOAD 0", "CLOAD 6", "CLOAD 255"]);
+
+    let mut ram = parse(source);
+    ram.run_to_completion(&RunConfig::new());
+    assert_eq!([1, 2, 3].map(|i| ram.get_state().peek_reg(i)), [16, 5, 1000]);
+}
+
+#[test]
+fn invalid_expressions_are_errors() {
+    for (source, message) in [
+        ("INIT\nCLOAD 0b102\nEND\n", "CLOAD needs an integer constant [invalid digit '2' in 0b102]"),
+        ("INIT\nCLOAD (1+2\nEND\n", "CLOAD needs an integer constant [missing ')']"),
+        ("INIT\nCLOAD 1-2\nEND\n", "CLOAD needs an integer constant [the expression is out of range]"),
+        ("INIT\nCLOAD 1/0\nEND\n", "CLOAD needs an integer constant [the expression divides by zero]"),
+    ] {
+        assert_eq!(error(source).message, message);
+    }
+}
+
+#[test]
+fn blank_and_comment_lines_dont_shift_jumps() {
+    let source = "\
+; counts c(1) down
+
+INIT 3  // the counter
+/* a comment
+   over lines */
+LOAD 1  # line 1
+CSUB 1
+
+STORE 1 /* stored */
+IF c(0) > 0 THEN GOTO 1
+END
+";
+    let plain = "INIT 3\nLOAD 1\nCSUB 1\nSTORE 1\nIF c(0) > 0 THEN GOTO 1\nEND\n";
+    assert_eq!(instructions(source), instructions(plain));
+
+    let mut ram = parse(source);
+    assert_eq!(ram.run_to_completion(&RunConfig::new()).termination, Termination::Halted);
+    assert_eq!(ram.get_state().get_steps(), 13);
+}
+
+#[test]
+fn unterminated_comments_are_errors() {
+    // It swallows the END
+    let errors = errors("INIT\nLOAD 1 /* open\nEND\n");
+    assert_eq!(errors[0].kind, ParseErrorKind::UnterminatedComment);
+    assert_eq!(errors[0].span, Some(Span { line: 2, start: 8, end: 10 }));
+    assert_eq!(errors[1].kind, ParseErrorKind::MissingEnd);
+}
+
+/// Writes `files` (paths relative to a directory of their own) and parses the first one
+fn parse_files(name: &str, files: &[(&str, &str)]) -> Result<RegisterMachine<u128>, ParseErrors> {
+    let dir = std::env::temp_dir().join(format!("ram-includes-{}-{}", std::process::id(), name));
+    for (path, source) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, source).unwrap();
+    }
+
+    let res = parse_file_as::<u128>(dir.join(files[0].0));
+    std::fs::remove_dir_all(dir).unwrap();
+    res
+}
+
+#[test]
+fn includes_are_relative_to_the_including_file() {
+    let files = [
+        ("main.s", "INIT 4\n.include \"lib/double.ram\"\ndouble 1\nEND\n"),
+        ("lib/double.ram", ".include \"sum.ram\"\n.macro double r\nsum r r\n.endm\n"),
+        ("lib/sum.ram", ".macro sum r s\nLOAD r\nADD s\nSTORE r\n.endm\n"),
+    ];
+    let mut ram = parse_files("relative", &files).unwrap();
+    assert_eq!(ram.run_to_completion(&RunConfig::new()).termination, Termination::Halted);
+    assert_eq!(ram.get_state().peek_reg(1), 8);
+}
+
+#[test]
+fn include_cycles_are_errors() {
+    let files = [
+        ("main.s", "INIT\n.include \"a.ram\"\nEND\n"),
+        ("a.ram", ".include \"lib/b.ram\"\n"),
+        ("lib/b.ram", ".include \"../a.ram\"\n"),
+    ];
+    let errors = parse_files("cycle", &files).err().unwrap().errors;
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, ParseErrorKind::IncludeCycle);
+    assert!(errors[0].file.as_ref().unwrap().ends_with("b.ram"));
+    assert_eq!(errors[0].span, Some(Span { line: 1, start: 10, end: 20 }));
+
+    let cycle: Vec<&str> = errors[0].suggestion.as_ref().unwrap().split(" -> ").collect();
+    assert_eq!(cycle.len(), 3);
+    assert!(cycle[0].ends_with("a.ram") && cycle[1].ends_with("b.ram") && cycle[2].ends_with("a.ram"));
+}
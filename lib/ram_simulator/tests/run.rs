@@ -0,0 +1,109 @@
+use std::io::BufReader;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use ram_simulator::cost::CostMeasure;
+use ram_simulator::run::{Budget, RunConfig, Termination};
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::RegisterMachine;
+
+const FOREVER: &str = "INIT\nCADD 1\nGOTO 1\nEND\n";
+
+fn machine(src: &str) -> RegisterMachine<u128> {
+    parse_buf_as(BufReader::new(src.as_bytes())).unwrap()
+}
+
+#[test]
+fn halting_runs_arent_limited() {
+    let mut machine = machine("INIT 3\nLOAD 1\nEND\n");
+    assert_eq!(machine.run_with(std::io::sink(), &RunConfig::with_max_steps(2)), Termination::Halted);
+    assert_eq!(machine.get_state().get_acc(), 3);
+}
+
+#[test]
+fn step_budget() {
+    let mut machine = machine(FOREVER);
+    let termination = machine.run_with(std::io::sink(), &RunConfig::with_max_steps(10));
+    assert_eq!(termination, Termination::BudgetExhausted(Budget::Steps(10)));
+    assert_eq!(machine.get_state().get_steps(), 10);
+    assert_eq!(machine.get_state().get_acc(), 5);
+
+    // Budgets count from the start of each run
+    let termination = machine.run_with(std::io::sink(), &RunConfig::with_max_steps(10));
+    assert_eq!(termination, Termination::BudgetExhausted(Budget::Steps(10)));
+    assert_eq!(machine.get_state().get_steps(), 20);
+}
+
+#[test]
+fn cost_budget() {
+    let config = RunConfig {
+        max_cost: Some(5),
+        ..RunConfig::new()
+    };
+    let mut uniform = machine(FOREVER);
+    assert_eq!(
+        uniform.run_with(std::io::sink(), &config),
+        Termination::BudgetExhausted(Budget::Cost(CostMeasure::Uniform, 5)),
+    );
+    assert_eq!(uniform.get_state().get_cost().uniform, 5);
+
+    // CADD 1 costs l(c(0)) + 1, GOTO 1 costs 1: the run stops at the first step that reaches the budget
+    let config = RunConfig {
+        max_cost: Some(5),
+        cost_measure: CostMeasure::Logarithmic,
+        ..RunConfig::new()
+    };
+    let mut logarithmic = machine(FOREVER);
+    assert_eq!(
+        logarithmic.run_with(std::io::sink(), &config),
+        Termination::BudgetExhausted(Budget::Cost(CostMeasure::Logarithmic, 5)),
+    );
+    assert_eq!(logarithmic.get_state().get_cost().uniform, 3);
+    assert_eq!(logarithmic.get_state().get_cost().logarithmic, 2 + 1 + 2);
+}
+
+#[test]
+fn cancellation() {
+    let mut config = RunConfig::new();
+    let flag = config.cancellation_flag();
+
+    flag.store(true, Ordering::Relaxed);
+    let mut machine = machine(FOREVER);
+    assert_eq!(machine.run_with(std::io::sink(), &config), Termination::Cancelled);
+    assert_eq!(machine.get_state().get_steps(), 0);
+
+    flag.store(false, Ordering::Relaxed);
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        flag.store(true, Ordering::Relaxed);
+    });
+    assert_eq!(machine.run_with(std::io::sink(), &config), Termination::Cancelled);
+    assert!(machine.get_state().get_steps() > 0);
+    canceller.join().unwrap();
+}
+
+#[test]
+fn run_to_completion_ends_like_run() {
+    let src = "INIT 5\nLOAD 1\nCSUB 1\nSTORE 1\nIF c(0) > 0 THEN GOTO 1\nEND\n";
+    let mut traced = machine(src);
+    let mut trace = Vec::new();
+    assert!(traced.run(&mut trace).is_halted());
+
+    let mut quiet = machine(src);
+    let completion = quiet.run_to_completion(&RunConfig::new());
+    assert_eq!(completion.termination, Termination::Halted);
+    assert_eq!(completion.state.to_wasm_comm_str(), traced.get_state().to_wasm_comm_str());
+    assert_eq!(completion.steps, 4 * 5 + 1);
+    assert_eq!(completion.cost, traced.get_state().get_cost());
+    assert_eq!(completion.space, traced.get_state().get_space());
+}
+
+#[test]
+fn run_to_completion_respects_the_budget() {
+    let mut machine = machine(FOREVER);
+    let completion = machine.run_to_completion(&RunConfig::with_max_steps(7));
+    assert_eq!(completion.termination, Termination::BudgetExhausted(Budget::Steps(7)));
+    assert_eq!(completion.steps, 7);
+    assert_eq!(completion.state.get_acc(), 4);
+}
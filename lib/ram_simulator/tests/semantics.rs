@@ -0,0 +1,62 @@
+use ram_simulator::error::Fault;
+use ram_simulator::semantics::{Arithmetic, DivisionByZero, Semantics};
+
+fn semantics(arithmetic: Arithmetic) -> Semantics {
+    Semantics::new(arithmetic, DivisionByZero::Zero)
+}
+
+#[test]
+fn bounded_words_wrap_and_saturate() {
+    assert!(semantics(Arithmetic::Wrapping).check::<u128>().is_ok());
+    assert_eq!(semantics(Arithmetic::Wrapping).sub(&0u128, &1), Ok(u128::MAX));
+    assert_eq!(semantics(Arithmetic::Saturating).add(&u128::MAX, &1), Ok(u128::MAX));
+    assert_eq!(semantics(Arithmetic::Trapping).add(&u128::MAX, &1), Err(Fault::Overflow));
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn unbounded_words_reject_wrapping() {
+    use num_bigint::{BigInt, BigUint};
+
+    assert!(semantics(Arithmetic::Wrapping).check::<BigUint>().is_err());
+    assert!(semantics(Arithmetic::Wrapping).check::<BigInt>().is_err());
+
+    // Only unsigned subtraction has a bound to saturate at
+    let saturating = semantics(Arithmetic::Saturating);
+    assert!(saturating.check::<BigUint>().is_ok());
+    assert_eq!(saturating.sub(&BigUint::from(0u8), &BigUint::from(1u8)), Ok(BigUint::from(0u8)));
+    assert_eq!(saturating.sub(&BigInt::from(0), &BigInt::from(1)), Ok(BigInt::from(-1)));
+}
+
+#[test]
+fn every_combination_on_signed_words() {
+    const ALL: [Arithmetic; 5] = [
+        Arithmetic::Monus, Arithmetic::Signed, Arithmetic::Wrapping, Arithmetic::Saturating, Arithmetic::Trapping,
+    ];
+
+    for arithmetic in ALL {
+        // (0 - 1, MAX + 1, MIN / -1)
+        let expected: (Result<i128, Fault>, Result<i128, Fault>, Result<i128, Fault>) = match arithmetic {
+            Arithmetic::Monus      => (Ok(0),  Err(Fault::Overflow), Err(Fault::Overflow)),
+            Arithmetic::Signed     => (Ok(-1), Err(Fault::Overflow), Err(Fault::Overflow)),
+            Arithmetic::Wrapping   => (Ok(-1), Ok(i128::MIN),        Ok(i128::MIN)),
+            Arithmetic::Saturating => (Ok(-1), Ok(i128::MAX),        Ok(i128::MAX)),
+            Arithmetic::Trapping   => (Ok(-1), Err(Fault::Overflow), Err(Fault::Overflow)),
+        };
+
+        for division_by_zero in [DivisionByZero::Zero, DivisionByZero::Trap] {
+            let semantics = Semantics::new(arithmetic, division_by_zero);
+            assert!(semantics.check::<i128>().is_ok());
+            assert_eq!(
+                (semantics.sub(&0i128, &1), semantics.add(&i128::MAX, &1), semantics.div(&i128::MIN, &-1)),
+                expected,
+                "{} arithmetic",
+                arithmetic,
+            );
+            assert_eq!(semantics.div(&5i128, &0), match division_by_zero {
+                DivisionByZero::Zero => Ok(0),
+                DivisionByZero::Trap => Err(Fault::DivisionByZero),
+            });
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use ram_simulator::state::State;
+
+#[test]
+fn reading_doesnt_store_registers() {
+    let mut state: State<u128> = State::initial();
+    state.set_reg(2, 5);
+    assert_eq!(state.get_reg(1000), 0);
+    assert_eq!(state.get_highest_register(), 2);
+    assert_eq!(state.used_registers().collect::<Vec<_>>(), [(0, 0), (2, 5)]);
+}
+
+#[test]
+fn registers_are_sparse() {
+    let mut state: State<u128> = State::initial();
+    state.set_acc(1);
+    state.set_reg(usize::MAX, 3);
+    assert_eq!(state.get_reg(usize::MAX), 3);
+    assert_eq!(state.get_highest_register(), usize::MAX);
+    assert_eq!(state.used_registers().collect::<Vec<_>>(), [(0, 1), (usize::MAX, 3)]);
+}
+
+#[test]
+fn wasm_comm_str_round_trips() {
+    let mut state: State<u128> = State::initial();
+    state.start();
+    state.set_reg(0, 7);
+    state.set_reg(3, 12);
+    state.set_pc(4);
+    state.charge(9);
+    state.inc_steps();
+
+    let text = state.to_wasm_comm_str();
+    let parsed = State::<u128>::from_wasm_comm_str(&text).unwrap();
+    assert_eq!(parsed.to_wasm_comm_str(), text);
+    assert_eq!(parsed.peek_reg(3), 12);
+    assert_eq!(parsed.get_pc(), 4);
+}
+
+#[test]
+fn wasm_comm_str_reports_missing_and_invalid_fields() {
+    assert_eq!(State::<u128>::from_wasm_comm_str("r<1").unwrap_err(), "Expected the uniform cost at pos 2");
+    assert!(State::<u128>::from_wasm_comm_str("x<1").unwrap_err().starts_with("r?"));
+    assert!(State::<u128>::from_wasm_comm_str("r<one").unwrap_err().starts_with("step number one"));
+}
@@ -0,0 +1,111 @@
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::text::transpiler::{transpile, Target};
+use ram_simulator::RegisterMachine;
+
+/// The `floor(ld(c(1)))` program from the readme
+const LOG2: &str = "INIT 256
+CLOAD 0
+STORE 2
+CLOAD 1
+STORE 3
+LOAD 3
+SUB 1
+IF c(0) > 0 THEN GOTO 15
+LOAD 2
+CADD 1
+STORE 2
+LOAD 3
+CMULT 2
+STORE 3
+GOTO 4
+LOAD 2
+CSUB 1
+STORE 1
+END
+";
+
+/// Reads registers that are never written and goes through pointers
+const POINTERS: &str = "INIT 3 0 9\nINDLOAD 1\nADD 7\nCDIV 0\nCADD 4\nSTORE 2\nINDSTORE 1\nDIV 8\nINDADD 2\nEND\n";
+
+const OVERFLOW: &str = "INIT 340282366920938463463374607431768211455\nLOAD 1\nMULT 1\nCADD 1\nEND\n";
+
+fn machine(src: &str) -> RegisterMachine<u128> {
+    parse_buf_as(BufReader::new(src.as_bytes())).unwrap()
+}
+
+/// Transpiles `src` and builds it in the temp directory
+fn build(name: &str, src: &str, target: Target) -> PathBuf {
+    let ram = machine(src);
+    let source = transpile(ram.get_program(), ram.get_state(), target).unwrap();
+
+    let dir = std::env::temp_dir();
+    let stem = format!("ram-transpiled-{}-{}-{}", std::process::id(), name, target);
+    let source_path = dir.join(format!("{}.{}", stem, target.extension()));
+    let binary = dir.join(&stem);
+    std::fs::write(&source_path, source).unwrap();
+
+    let compiler = match target {
+        Target::C => Command::new("cc").arg("-O1").arg("-o").arg(&binary).arg(&source_path).output(),
+        Target::Rust => Command::new("rustc").arg("-O").arg("-o").arg(&binary).arg(&source_path).output(),
+    }.unwrap();
+    assert!(compiler.status.success(), "{}", String::from_utf8_lossy(&compiler.stderr));
+    std::fs::remove_file(&source_path).unwrap();
+    binary
+}
+
+fn execute(binary: &Path, args: &[&str]) -> Output {
+    Command::new(binary).args(args).output().unwrap()
+}
+
+/// What the interpreter prints for `src` (with `args` in r1, r2, ... instead of the values of INIT).
+/// Transpiled programs don't know the source, so errors name instructions instead of lines.
+fn interpret(src: &str, args: &[&str]) -> Result<String, String> {
+    let mut ram = machine(src);
+    ram.set_source_map(None);
+    if !args.is_empty() {
+        let mut state = ram.get_state().clone();
+        state.reset();
+        ram.set_state(state);
+        ram.push_vec(args.iter().map(|a| a.parse().unwrap()).collect());
+    }
+
+    let termination = ram.run(std::io::sink());
+    if !termination.is_halted() {
+        return Err(format!("Error: {}\n", termination));
+    }
+
+    let mut registers = Vec::new();
+    ram.get_state().print_registers(&mut registers).unwrap();
+    registers.push(b'\n');
+    Ok(String::from_utf8(registers).unwrap())
+}
+
+fn compare(name: &str, src: &str, runs: &[&[&str]]) {
+    for target in [Target::C, Target::Rust] {
+        let binary = build(name, src, target);
+        for args in runs {
+            let output = execute(&binary, args);
+            let result = match output.status.success() {
+                true => Ok(String::from_utf8(output.stdout).unwrap()),
+                false => Err(String::from_utf8(output.stderr).unwrap()),
+            };
+            assert_eq!(result, interpret(src, args), "{} in {} with {:?}", name, target, args);
+        }
+        std::fs::remove_file(&binary).unwrap();
+    }
+}
+
+#[test]
+fn transpiled_programs_match_the_interpreter() {
+    compare("log2", LOG2, &[&[], &["1"], &["1000000"]]);
+    compare("pointers", POINTERS, &[&[], &["1", "5"]]);
+}
+
+#[test]
+fn transpiled_programs_fail_like_the_interpreter() {
+    compare("overflow", OVERFLOW, &[&[], &["2"], &["18446744073709551616"]]);
+}
@@ -0,0 +1,43 @@
+use std::io::BufReader;
+
+use ram_simulator::error::{ExecError, Fault};
+use ram_simulator::run::Termination;
+use ram_simulator::text::deserializer::parse_buf_as;
+use ram_simulator::word::Word;
+
+// Squares r1 twice, which overflows a u128 when r1 is 2^64
+const SQUARE_TWICE: &str = "INIT 18446744073709551616\nLOAD 1\nMULT 1\nMULT 0\nEND\n";
+
+#[test]
+fn u128_arithmetic_is_checked() {
+    assert_eq!(Word::checked_add(&u128::MAX, &0), Some(u128::MAX));
+    assert_eq!(Word::checked_add(&u128::MAX, &1), None);
+    assert_eq!(Word::checked_sub(&0u128, &1), None);
+    assert_eq!(Word::checked_mul(&(1u128 << 64), &(1u128 << 64)), None);
+    assert_eq!(Word::checked_div(&7u128, &0), None);
+    assert_eq!(<u128 as Word>::parse_word("340282366920938463463374607431768211456").ok(), None);
+}
+
+#[test]
+fn u128_registers_overflow() {
+    let mut machine = parse_buf_as::<u128, _>(BufReader::new(SQUARE_TWICE.as_bytes())).unwrap();
+    assert!(matches!(
+        machine.run(std::io::sink()),
+        Termination::Fault(ExecError::Fault { pc: 1, cause: Fault::Overflow, .. })
+    ));
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn bigint_registers_grow_past_u128() {
+    use num_bigint::BigUint;
+
+    let max = BigUint::from(u128::MAX);
+    assert_eq!(Word::checked_add(&max, &BigUint::from(1u8)), Some(BigUint::from(1u8) << 128));
+    assert_eq!(Word::checked_sub(&BigUint::from(0u8), &BigUint::from(1u8)), None);
+    assert_eq!(Word::checked_div(&max, &BigUint::from(0u8)), None);
+
+    let mut machine = parse_buf_as::<BigUint, _>(BufReader::new(SQUARE_TWICE.as_bytes())).unwrap();
+    assert!(machine.run(std::io::sink()).is_halted());
+    assert_eq!(machine.get_state().get_acc(), BigUint::from(1u8) << 256);
+}
@@ -1,4 +1,8 @@
-use std::io::{BufReader};
+use std::io::{BufReader, Write};
+use breakpoint::Breakpoint;
+use cycle::LoopDetection;
+use run::{RunConfig, Termination};
+use semantics::{Arithmetic, DivisionByZero, Semantics};
 use state::State;
 use text::{deserializer, Serializable};
 
@@ -8,6 +12,9 @@ use ram_simulator::*;
 static mut RAM: RegisterMachine = RegisterMachine::new_empty();
 static mut INIT_STATE: State = State::initial();
 
+/// Number of steps between two saved states when going back
+const CHECKPOINT_INTERVAL: usize = 1000;
+
 #[wasm_bindgen]
 extern {
     fn ram_post_res(r: &str, t: &str);
@@ -21,9 +28,33 @@ pub fn init_machine(program: &str) {
     match deserializer::parse_buf(br) {
         Ok(m) => unsafe {
                 RAM = m;
+                RAM.set_loop_detection(Some(LoopDetection::Brent));
+                RAM.set_history(Some(CHECKPOINT_INTERVAL));
                 INIT_STATE = RAM.get_state().clone();
         },
-        Err(e) => {
+        Err(errors) => {
+            for error in &errors.errors {
+                ram_post_res(&error.to_string(), "ramBadResult");
+            }
+        }
+    }
+}
+
+#[no_mangle]
+#[wasm_bindgen]
+pub fn set_semantics(arithmetic: &str, division_by_zero: &str) {
+    let arithmetic = match Arithmetic::from_name(arithmetic) {
+        Some(a) => a,
+        None => return ram_post_res(&format!("Unknown arithmetic {}", arithmetic), "ramBadResult"),
+    };
+
+    let division_by_zero = match DivisionByZero::from_name(division_by_zero) {
+        Some(d) => d,
+        None => return ram_post_res(&format!("Unknown division policy {}", division_by_zero), "ramBadResult"),
+    };
+
+    unsafe {
+        if let Err(e) = RAM.set_semantics(Semantics::new(arithmetic, division_by_zero)) {
             ram_post_res(&e, "ramBadResult");
         }
     }
@@ -33,7 +64,7 @@ pub fn init_machine(program: &str) {
 #[wasm_bindgen]
 pub fn reset_machine() {
     unsafe {
-        RAM.set_state(INIT_STATE);
+        RAM.set_state(INIT_STATE.clone());
     }
 }
 
@@ -41,7 +72,10 @@ pub fn reset_machine() {
 #[wasm_bindgen]
 pub unsafe fn run_machine(max_depth: usize) {
     if !RAM.has_not_ended() {
-        ram_post_res("Machine has already reached an END command", "ramBadResult");
+        match RAM.get_fault() {
+            Some(err) => ram_post_res(&err.to_string(), "ramBadResult"),
+            None => ram_post_res("Machine has already reached an END command", "ramBadResult"),
+        }
         return;
     }
 
@@ -52,24 +86,131 @@ pub unsafe fn run_machine(max_depth: usize) {
         );
     }
 
-    for _ in 0..max_depth {
-        match &mut RAM.step() {
-            Ok(res_st) => {
-                if !RAM.has_not_ended() {
-                    ram_post_res(&"Machine halted.", "ramGoodResult");
-                    break;
-                }
+    let config = RunConfig::with_max_steps(max_depth as u64);
 
-                ram_post_res(&res_st.to_string(), "");
+    match RAM.run_until(ResultWriter::new(), &config) {
+        Termination::Halted => {
+            let state = RAM.get_state();
+            ram_post_res(
+                &format!("Machine halted. Cost -- {}. Space -- {}", state.get_cost(), state.get_space()),
+                "ramGoodResult"
+            );
         },
-            Err(m_err) => {
-                ram_post_res(m_err, "ramBadResult");
-                return;
+        Termination::BudgetExhausted(budget) => ram_post_res(
+            &format!("{}. The machine hasn't halted yet.", Termination::BudgetExhausted(budget)),
+            "ramStateInfo"
+        ),
+        Termination::Breakpoint(hit) => ram_post_res(&hit.to_string(), "ramStateInfo"),
+        other => ram_post_res(&other.to_string(), "ramBadResult"),
+    }
+}
+
+/// Executes a single instruction and posts what it did in words, then the new configuration
+#[no_mangle]
+#[wasm_bindgen]
+pub fn step_machine() {
+    unsafe {
+        let explanation = RAM.explain_next();
+        match RAM.step() {
+            Ok(_) => {
+                if let Some(explanation) = explanation {
+                    ram_post_res(&explanation, "ramStateInfo");
+                }
+                ram_post_res(&Serializable::to_string(RAM.get_state()), "");
+            },
+            Err(e) => ram_post_res(&e.to_string(), "ramBadResult"),
+        }
+    }
+}
+
+/// Explains in words what the next instruction does (empty if the machine has stopped)
+#[no_mangle]
+#[wasm_bindgen]
+pub fn explain_next() -> String {
+    unsafe { RAM.explain_next() }.unwrap_or_default()
+}
+
+#[no_mangle]
+#[wasm_bindgen]
+pub fn step_back() {
+    match unsafe { RAM.step_back() } {
+        Ok(state) => ram_post_res(&format!("Went back to state {}", state.to_wasm_comm_str()), "ramStateInfo"),
+        Err(e) => ram_post_res(&e.to_string(), "ramBadResult"),
+    }
+}
+
+#[no_mangle]
+#[wasm_bindgen]
+pub fn seek(step: usize) {
+    match unsafe { RAM.seek(step) } {
+        Ok(state) => ram_post_res(&format!("Went to state {}", state.to_wasm_comm_str()), "ramStateInfo"),
+        Err(e) => ram_post_res(&e.to_string(), "ramBadResult"),
+    }
+}
+
+#[no_mangle]
+#[wasm_bindgen]
+pub fn add_breakpoint(spec: &str) -> usize {
+    match Breakpoint::parse(spec) {
+        Ok(breakpoint) => unsafe { RAM.add_breakpoint(breakpoint) },
+        Err(e) => {
+            ram_post_res(&format!("Breakpoint '{}': {}", spec, e), "ramBadResult");
+            0
+        }
+    }
+}
+
+#[no_mangle]
+#[wasm_bindgen]
+pub fn remove_breakpoint(id: usize) -> bool {
+    unsafe { RAM.remove_breakpoint(id) }
+}
+
+#[no_mangle]
+#[wasm_bindgen]
+pub fn clear_breakpoints() {
+    unsafe { RAM.clear_breakpoints() }
+}
+
+/// Returns the line of the program's source that the instruction at `pc` is on
+#[no_mangle]
+#[wasm_bindgen]
+pub fn source_line(pc: usize) -> Option<usize> {
+    unsafe { RAM.get_source_map() }.and_then(|source_map| source_map.line(pc))
+}
+
+/// Returns the PC of the instruction on line `line` of the program's source, if there is one
+#[no_mangle]
+#[wasm_bindgen]
+pub fn source_pc(line: usize) -> Option<usize> {
+    unsafe { RAM.get_source_map() }.and_then(|source_map| source_map.pc(line))
+}
+
+/// Posts every line of the trace as a result
+struct ResultWriter {
+    line: Vec<u8>,
+}
+
+impl ResultWriter {
+    fn new() -> ResultWriter {
+        ResultWriter { line: Vec::new() }
+    }
+}
+
+impl Write for ResultWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' {
+                ram_post_res(&String::from_utf8_lossy(&self.line), "");
+                self.line.clear();
+            } else {
+                self.line.push(byte);
             }
         }
+        Ok(buf.len())
     }
 
-    if RAM.has_not_ended() {
-        ram_post_res("The machine hasn't halted yet.", "ramStateInfo");
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,16 +1,16 @@
-use std::io::BufWriter;
-
-/**!
+/*!
  * An example of a hardcoded program.
  * This program computes `ceil(ld(c(1))`
  */
 
+use std::io::BufWriter;
+
 use ram_simulator::*;
 use ram_simulator::instruction::*;
 use comparison::Comparison;
 
 fn main() {
-    let mut isl = InstructionVec::new();
+    let mut isl: InstructionVec<u128> = InstructionVec::new();
     ivec_push_multiple!(isl, 
         CLoad::new(1),
         Store::new(3),
@@ -29,5 +29,8 @@ fn main() {
 
     let mut ram = RegisterMachine::new(isl);
     ram.push_vec(vec![2,3]);
-    ram.run(bw);
+    let termination = ram.run(bw);
+    if !termination.is_halted() {
+        eprintln!("{}", termination);
+    }
 }
\ No newline at end of file
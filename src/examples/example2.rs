@@ -5,7 +5,7 @@ use ram_simulator::instruction::*;
 use comparison::Comparison;
 
 fn main() {
-    let mut isl = InstructionVec::new();
+    let mut isl: InstructionVec<u128> = InstructionVec::new();
     ivec_push_multiple!(isl,
         CLoad::new(0),
         Store::new(2),
@@ -31,5 +31,8 @@ fn main() {
 
     let mut ram = RegisterMachine::new(isl);
     ram.push_vec(vec![2,3]);
-    ram.run(bw);
+    let termination = ram.run(bw);
+    if !termination.is_halted() {
+        eprintln!("{}", termination);
+    }
 }
\ No newline at end of file
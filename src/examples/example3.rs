@@ -1,4 +1,4 @@
-/**!
+/*!
  * Example of a program being deserialized from a file
  */
 
@@ -13,7 +13,10 @@ fn main() {
 
     let bw = BufWriter::new(std::io::stdout());
 
-    another_ram.run(bw);
+    let termination = another_ram.run(bw);
+    if !termination.is_halted() {
+        eprintln!("{}", termination);
+    }
 
     // let ser = Serializer::to_string(another_ram);
     // Serializer::dump(another_ram);
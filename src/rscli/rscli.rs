@@ -6,46 +6,528 @@ use std::process::exit;
 
 use text::Serializable;
 use ram_simulator::*;
+use cost::CostMeasure;
+use breakpoint::Breakpoint;
+use cycle::LoopDetection;
+use run::{RunConfig, Termination};
+use semantics::{Arithmetic, DivisionByZero, Semantics};
+use text::format::{Annotated, Format, TraceFormat};
+use text::formatter;
+use text::transpiler::{self, Target};
+use word::{SignedValue, Value, Word};
+
+/// Number of steps between two saved states when stepping backwards
+const CHECKPOINT_INTERVAL: usize = 1000;
+
+/// Options that can follow the program path
+struct Options {
+    semantics: Semantics,
+    run_config: RunConfig,
+    loop_detection: Option<LoopDetection>,
+    breakpoints: Vec<String>,
+    /// Format of the trace in continuous mode
+    format: Format,
+    /// Show the instructions and changed registers in text traces (and only the changes)
+    annotate: bool,
+    changes_only: bool,
+    /// Explain every step in words in step mode
+    explain: bool,
+    /// Language and file the transpiler writes
+    target: Target,
+    output: Option<String>,
+    /// Run with the JIT (and check it against the interpreter)
+    #[cfg(feature = "jit")]
+    jit: bool,
+    #[cfg(feature = "jit")]
+    jit_check: bool,
+}
+
+fn usage() -> ! {
+    println!("\n\x1b[31mUsage\x1b[0m: rscli [cqst] /path/to/ram-program.s [options]");
+    println!("       rscli fmt /path/to/ram-program.s [--check]\n");
+    println!("Modes: c (continuous), q (quiet, only the final configuration), s (step by step),");
+    println!("       t (transpile to C or Rust), fmt (format the program in place, or with --check");
+    println!("       only check whether it's formatted)\n");
+    println!("Options:");
+    println!("  --arith <monus|signed|wrapping|saturating|trapping>   arithmetic semantics (default: monus)");
+    println!("  --div-zero <zero|trap>                                division by zero (default: zero)");
+    println!("  --max-steps <n>                                       stop after n steps");
+    println!("  --max-cost <n>                                        stop once the cost reaches n");
+    println!("  --cost-measure <uniform|logarithmic>                  measure for --max-cost (default: uniform)");
+    println!("  --loop-detection <off|hash|brent>                     stop on repeated configurations (default: off)");
+    println!("  --break <spec>                                        breakpoint: 'line N' (of the file), 'instr N' (counted like");
+    println!("                                                        GOTO targets), 'watch [read|write] c(i)'");
+    println!("                                                        or 'if CONDITION', e.g. 'if c(3) > 10 && pc = 7'");
+    println!("  --format <ansi|plain|jsonl|csv>                       trace format of c (default: plain if NO_COLOR is set, else ansi)");
+    println!("  --annotate                                            show the instruction and the changed registers in the trace");
+    println!("  --changes-only                                        like --annotate, but leave out the unchanged registers");
+    println!("  --explain                                             explain every step in words in step mode");
+    println!("  --lang <c|rust>                                       target language of t (default: c)");
+    println!("  --output <path>                                       file t writes (default: the program's path as .c/.rs)");
+    #[cfg(feature = "jit")]
+    {
+        println!("  --jit                                                 compile the program to native code (no trace)");
+        println!("  --jit-check                                           like --jit, but also check it against the interpreter");
+    }
+    println!();
+    println!("In step mode, press enter to execute the next instruction. Other commands:");
+    println!("  c          run to the next breakpoint");
+    println!("  e          explain the next instruction without executing it");
+    println!("  b          go back a step");
+    println!("  g <n>      go to the state after n steps (backwards or forwards)\n");
+    exit(1);
+}
+
+fn parse_options(argv: &[String]) -> Options {
+    let mut options = Options {
+        semantics: Semantics::default(),
+        run_config: RunConfig::new(),
+        loop_detection: None,
+        breakpoints: Vec::new(),
+        format: Format::from_env(),
+        annotate: false,
+        changes_only: false,
+        explain: false,
+        target: Target::default(),
+        output: None,
+        #[cfg(feature = "jit")]
+        jit: false,
+        #[cfg(feature = "jit")]
+        jit_check: false,
+    };
+
+    let mut iter = argv.iter();
+    while let Some(arg) = iter.next() {
+        // Flags without a value
+        match arg.as_str() {
+            "--annotate" => {
+                options.annotate = true;
+                continue;
+            },
+            "--changes-only" => {
+                options.annotate = true;
+                options.changes_only = true;
+                continue;
+            },
+            "--explain" => {
+                options.explain = true;
+                continue;
+            },
+            _ => {},
+        }
+
+        #[cfg(feature = "jit")]
+        match arg.as_str() {
+            "--jit" => {
+                options.jit = true;
+                continue;
+            },
+            "--jit-check" => {
+                options.jit = true;
+                options.jit_check = true;
+                continue;
+            },
+            _ => {},
+        }
+
+        let value = match iter.next() {
+            Some(v) => v,
+            None => usage(),
+        };
+
+        match arg.as_str() {
+            "--arith" => match Arithmetic::from_name(value) {
+                Some(a) => options.semantics.arithmetic = a,
+                None => usage(),
+            },
+            "--div-zero" => match DivisionByZero::from_name(value) {
+                Some(d) => options.semantics.division_by_zero = d,
+                None => usage(),
+            },
+            "--max-steps" => match value.parse::<u64>() {
+                Ok(n) => options.run_config.max_steps = Some(n),
+                Err(_) => usage(),
+            },
+            "--max-cost" => match value.parse::<u64>() {
+                Ok(n) => options.run_config.max_cost = Some(n),
+                Err(_) => usage(),
+            },
+            "--cost-measure" => match CostMeasure::from_name(value) {
+                Some(m) => options.run_config.cost_measure = m,
+                None => usage(),
+            },
+            "--break" => options.breakpoints.push(value.clone()),
+            "--format" => match Format::from_name(value) {
+                Some(f) => options.format = f,
+                None => usage(),
+            },
+            "--lang" => match Target::from_name(value) {
+                Some(t) => options.target = t,
+                None => usage(),
+            },
+            "--output" => options.output = Some(value.clone()),
+            "--loop-detection" => match value.as_str() {
+                "off" => options.loop_detection = None,
+                _ => match LoopDetection::from_name(value) {
+                    Some(l) => options.loop_detection = Some(l),
+                    None => usage(),
+                },
+            },
+            _ => usage(),
+        }
+    }
+
+    options
+}
 
 fn main() {
-    let argv: Vec<String> = args().collect(); 
+    let argv: Vec<String> = args().collect();
 
-    println!("\x1b[34mRegister Machine Simulator CLI -- kernzerfall 2022\x1b[0m");
+    if argv.len() < 3 {
+        println!("\x1b[34mRegister Machine Simulator CLI -- kernzerfall 2022\x1b[0m");
+        usage();
+    }
 
-    if argv.len() != 3 {
-        println!("\n\x1b[31mUsage\x1b[0m: rscli [cs] /path/to/ram-program.s\n");
-        exit(1);
+    if argv[1] == "fmt" {
+        fmt(&argv[2], &argv[3..]);
+        return;
     }
 
-    println!("Running \x1b[35m'{}'\x1b[0m", &argv[2]);
+    let options = parse_options(&argv[3..]);
+
+    // Traces meant for other programs get stdout to themselves
+    if !options.format.is_machine_readable() {
+        println!("{}", paint(&options, "34", "Register Machine Simulator CLI -- kernzerfall 2022"));
+        println!("Running {}", paint(&options, "35", &format!("'{}'", &argv[2])));
+    }
+
+    if argv[1].starts_with('t') {
+        transpile(&argv[2], &options);
+        return;
+    }
+
+    #[cfg(feature = "jit")]
+    if options.jit && argv[1].starts_with('c') {
+        simulate_jit(&argv[2], &options);
+        return;
+    }
+
+    // Negative numbers need a signed register type
+    match options.semantics.arithmetic {
+        Arithmetic::Signed => simulate::<SignedValue>(&argv[1], &argv[2], &options),
+        _ => simulate::<Value>(&argv[1], &argv[2], &options),
+    }
+
+    // let ser = Serializer::to_string(another_ram);
+    // Serializer::dump(another_ram);
+    // Serializer::dump(ram);
+}
 
-    let mut another_ram = text::deserializer::parse_file(Path::new(&argv[2]).to_path_buf())
-        .expect("File should contain a valid assembly program");
+fn simulate<W: Word>(mode: &str, path: &str, options: &Options) {
+    let mut another_ram = load::<W>(path);
 
-    match argv[1].chars().nth(0).expect("A valid argument") {
+    if let Err(err) = another_ram.set_semantics(options.semantics) {
+        println!("\x1b[31mError\x1b[0m: {}", err);
+        exit(1);
+    }
+
+    for spec in &options.breakpoints {
+        match Breakpoint::parse(spec) {
+            Ok(Breakpoint::Line(line)) if another_ram.get_source_map().and_then(|map| map.pc(line)).is_none() => {
+                println!("\x1b[31mError\x1b[0m: Breakpoint '{}': There's no instruction on line {}", spec, line);
+                exit(1);
+            },
+            Ok(breakpoint) => { another_ram.add_breakpoint(breakpoint); },
+            Err(err) => {
+                println!("\x1b[31mError\x1b[0m: Breakpoint '{}': {}", spec, err);
+                exit(1);
+            },
+        }
+    }
+
+    match mode.chars().next().expect("A valid argument") {
         'c' => {
+            another_ram.set_loop_detection(options.loop_detection);
+            let mut format = trace_format(&another_ram, options);
             let bw = BufWriter::new(std::io::stdout());
-            let res = another_ram.run(bw);
-            if res.is_some() {
-                panic!("{}", res.unwrap());
+            let mut termination = another_ram.run_formatted(bw, &options.run_config, format.as_mut());
+
+            // Breakpoints pause the run until enter is pressed
+            while let Termination::Breakpoint(hit) = &termination {
+                say(options, &format!("{} (press enter to continue)", paint(options, "33", &hit.to_string())));
+                std::io::stdin().read_line(&mut String::new()).unwrap();
+
+                let mut bw = BufWriter::new(std::io::stdout());
+                termination = another_ram.run_until_formatted(&mut bw, &options.run_config, format.as_mut());
+                if termination.is_halted() {
+                    format.summary(&mut bw, another_ram.get_state()).unwrap();
+                }
+            }
+
+            finish(termination, options);
+        },
+        'q' => {
+            another_ram.set_loop_detection(options.loop_detection);
+            let completion = another_ram.run_to_completion(&options.run_config);
+
+            print_final(&completion.state);
+            if completion.termination.is_halted() {
+                print_summary(&completion.state);
             }
+
+            finish(completion.termination, options);
         },
         's' => {
-            while another_ram.has_not_ended() {
-                // Wait for enter
-                std::io::stdin().read_line(&mut String::new()).unwrap();
-                // Run step
-                match another_ram.step() {
-                    Ok(s) => { s.dump() },
-                    Err(u) => { println!("{}", u) }
+            another_ram.set_history(Some(CHECKPOINT_INTERVAL));
+
+            loop {
+                // Wait for enter (or a command)
+                let mut command = String::new();
+                if std::io::stdin().read_line(&mut command).unwrap() == 0 {
+                    break;
+                }
+
+                let mut words = command.split_whitespace();
+                match (words.next(), words.next()) {
+                    (None, _) if !another_ram.has_not_ended() => break,
+                    (None, _) => {
+                        if options.explain {
+                            if let Some(explanation) = another_ram.explain_next() {
+                                println!("\x1b[36m{}\x1b[0m", explanation);
+                            }
+                        }
+
+                        // Run step
+                        match another_ram.step() {
+                            Ok(_) => { another_ram.get_state().dump() },
+                            Err(u) => { println!("\x1b[31mError\x1b[0m: {}", u) }
+                        }
+                    },
+                    (Some("c"), None) => {
+                        // Run to the next breakpoint
+                        match another_ram.run_until(std::io::stdout(), &options.run_config) {
+                            Termination::Halted => {},
+                            Termination::Fault(u) => { println!("\x1b[31mError\x1b[0m: {}", u) },
+                            other => { println!("\x1b[33m{}\x1b[0m", other) },
+                        }
+                    },
+                    (Some("e"), None) => match another_ram.explain_next() {
+                        Some(explanation) => println!("\x1b[36m{}\x1b[0m", explanation),
+                        None => println!("\x1b[31mError\x1b[0m: There's no instruction to explain"),
+                    },
+                    (Some("b"), None) => match another_ram.step_back() {
+                        Ok(s) => show_state(s),
+                        Err(u) => { println!("\x1b[31mError\x1b[0m: {}", u) }
+                    },
+                    (Some("g"), Some(step)) => match step.parse::<usize>() {
+                        Ok(step) => match another_ram.seek(step) {
+                            Ok(s) => show_state(s),
+                            Err(u) => { println!("\x1b[31mError\x1b[0m: {}", u) }
+                        },
+                        Err(_) => println!("\x1b[31mError\x1b[0m: '{}' is not a step", step),
+                    },
+                    _ => println!("\x1b[31mError\x1b[0m: Unknown command '{}'", command.trim()),
+                }
+
+                if !another_ram.has_not_ended() {
+                    println!("\x1b[33mThe machine has stopped. Enter 'b' to go back or press enter to quit.\x1b[0m");
                 }
                 std::io::stdout().flush().unwrap();
             }
+
+            // The totals of a machine that hasn't halted (quit early or faulted) would be partial
+            if !another_ram.has_not_ended() && another_ram.get_fault().is_none() {
+                print_summary(another_ram.get_state());
+            }
         },
-        _ => panic!("Unknown argument {}", &argv[1])
+        _ => panic!("Unknown argument {}", mode)
     }
+}
 
-    // let ser = Serializer::to_string(another_ram);
-    // Serializer::dump(another_ram);
-    // Serializer::dump(ram);
-}
\ No newline at end of file
+/// Writes the program as a C or Rust source file
+fn transpile(path: &str, options: &Options) {
+    let mut ram = load::<Value>(path);
+
+    let source = ram.set_semantics(options.semantics)
+        .and_then(|_| transpiler::transpile(ram.get_program(), ram.get_state(), options.target));
+    let source = match source {
+        Ok(source) => source,
+        Err(err) => {
+            println!("\x1b[31mError\x1b[0m: {}", err);
+            exit(1);
+        },
+    };
+
+    let output = match &options.output {
+        Some(output) => Path::new(output).to_path_buf(),
+        None => Path::new(path).with_extension(options.target.extension()),
+    };
+
+    match std::fs::write(&output, source) {
+        Ok(_) => println!("Wrote \x1b[35m'{}'\x1b[0m", output.display()),
+        Err(err) => {
+            println!("\x1b[31mError\x1b[0m: Could not write '{}': {}", output.display(), err);
+            exit(1);
+        },
+    }
+}
+
+/// Formats the program at `path` in place. With `--check`, only checks whether it's formatted
+/// and exits with 1 if it isn't.
+fn fmt(path: &str, argv: &[String]) {
+    let check = match argv {
+        [] => false,
+        [flag] if flag == "--check" => true,
+        _ => usage(),
+    };
+
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            println!("\x1b[31mError\x1b[0m: Could not read '{}': {}", path, err);
+            exit(1);
+        },
+    };
+
+    let formatted = formatter::format(&source);
+    if formatted == source {
+        println!("\x1b[35m'{}'\x1b[0m is formatted", path);
+        return;
+    }
+
+    if check {
+        // The first line that would change
+        let (old, new) = (source.lines().collect::<Vec<&str>>(), formatted.lines().collect::<Vec<&str>>());
+        let n = old.iter().zip(&new).take_while(|(old, new)| old == new).count();
+        println!("\x1b[31mError\x1b[0m: '{}' isn't formatted, from line {} on:", path, n + 1);
+        println!("\x1b[31m- {}\x1b[0m", old.get(n).unwrap_or(&""));
+        println!("\x1b[32m+ {}\x1b[0m", new.get(n).unwrap_or(&""));
+        exit(1);
+    }
+
+    match std::fs::write(path, formatted) {
+        Ok(_) => println!("Formatted \x1b[35m'{}'\x1b[0m", path),
+        Err(err) => {
+            println!("\x1b[31mError\x1b[0m: Could not write '{}': {}", path, err);
+            exit(1);
+        },
+    }
+}
+
+/// Runs a program compiled by the JIT in continuous mode.
+/// Prints the initial and the final configuration instead of a trace.
+#[cfg(feature = "jit")]
+fn simulate_jit(path: &str, options: &Options) {
+    let mut ram = load::<u128>(path);
+
+    if let Err(err) = ram.set_semantics(options.semantics) {
+        println!("\x1b[31mError\x1b[0m: {}", err);
+        exit(1);
+    }
+
+    print!("\x1b[33mInitial Configuration -- ");
+    ram.get_state().print_registers(&mut std::io::stdout()).unwrap();
+    println!("\x1b[0m");
+
+    let result = match options.jit_check {
+        true => ram.run_jit_checked(&options.run_config),
+        false => ram.run_jit(&options.run_config),
+    };
+
+    let termination = match result {
+        Ok(termination) => termination,
+        Err(err) => {
+            println!("\x1b[31mError\x1b[0m: JIT: {}", err);
+            exit(1);
+        },
+    };
+
+    print_final(ram.get_state());
+
+    if termination.is_halted() {
+        print_summary(ram.get_state());
+    }
+
+    if options.jit_check {
+        println!("\x1b[32mThe JIT agrees with the interpreter\x1b[0m");
+    }
+
+    finish(termination, options);
+}
+
+/// Parses the program at `path`. Prints all errors in it (with the lines they're on) and exits
+/// with an error (1) if it isn't valid.
+fn load<W: Word>(path: &str) -> RegisterMachine<W> {
+    match text::deserializer::parse_file_as::<W>(Path::new(path).to_path_buf()) {
+        Ok(ram) => ram,
+        Err(errors) => {
+            let source = std::fs::read_to_string(path).unwrap_or_default();
+            eprint!("{}", errors.render(&source, path));
+            exit(1);
+        },
+    }
+}
+
+/// Creates the formatter for the trace. CSV gets a column for every register the program
+/// addresses or `INIT` sets (registers only reached through pointers beyond those are left out).
+fn trace_format<W: Word>(ram: &RegisterMachine<W>, options: &Options) -> Box<dyn TraceFormat<W>> {
+    let highest_register = ram.get_state().get_highest_register().max(ram.get_program().highest_register());
+
+    match options.format {
+        Format::Ansi | Format::Plain if options.annotate =>
+            Box::new(Annotated::new(options.format == Format::Ansi, options.changes_only)),
+        _ => options.format.formatter(highest_register),
+    }
+}
+
+/// Exits with an error (1) for faults and a warning (2) for runs that haven't halted.
+/// The message goes to stderr if stdout has a machine-readable trace.
+fn finish(termination: Termination, options: &Options) {
+    let (message, code) = match termination {
+        Termination::Halted => return,
+        Termination::Fault(err) => (format!("{}: {}", paint(options, "31", "Error"), err), 1),
+        other => (paint(options, "33", &other.to_string()), 2),
+    };
+
+    say(options, &message);
+    exit(code);
+}
+
+/// Prints the configuration a run without a trace ended in
+fn print_final<W: Word>(state: &state::State<W>) {
+    print!("Final Configuration -- Step {}, PC: {}, ", state.get_steps(), state.get_pc());
+    state.print_registers(&mut std::io::stdout()).unwrap();
+    println!();
+}
+
+/// Prints a message, to stderr if stdout has a machine-readable trace
+fn say(options: &Options, message: &str) {
+    match options.format.is_machine_readable() {
+        true => eprintln!("{}", message),
+        false => println!("{}", message),
+    }
+}
+
+/// Colours `text` with the given SGR code, unless the trace is plain text or machine-readable
+fn paint(options: &Options, code: &str, text: &str) -> String {
+    match options.format {
+        Format::Ansi => format!("\x1b[{}m{}\x1b[0m", code, text),
+        _ => text.to_string(),
+    }
+}
+
+/// Prints the cost and space of a halted run
+fn print_summary<W: Word>(state: &state::State<W>) {
+    println!("\n\x1b[33mCost -- {}\nSpace -- {}\x1b[0m", state.get_cost(), state.get_space());
+}
+
+/// Prints a state the step mode went back or forward to
+fn show_state<W: Word>(state: &state::State<W>) {
+    if state.get_steps() == 0 {
+        print!("\x1b[33mInitial Configuration -- ");
+        state.print_registers(&mut std::io::stdout()).unwrap();
+        println!("\x1b[0m");
+    } else {
+        state.dump();
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Output, Stdio};
+
+/// Writes `source` to a file of its own in the temp directory
+fn program(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rscli-test-{}-{}.s", std::process::id(), name));
+    std::fs::write(&path, source).unwrap();
+    path
+}
+
+fn rscli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rscli")).args(args).output().unwrap()
+}
+
+/// Runs rscli with `input` as its standard input (e.g. the commands of step mode)
+fn rscli_with_input(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rscli"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn csv_header_covers_the_registers_of_the_program() {
+    // r5 is only reached through the pointer in r2
+    let path = program("csv", "INIT 0 5\nCADD 1\nSTORE 3\nINDSTORE 2\nEND\n");
+    let output = rscli(&["c", path.to_str().unwrap(), "--format", "csv"]);
+    std::fs::remove_file(&path).unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<&str> = stdout.lines().collect();
+    assert_eq!(rows, ["step,pc,r0,r1,r2,r3", "0,0,0,0,5,0", "1,1,1,0,5,0", "2,2,1,0,5,1", "3,3,1,0,5,1", "4,3,1,0,5,1"]);
+}
+
+#[test]
+fn csv_traces_of_endless_runs_start_right_away() {
+    let path = program("endless", "INIT 0\nCADD 1\nJMP 1\nEND\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rscli"))
+        .args(["c", path.to_str().unwrap(), "--format", "csv"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    assert_eq!(lines.next().unwrap().unwrap(), "step,pc,r0,r1");
+    assert_eq!(lines.next().unwrap().unwrap(), "0,0,0,0");
+    child.kill().unwrap();
+    child.wait().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn jsonl_and_text_traces_number_steps_alike() {
+    let path = program("steps", "INIT 2\nLOAD 1\nEND\n");
+    let path = path.to_str().unwrap();
+
+    let jsonl = String::from_utf8(rscli(&["c", path, "--format", "jsonl"]).stdout).unwrap();
+    assert!(jsonl.lines().nth(1).unwrap().starts_with("{\"step\":1,\"pc\":1,"), "{}", jsonl);
+    let plain = String::from_utf8(rscli(&["c", path, "--format", "plain"]).stdout).unwrap();
+    assert!(plain.contains("Step  1 -- PC:  1, r0: 2, r1: 2"), "{}", plain);
+    assert!(!plain.contains("Step  0"), "{}", plain);
+    std::fs::remove_file(path).unwrap();
+}
+
+
+#[test]
+fn fmt_check_fails_on_unformatted_programs() {
+    let path = program("fmt", "INIT\nload 1\nEND\n");
+    let path = path.to_str().unwrap();
+
+    let check = rscli(&["fmt", path, "--check"]);
+    assert_eq!(check.status.code(), Some(1));
+    assert!(String::from_utf8(check.stdout).unwrap().contains("from line 2 on"));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "INIT\nload 1\nEND\n");
+
+    assert!(rscli(&["fmt", path]).status.success());
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "INIT\n        LOAD 1\n        END\n");
+    assert!(rscli(&["fmt", path, "--check"]).status.success());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn loop_detection_is_off_unless_asked_for() {
+    let path = program("loop", "INIT 0\nCADD 1\nCSUB 1\nJMP 1\nEND\n");
+    let path = path.to_str().unwrap();
+
+    let budget = String::from_utf8(rscli(&["c", path, "--max-steps", "100"]).stdout).unwrap();
+    assert!(budget.contains("didn't halt within its budget (100 steps)"));
+    for mode in ["hash", "brent"] {
+        let detected = String::from_utf8(rscli(&["c", path, "--max-steps", "100", "--loop-detection", mode]).stdout).unwrap();
+        assert!(detected.contains("the machine won't halt"), "{}", mode);
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn step_mode_prints_the_totals_only_once_halted() {
+    let path = program("step", "INIT 2\nLOAD 1\nCADD 1\nSTORE 1\nEND\n");
+    let path = path.to_str().unwrap();
+
+    // Quits (at the end of the input) after two of the four steps
+    let quit = String::from_utf8(rscli_with_input(&["s", path], "\n\n").stdout).unwrap();
+    assert!(!quit.contains("Cost --"), "{}", quit);
+
+    let halted = String::from_utf8(rscli_with_input(&["s", path], "\n\n\n\n\n").stdout).unwrap();
+    assert!(halted.contains("Cost -- uniform: 4"), "{}", halted);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn quiet_mode_prints_only_the_final_configuration() {
+    let path = program("quiet", "INIT 2\nLOAD 1\nCADD 1\nSTORE 1\nEND\n");
+    let path = path.to_str().unwrap();
+
+    let output = rscli(&["q", path]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Final Configuration -- Step 4, PC: 3, r0: 3, r1: 3"), "{}", stdout);
+    assert!(stdout.contains("Cost -- uniform: 4"), "{}", stdout);
+    assert!(!stdout.contains("Step  1"), "{}", stdout);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn line_breakpoints_need_an_instruction_on_the_line() {
+    let path = program("break", "; counts\nINIT 3\nLOAD 1\nEND\n");
+    let path = path.to_str().unwrap();
+
+    let empty = rscli(&["q", path, "--break", "line 1"]);
+    assert_eq!(empty.status.code(), Some(1));
+    assert!(String::from_utf8(empty.stdout).unwrap().contains("There's no instruction on line 1"));
+    assert!(rscli(&["q", path, "--break", "line 3"]).status.success());
+    std::fs::remove_file(path).unwrap();
+}